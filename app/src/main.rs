@@ -1,50 +1,185 @@
-use std::{error, ffi, mem};
+use std::{
+  error,
+  sync::{atomic::AtomicBool, Arc, OnceLock},
+  thread,
+  time::Duration,
+};
 
-use injector::load_module;
-use win::process::{util::enum_processes, WinApiProcess};
-use winapi::um::{
-  libloaderapi::{GetModuleHandleA, GetProcAddress},
-  winnt::{PROCESS_QUERY_INFORMATION, PROCESS_VM_READ},
+use injector::watch::{WatchEvent, WatchOptions, Watcher};
+use ipc::{
+  config::HookConfig,
+  message::{AppMessage, GameEvent, HookMessage, HookState},
+  transport::{Connection, Listener, DEFAULT_ENDPOINT},
+};
+use vibe::{
+  init_xbone,
+  pattern::{Pattern, Scheduler},
+  XBone,
 };
 
 pub type BoxResult<T> = Result<T, Box<dyn error::Error + Send + Sync + 'static>>;
 
+const SUPPORTED_GAMES: &[&str] = &["th08.exe"];
+const HOOK_MODULE_NAME: &str = "hook.dll";
+const HOOK_MODULE_PATH: &str = "target/debug/hook.dll";
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const SETTLE_TIME: Duration = Duration::from_secs(2);
+const STARTUP_TIMEOUT: Duration = Duration::from_secs(30);
+
+// set once a device is found, events from before that are dropped
+type Haptics = Arc<OnceLock<XBone>>;
+
 fn main() -> BoxResult<()> {
-  let proc_ids = enum_processes()?;
+  let listener = Listener::bind(DEFAULT_ENDPOINT)?;
 
-  let mut th08 = 0;
+  // the app owns the devices rather than the hook, so they stay connected across game restarts
+  let haptics = Haptics::default();
 
-  for &pid in proc_ids.iter() {
-    let proc = WinApiProcess::new(pid, PROCESS_QUERY_INFORMATION | PROCESS_VM_READ);
+  thread::spawn({
+    let haptics = haptics.clone();
 
-    if proc.is_err() {
-      continue;
+    // blocks until a device shows up
+    move || match init_xbone() {
+      Ok(xbone) => {
+        let _ = haptics.set(xbone);
+      }
+      Err(err) => eprintln!("failed to start haptics - {}", err),
     }
+  });
+
+  thread::spawn(move || serve_hooks(listener, haptics));
+
+  let config = HookConfig {
+    server_addr: Some(DEFAULT_ENDPOINT.to_string()),
+    ..HookConfig::default()
+  };
+
+  let mut watcher = Watcher::new(WatchOptions {
+    exe_names: SUPPORTED_GAMES.iter().map(|name| name.to_string()).collect(),
+    module_path: HOOK_MODULE_PATH.to_string(),
+    module_name: HOOK_MODULE_NAME.to_string(),
+    poll_interval: POLL_INTERVAL,
+    settle: SETTLE_TIME,
+    startup_timeout: STARTUP_TIMEOUT,
+    init: Some(config.to_blob()?),
+  });
+
+  println!("waiting for {}", SUPPORTED_GAMES.join(", "));
+
+  // runs until the app is closed
+  let stop = AtomicBool::new(false);
+
+  watcher.run(&stop, |event| match event {
+    WatchEvent::Found { pid, exe_name } => println!("found {} (pid {})", exe_name, pid),
+    WatchEvent::Injected { pid, module } => println!("loaded {:?} at {:#x} (pid {})", module.path, module.base, pid),
+    WatchEvent::AlreadyLoaded { pid, base } => {
+      println!("{} already loaded at {:#x} (pid {})", HOOK_MODULE_NAME, base, pid)
+    }
+    WatchEvent::Failed { err, .. } => {
+      eprintln!("{}", err);
 
-    let proc = proc?;
+      if let Some(hint) = err.hint() {
+        eprintln!("hint: {}", hint);
+      }
+    }
+    WatchEvent::Exited { pid } => println!("pid {} exited", pid),
+    WatchEvent::ProcessSkipped { err } => eprintln!("skipped a process - {}", err),
+    WatchEvent::SnapshotFailed { err } => eprintln!("failed to list processes - {}", err),
+  })?;
 
-    let name = proc.get_name();
+  Ok(())
+}
 
-    if name.is_err() {
-      continue;
+// every injected hook connects back to us, one thread each
+fn serve_hooks(mut listener: Listener, haptics: Haptics) {
+  loop {
+    match listener.accept() {
+      Ok(connection) => {
+        let haptics = haptics.clone();
+
+        thread::spawn(move || {
+          if let Err(err) = handle_hook(connection, &haptics) {
+            eprintln!("{}", err);
+          }
+        });
+      }
+      Err(err) => {
+        eprintln!("failed to accept hook connection - {}", err);
+
+        return;
+      }
     }
+  }
+}
 
-    let name = name?;
+fn handle_hook(connection: Connection, haptics: &Haptics) -> BoxResult<()> {
+  // patterns run on the game's frames, see HookMessage::Frame
+  let mut scheduler = Scheduler::default();
 
-    if name == "th08.exe" {
-      th08 = pid;
-    }
+  let handled = read_hook(connection, haptics, &mut scheduler);
 
-    // println!("{}", name);
-  }
+  // however the connection ended, nothing is left to end the current pattern
+  scheduler.stop();
 
-  if th08 == 0 {
-    panic!("th08 process not found");
-  }
+  let stopped = match haptics.get() {
+    Some(xbone) => xbone.stop(),
+    None => Ok(()),
+  };
 
-  println!("th08 pid: {}", th08);
+  handled?;
+  stopped
+}
 
-  load_module("target/debug/hook.dll", th08)?;
+// returns once the hook disconnects, or on the first error
+fn read_hook(connection: Connection, haptics: &Haptics, scheduler: &mut Scheduler) -> BoxResult<()> {
+  let (mut sender, mut receiver) = connection.split()?;
+
+  sender.send(&AppMessage::QueryStatus)?;
+
+  loop {
+    let message = match receiver.recv() {
+      Ok(message) => message,
+      Err(err) if err.is_closed() => return Ok(()),
+      Err(err) => return Err(err.into()),
+    };
+
+    match message {
+      HookMessage::Hello { pid, version } => println!("hook {} connected (pid {})", version, pid),
+      HookMessage::Event(event) => {
+        println!("event: {:?}", event);
+
+        if let Some(pattern) = pattern(&event) {
+          scheduler.play(pattern);
+        }
+      }
+      HookMessage::Frame(tick) => {
+        if let (Some(intensity), Some(xbone)) = (scheduler.tick(tick.frame, tick.paused), haptics.get()) {
+          xbone.set(intensity)?;
+        }
+      }
+      HookMessage::Log(record) => println!("[{:?}] {}: {}", record.level, record.target, record.message),
+      HookMessage::Status(status) => {
+        println!("status: {:?}", status);
+
+        // the hook is going away, it will not send whatever would have ended the current effect
+        if status.state == HookState::ShuttingDown {
+          scheduler.stop();
+
+          if let Some(xbone) = haptics.get() {
+            xbone.stop()?;
+          }
+        }
+      }
+    }
+  }
+}
 
-  Ok(())
+// TODO: play with the configured profile
+// in frames, the game runs at 60 fps
+fn pattern(event: &GameEvent) -> Option<Pattern> {
+  match event {
+    GameEvent::LifeChanged { .. } => Some(Pattern::constant(1.0, 300)),
+    GameEvent::Graze | GameEvent::ItemCollected => None,
+  }
 }
@@ -0,0 +1,252 @@
+use std::{
+  io, mem,
+  sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicU8, AtomicUsize, Ordering},
+    mpsc, Condvar, Mutex, OnceLock, PoisonError,
+  },
+  time::Duration,
+};
+
+use ipc::{
+  config::HookConfig,
+  message::{HookMessage, HookState, HookStatus},
+  transport::Connection,
+};
+#[cfg(feature = "local-haptics")]
+use vibe::{pattern::Scheduler, XBone};
+
+use crate::{detour::Detour, frame::FrameClock, midhook::MidHook, worker::Worker};
+
+static CONTEXT: OnceLock<HookContext> = OnceLock::new();
+
+// our end of the connection to the app
+pub struct AppLink {
+  pub outbox: mpsc::Sender<HookMessage>,
+  // a clone of what the ipc threads use, kept to unblock them on shutdown
+  pub connection: Connection,
+}
+
+// everything the game threads running our hooks share with dll_attach and the ipc threads
+// set once by dll_attach before any hook is enabled, so a hook always finds its trampoline here
+pub struct HookContext {
+  state: AtomicU8,
+  shutting_down: AtomicBool,
+  // set once the shutdown sequence has run to the end, for later triggers to wait on
+  shut_down: Mutex<bool>,
+  shut_down_set: Condvar,
+  callback_failures: AtomicU32,
+  // game threads currently inside one of our detours, see enter_call
+  calls_in_flight: AtomicUsize,
+  pub config: Mutex<HookConfig>,
+  // messages for the app, written to the pipe by their own thread so game threads never block on it
+  // none when there is no app to talk to, or once shutdown has closed it
+  outbox: Mutex<Option<mpsc::Sender<HookMessage>>>,
+  connection: Option<Connection>,
+  // threads shutdown has to wait for before we can be unloaded
+  workers: Mutex<Vec<Worker>>,
+  pub health: Detour,
+  pub mid_hooks: Vec<MidHook>,
+  pub frame_clock: FrameClock,
+  // only set once a device shows up, which can take a while
+  #[cfg(feature = "local-haptics")]
+  pub xbone: Mutex<Option<XBone>>,
+  // run by the frame clock, see haptics::tick
+  #[cfg(feature = "local-haptics")]
+  pub scheduler: Mutex<Scheduler>,
+}
+
+impl HookContext {
+  pub fn new(
+    config: HookConfig,
+    app: Option<AppLink>,
+    health: Detour,
+    mid_hooks: Vec<MidHook>,
+    frame_clock: FrameClock,
+  ) -> Self {
+    let (outbox, connection) = match app {
+      Some(app) => (Some(app.outbox), Some(app.connection)),
+      None => (None, None),
+    };
+
+    Self {
+      state: AtomicU8::new(state_to_u8(HookState::Starting)),
+      shutting_down: AtomicBool::new(false),
+      shut_down: Mutex::new(false),
+      shut_down_set: Condvar::new(),
+      callback_failures: AtomicU32::new(0),
+      calls_in_flight: AtomicUsize::new(0),
+      config: Mutex::new(config),
+      outbox: Mutex::new(outbox),
+      connection,
+      workers: Mutex::new(Vec::new()),
+      health,
+      mid_hooks,
+      frame_clock,
+      #[cfg(feature = "local-haptics")]
+      xbone: Mutex::new(None),
+      #[cfg(feature = "local-haptics")]
+      scheduler: Mutex::new(Scheduler::default()),
+    }
+  }
+
+  pub fn state(&self) -> HookState {
+    state_from_u8(self.state.load(Ordering::SeqCst))
+  }
+
+  pub fn set_state(&self, state: HookState) {
+    self.state.store(state_to_u8(state), Ordering::SeqCst);
+  }
+
+  // moves from starting to ready, unless a shutdown already got in
+  pub fn set_ready(&self) -> bool {
+    self
+      .state
+      .compare_exchange(
+        state_to_u8(HookState::Starting),
+        state_to_u8(HookState::Ready),
+        Ordering::SeqCst,
+        Ordering::SeqCst,
+      )
+      .is_ok()
+  }
+
+  // true for the first caller only, so the shutdown sequence runs once whatever triggered it
+  pub fn begin_shutdown(&self) -> bool {
+    !self.shutting_down.swap(true, Ordering::SeqCst)
+  }
+
+  pub fn is_shutting_down(&self) -> bool {
+    self.shutting_down.load(Ordering::SeqCst)
+  }
+
+  pub fn finish_shutdown(&self) {
+    *self.shut_down.lock().unwrap_or_else(PoisonError::into_inner) = true;
+    self.shut_down_set.notify_all();
+  }
+
+  // false if the shutdown sequence still has not finished by timeout
+  pub fn wait_for_shutdown(&self, timeout: Duration) -> bool {
+    let shut_down = self.shut_down.lock().unwrap_or_else(PoisonError::into_inner);
+
+    let (shut_down, _) = self
+      .shut_down_set
+      .wait_timeout_while(shut_down, timeout, |shut_down| !*shut_down)
+      .unwrap_or_else(PoisonError::into_inner);
+
+    *shut_down
+  }
+
+  // hooks only act on calls while ready, and otherwise just call through to the original
+  pub fn is_ready(&self) -> bool {
+    self.state() == HookState::Ready
+  }
+
+  pub fn status(&self) -> HookStatus {
+    HookStatus {
+      state: self.state(),
+      hooks_enabled: self.health.is_enabled() || self.mid_hooks.iter().any(MidHook::is_enabled),
+      frame_clock_hooked: self.frame_clock.is_hooked(),
+      callback_failures: self.callback_failures.load(Ordering::SeqCst),
+    }
+  }
+
+  // returns the failures so far, this one included
+  pub fn add_failure(&self) -> u32 {
+    self.callback_failures.fetch_add(1, Ordering::SeqCst) + 1
+  }
+
+  pub fn owns_trampoline(&self, addr: usize) -> bool {
+    self.health.owns_trampoline(addr) || self.mid_hooks.iter().any(|mid_hook| mid_hook.owns_stub(addr))
+  }
+
+  // puts back the original bytes of every hook
+  // safety
+  // - must not run concurrently with dll_attach enabling the hooks
+  pub unsafe fn disable_hooks(&self) {
+    self.health.disable();
+
+    for mid_hook in &self.mid_hooks {
+      mid_hook.disable();
+    }
+
+    self.frame_clock.disable();
+  }
+
+  // held by a detour for as long as it runs, the original call included
+  pub fn enter_call(&self) -> CallGuard<'_> {
+    self.calls_in_flight.fetch_add(1, Ordering::SeqCst);
+
+    CallGuard { context: self }
+  }
+
+  pub fn calls_in_flight(&self) -> usize {
+    self.calls_in_flight.load(Ordering::SeqCst)
+  }
+
+  // queues a message for the app, dropped if there is no app or it has gone away
+  pub fn post(&self, message: HookMessage) {
+    if let Some(outbox) = &*self.outbox.lock().unwrap_or_else(PoisonError::into_inner) {
+      // only fails once write_messages has stopped, which already reported why
+      let _ = outbox.send(message);
+    }
+  }
+
+  // write_messages stops once it has sent whatever was already queued
+  pub fn close_outbox(&self) {
+    self.outbox.lock().unwrap_or_else(PoisonError::into_inner).take();
+  }
+
+  // fails whatever the ipc threads are blocked on, see Connection::shutdown
+  pub fn interrupt_app(&self) -> io::Result<()> {
+    match &self.connection {
+      Some(connection) => connection.shutdown(),
+      None => Ok(()),
+    }
+  }
+
+  pub fn add_worker(&self, worker: Worker) {
+    self.workers.lock().unwrap_or_else(PoisonError::into_inner).push(worker);
+  }
+
+  pub fn take_workers(&self) -> Vec<Worker> {
+    mem::take(&mut *self.workers.lock().unwrap_or_else(PoisonError::into_inner))
+  }
+}
+
+pub struct CallGuard<'a> {
+  context: &'a HookContext,
+}
+
+impl Drop for CallGuard<'_> {
+  fn drop(&mut self) {
+    self.context.calls_in_flight.fetch_sub(1, Ordering::SeqCst);
+  }
+}
+
+// none if one was already set
+pub fn set(context: HookContext) -> Option<&'static HookContext> {
+  CONTEXT.set(context).ok()?;
+
+  get()
+}
+
+// none until dll_attach has set it
+pub fn get() -> Option<&'static HookContext> {
+  CONTEXT.get()
+}
+
+fn state_to_u8(state: HookState) -> u8 {
+  match state {
+    HookState::Starting => 0,
+    HookState::Ready => 1,
+    HookState::ShuttingDown => 2,
+  }
+}
+
+fn state_from_u8(state: u8) -> HookState {
+  match state {
+    0 => HookState::Starting,
+    1 => HookState::Ready,
+    _ => HookState::ShuttingDown,
+  }
+}
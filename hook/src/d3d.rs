@@ -0,0 +1,198 @@
+// a throwaway IDirect3DDevice8, made only to find the vtable every device in the process shares
+// hooking a slot in it hooks the game's device too, without needing to know where the game keeps it
+// d3d8.dll is never loaded by us, a game without it already loaded has no device to find
+
+use std::{ffi, mem, ptr};
+
+use win::error::util::get_last_error;
+
+use crate::{get_module_handle, Bool, BoxResult, Pvoid};
+
+const D3D8_MODULE_NAME: &str = "d3d8.dll";
+const CREATE_PROC_NAME: &str = "Direct3DCreate8";
+
+// values mirror d3d8.h
+const D3D_SDK_VERSION: u32 = 220;
+const D3DADAPTER_DEFAULT: u32 = 0;
+const D3DDEVTYPE_HAL: u32 = 1;
+const D3DCREATE_SOFTWARE_VERTEXPROCESSING: u32 = 0x20;
+const D3DSWAPEFFECT_DISCARD: u32 = 1;
+
+// QueryInterface, AddRef, Release, then the IDirect3D8 methods in declaration order
+const RELEASE_INDEX: usize = 2;
+const CREATE_DEVICE_INDEX: usize = 15;
+
+// WS_POPUP, and no WS_VISIBLE so it is never shown
+const WS_POPUP: u32 = 0x8000_0000;
+
+type Direct3DCreate8Fn = extern "system" fn(sdk_version: u32) -> Pvoid;
+type ReleaseFn = extern "system" fn(object: Pvoid) -> u32;
+type CreateDeviceFn = extern "system" fn(
+  d3d: Pvoid,
+  adapter: u32,
+  device_type: u32,
+  focus_window: Pvoid,
+  behavior_flags: u32,
+  params: *mut PresentParameters,
+  device: *mut Pvoid,
+) -> i32;
+
+// D3DPRESENT_PARAMETERS as d3d8 has it
+#[repr(C)]
+struct PresentParameters {
+  back_buffer_width: u32,
+  back_buffer_height: u32,
+  back_buffer_format: u32,
+  back_buffer_count: u32,
+  multi_sample_type: u32,
+  swap_effect: u32,
+  device_window: Pvoid,
+  windowed: Bool,
+  enable_auto_depth_stencil: Bool,
+  auto_depth_stencil_format: u32,
+  flags: u32,
+  full_screen_refresh_rate: u32,
+  full_screen_presentation_interval: u32,
+}
+
+extern "system" {
+  fn GetProcAddress(module_handle: Pvoid, proc_name: *const i8) -> Pvoid;
+}
+
+#[link(name = "user32")]
+extern "system" {
+  fn CreateWindowExA(
+    ex_style: u32,
+    class_name: *const i8,
+    window_name: *const i8,
+    style: u32,
+    x: i32,
+    y: i32,
+    width: i32,
+    height: i32,
+    parent: Pvoid,
+    menu: Pvoid,
+    instance: Pvoid,
+    param: Pvoid,
+  ) -> Pvoid;
+  fn DestroyWindow(window: Pvoid) -> Bool;
+}
+
+// TODO: better error type
+// the vtable of IDirect3DDevice8, which lives in d3d8.dll for as long as it is loaded
+// fails when the game has not loaded d3d8, or a second device cannot be made, eg. next to a lost fullscreen one
+// safety
+// - must run on a thread that can own a window, and that does not exit before the window is destroyed, ie. any but DllMain
+pub unsafe fn device_vtable() -> BoxResult<usize> {
+  let d3d8 = get_module_handle(Some(D3D8_MODULE_NAME))?;
+
+  let create_cstr = ffi::CString::new(CREATE_PROC_NAME)?;
+  let create = GetProcAddress(d3d8, create_cstr.as_ptr());
+
+  if create.is_null() {
+    let error = get_last_error("GetProcAddress");
+
+    return Err(Box::new(error));
+  }
+
+  let create: Direct3DCreate8Fn = mem::transmute(create);
+  let d3d = create(D3D_SDK_VERSION);
+
+  if d3d.is_null() {
+    return Err(format!("{} failed", CREATE_PROC_NAME).into());
+  }
+
+  let vtable = create_device_vtable(d3d);
+
+  release(d3d);
+
+  vtable
+}
+
+// safety
+// - d3d must be a live IDirect3D8
+unsafe fn create_device_vtable(d3d: Pvoid) -> BoxResult<usize> {
+  let class_cstr = ffi::CString::new("STATIC")?;
+
+  let window = CreateWindowExA(
+    0,
+    class_cstr.as_ptr(),
+    ptr::null(),
+    WS_POPUP,
+    0,
+    0,
+    1,
+    1,
+    ptr::null_mut(),
+    ptr::null_mut(),
+    ptr::null_mut(),
+    ptr::null_mut(),
+  );
+
+  if window.is_null() {
+    let error = get_last_error("CreateWindowExA");
+
+    return Err(Box::new(error));
+  }
+
+  let mut params = PresentParameters {
+    back_buffer_width: 1,
+    back_buffer_height: 1,
+    // D3DFMT_UNKNOWN, windowed devices take the desktop's
+    back_buffer_format: 0,
+    back_buffer_count: 1,
+    multi_sample_type: 0,
+    swap_effect: D3DSWAPEFFECT_DISCARD,
+    device_window: window,
+    windowed: Bool::True,
+    enable_auto_depth_stencil: Bool::False,
+    auto_depth_stencil_format: 0,
+    flags: 0,
+    full_screen_refresh_rate: 0,
+    full_screen_presentation_interval: 0,
+  };
+
+  let mut device = ptr::null_mut();
+
+  let create_device: CreateDeviceFn = mem::transmute(method(d3d, CREATE_DEVICE_INDEX));
+  let result = create_device(
+    d3d,
+    D3DADAPTER_DEFAULT,
+    D3DDEVTYPE_HAL,
+    window,
+    D3DCREATE_SOFTWARE_VERTEXPROCESSING,
+    &mut params,
+    &mut device,
+  );
+
+  let vtable = match result {
+    result if result < 0 || device.is_null() => Err(format!("CreateDevice failed - {:#x}", result as u32).into()),
+    _ => {
+      let vtable = *(device as *const usize);
+
+      release(device);
+
+      Ok(vtable)
+    }
+  };
+
+  DestroyWindow(window);
+
+  vtable
+}
+
+// safety
+// - object must be a live com object with at least index + 1 methods
+unsafe fn method(object: Pvoid, index: usize) -> usize {
+  let vtable = *(object as *const usize);
+
+  *((vtable + index * mem::size_of::<usize>()) as *const usize)
+}
+
+// safety
+// - object must be a live com object we hold a reference to, which is gone after this
+unsafe fn release(object: Pvoid) {
+  let release: ReleaseFn = mem::transmute(method(object, RELEASE_INDEX));
+
+  release(object);
+}
@@ -0,0 +1,131 @@
+use std::{
+  ptr,
+  sync::atomic::{AtomicBool, Ordering},
+};
+
+use win::error::{util::get_last_error, WinApiCodeResult};
+
+use crate::{Bool, Pvoid, VirtualAlloc, VirtualProtect};
+
+const JMP_REL_32: u8 = 0xe9;
+pub const JMP_SIZE: usize = 5;
+const TRAMPOLINE_SIZE: usize = 1024;
+
+// MEM_COMMIT = 0x1000
+// MEM_RESERVE = 0x2000
+const MEM_COMMIT_RESERVE: u32 = 0x1000 | 0x2000;
+// PAGE_EXECUTE_READWRITE = 0x40
+const PAGE_EXECUTE_READWRITE: u32 = 0x40;
+
+// a jmp over the first 5 bytes of target, and a trampoline that runs those bytes before jumping back past them
+// the trampoline is built before target is touched, so a detour can always call through it to the original
+#[derive(Debug)]
+pub struct Detour {
+  target: usize,
+  trampoline: usize,
+  stolen_bytes: [u8; JMP_SIZE],
+  enabled: AtomicBool,
+}
+
+impl Detour {
+  // safety
+  // - target must be the start of a function whose first 5 bytes are whole, position independent instructions
+  pub unsafe fn new(target: usize) -> WinApiCodeResult<Self> {
+    let mut stolen_bytes = [0; JMP_SIZE];
+
+    ptr::copy_nonoverlapping(target as *const u8, stolen_bytes.as_mut_ptr(), JMP_SIZE);
+
+    let trampoline = valloc(TRAMPOLINE_SIZE)?;
+
+    let tramp_bytes = stolen_bytes
+      .into_iter()
+      .chain(jmp_rel_32(trampoline + JMP_SIZE, target + JMP_SIZE))
+      .collect::<Vec<_>>();
+
+    ptr::copy_nonoverlapping(tramp_bytes.as_ptr(), trampoline as *mut u8, tramp_bytes.len());
+
+    Ok(Self {
+      target,
+      trampoline,
+      stolen_bytes,
+      enabled: AtomicBool::new(false),
+    })
+  }
+
+  // calls the original function, whether or not the detour is enabled
+  pub fn trampoline(&self) -> usize {
+    self.trampoline
+  }
+
+  pub fn owns_trampoline(&self, addr: usize) -> bool {
+    (self.trampoline..self.trampoline + TRAMPOLINE_SIZE).contains(&addr)
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.enabled.load(Ordering::SeqCst)
+  }
+
+  // does nothing if already enabled
+  // safety
+  // - detour must have the same signature and calling convention as target
+  // - must not run concurrently with disable
+  pub unsafe fn enable(&self, detour: usize) -> WinApiCodeResult<()> {
+    if self.is_enabled() {
+      return Ok(());
+    }
+
+    vprotect(self.target, JMP_SIZE)?;
+
+    let hook_bytes = jmp_rel_32(self.target, detour);
+
+    ptr::copy_nonoverlapping(hook_bytes.as_ptr(), self.target as *mut u8, JMP_SIZE);
+
+    self.enabled.store(true, Ordering::SeqCst);
+
+    Ok(())
+  }
+
+  // puts the stolen bytes back, does nothing if not enabled
+  // the trampoline is leaked on purpose, a game thread may still be inside it
+  // safety
+  // - must not run concurrently with enable
+  pub unsafe fn disable(&self) {
+    // the page is still writable from enable
+    if self.enabled.swap(false, Ordering::SeqCst) {
+      ptr::copy_nonoverlapping(self.stolen_bytes.as_ptr(), self.target as *mut u8, JMP_SIZE);
+    }
+  }
+}
+
+// jmp rel32 at from, landing on to
+pub fn jmp_rel_32(from: usize, to: usize) -> [u8; JMP_SIZE] {
+  let offset = (to.wrapping_sub(from + JMP_SIZE) as u32).to_le_bytes();
+
+  [JMP_REL_32, offset[0], offset[1], offset[2], offset[3]]
+}
+
+pub fn vprotect(addr: usize, size: usize) -> WinApiCodeResult<()> {
+  let mut old_protect = 0;
+
+  let success_code = unsafe { VirtualProtect(addr as Pvoid, size, PAGE_EXECUTE_READWRITE, &mut old_protect) };
+
+  if success_code == Bool::False {
+    let error = get_last_error("VirtualProtect");
+
+    Err(error)
+  } else {
+    Ok(())
+  }
+}
+
+pub fn valloc(size: usize) -> WinApiCodeResult<usize> {
+  let mem_ptr = unsafe { VirtualAlloc(ptr::null_mut(), size, MEM_COMMIT_RESERVE, PAGE_EXECUTE_READWRITE) };
+
+  if mem_ptr.is_null() {
+    let error = get_last_error("VirtualAlloc");
+
+    Err(error)
+  } else {
+    Ok(mem_ptr as usize)
+  }
+}
@@ -0,0 +1,175 @@
+// the game's frames, as the clock haptic patterns run on
+// counted on IDirect3DDevice8::Present, or by a wall clock thread when no device can be made to find it through
+// until the pause flag below is filled in frames are never paused
+
+use std::{
+  mem,
+  sync::atomic::{AtomicU64, Ordering},
+  thread,
+  time::Duration,
+};
+
+use ipc::message::{FrameTick, HookMessage};
+use log::{error, info, warn};
+use win::error::WinApiCodeResult;
+
+use crate::{
+  context::{self, HookContext},
+  d3d,
+  guard::guard,
+  haptics,
+  slot::SlotHook,
+  worker, BoxResult, Pvoid,
+};
+
+// TODO: find in th08
+// 0 means unknown, so every tick says the game is running, even in the pause menu
+const PAUSE_FLAG_OFFSET: usize = 0;
+
+// QueryInterface, AddRef, Release, then the IDirect3DDevice8 methods in declaration order
+const PRESENT_INDEX: usize = 15;
+
+// what th08 runs at when it keeps up
+const FRAME_TIME: Duration = Duration::from_micros(16667);
+
+type PresentFn = extern "system" fn(device: Pvoid, source: Pvoid, dest: Pvoid, window: Pvoid, dirty: Pvoid) -> i32;
+
+pub struct FrameClock {
+  frames: AtomicU64,
+  // none when ticked by the wall clock
+  present: Option<SlotHook>,
+  pause_flag: Option<usize>,
+}
+
+impl FrameClock {
+  // safety
+  // - process_addr must be the base of th08.exe
+  // - must not run in DllMain, see d3d::device_vtable
+  pub unsafe fn new(process_addr: usize) -> Self {
+    // every device shares the vtable, so the slot is the game's Present as much as it is ours
+    // it lives in d3d8.dll, which the game keeps loaded until it exits
+    let present = match d3d::device_vtable() {
+      Ok(vtable) => Some(SlotHook::new(vtable + PRESENT_INDEX * mem::size_of::<usize>())),
+      Err(error) => {
+        warn!("d3d::device_vtable errored: {:?}", error);
+
+        None
+      }
+    };
+
+    let pause_flag = match PAUSE_FLAG_OFFSET {
+      0 => None,
+      offset => Some(process_addr + offset),
+    };
+
+    Self {
+      frames: AtomicU64::new(0),
+      present,
+      pause_flag,
+    }
+  }
+
+  pub fn is_hooked(&self) -> bool {
+    self.present.as_ref().is_some_and(SlotHook::is_enabled)
+  }
+
+  // safety
+  // - must not run concurrently with disable
+  pub unsafe fn enable(&self) -> WinApiCodeResult<()> {
+    match &self.present {
+      Some(present_hook) => present_hook.enable(present as PresentFn as usize),
+      None => Ok(()),
+    }
+  }
+
+  // safety
+  // - must not run concurrently with enable
+  pub unsafe fn disable(&self) {
+    if let Some(present_hook) = &self.present {
+      present_hook.disable();
+    }
+  }
+
+  fn advance(&self) -> FrameTick {
+    let frame = self.frames.fetch_add(1, Ordering::SeqCst) + 1;
+
+    let paused = match self.pause_flag {
+      Some(pause_flag) => unsafe {
+        // safety
+        // - a global of th08.exe, mapped for as long as the game runs
+        *(pause_flag as *const u8) != 0
+      },
+      None => false,
+    };
+
+    FrameTick { frame, paused }
+  }
+}
+
+// the present hook needs nothing more than enabling, the wall clock needs a thread
+pub fn start(context: &'static HookContext) {
+  if context.frame_clock.pause_flag.is_none() {
+    warn!("pause flag not found, frames are never counted as paused");
+  }
+
+  if context.frame_clock.present.is_some() {
+    info!("frames counted on Present");
+
+    return;
+  }
+
+  warn!("Present not hooked, frames counted on a wall clock at 60 fps whatever the game runs at");
+
+  // stops once shutdown starts, which then joins it with the ipc threads
+  let wall_clock = worker::spawn("wall_clock", move || {
+    while !context.is_shutting_down() {
+      thread::sleep(FRAME_TIME);
+
+      guard(context, "on_frame", || on_frame(context));
+    }
+  });
+
+  match wall_clock {
+    Ok(worker) => context.add_worker(worker),
+    Err(error) => error!("spawn wall_clock errored: {:?}", error),
+  }
+}
+
+// TODO: better error type
+fn on_frame(context: &HookContext) -> BoxResult<()> {
+  // frames before we are ready or while shutting down are not counted
+  if !context.is_ready() {
+    return Ok(());
+  }
+
+  let tick = context.frame_clock.advance();
+
+  context.post(HookMessage::Frame(tick));
+
+  haptics::tick(context, tick)?;
+
+  Ok(())
+}
+
+// runs on the game thread, so everything past getting the context goes through guard
+extern "system" fn present(device: Pvoid, source: Pvoid, dest: Pvoid, window: Pvoid, dirty: Pvoid) -> i32 {
+  // unreachable, the slot is only swapped once the context is set
+  let context = match context::get() {
+    Some(context) => context,
+    None => return 0,
+  };
+
+  // held until the original returns, so shutdown knows when no game thread is left in here
+  let _call = context.enter_call();
+
+  guard(context, "on_frame", || on_frame(context));
+
+  let present_hook = match &context.frame_clock.present {
+    Some(present_hook) => present_hook,
+    None => return 0,
+  };
+
+  let orig: PresentFn = unsafe { mem::transmute(present_hook.original()) };
+
+  orig(device, source, dest, window, dirty)
+}
@@ -0,0 +1,198 @@
+use std::{
+  ffi,
+  ops::Range,
+  panic::{self, AssertUnwindSafe},
+  ptr, slice,
+  sync::{
+    atomic::{AtomicPtr, Ordering},
+    OnceLock,
+  },
+};
+
+use ipc::message::HookState;
+use log::error;
+use win::{
+  error::util::get_last_error,
+  pe::{Layout, PeImage, MAPPED_HEADERS_SIZE},
+};
+
+use crate::{context, context::HookContext, BoxResult, Pvoid};
+
+// values mirror winnt.h
+const EXCEPTION_ACCESS_VIOLATION: u32 = 0xc0000005;
+const EXCEPTION_CONTINUE_SEARCH: i32 = 0;
+const EXCEPTION_MAXIMUM_PARAMETERS: usize = 15;
+
+type VectoredExceptionHandler = extern "system" fn(info: *mut ExceptionPointers) -> i32;
+
+#[repr(C)]
+struct ExceptionRecord {
+  exception_code: u32,
+  exception_flags: u32,
+  exception_record: *mut ExceptionRecord,
+  exception_address: Pvoid,
+  number_parameters: u32,
+  exception_information: [usize; EXCEPTION_MAXIMUM_PARAMETERS],
+}
+
+#[repr(C)]
+struct ExceptionPointers {
+  exception_record: *mut ExceptionRecord,
+  context_record: Pvoid,
+}
+
+extern "system" {
+  fn AddVectoredExceptionHandler(first: u32, handler: VectoredExceptionHandler) -> Pvoid;
+  fn RemoveVectoredExceptionHandler(handle: Pvoid) -> u32;
+}
+
+// where hook.dll is mapped, set before the exception handler is added
+static MODULE_RANGE: OnceLock<Range<usize>> = OnceLock::new();
+static EXCEPTION_HANDLER: AtomicPtr<ffi::c_void> = AtomicPtr::new(ptr::null_mut());
+
+// runs a detour callback so that nothing it does can unwind into game code
+// panics and errors are counted and logged, the caller still calls the original either way
+pub fn guard(context: &HookContext, name: &str, callback: impl FnOnce() -> BoxResult<()>) {
+  let failed = match panic::catch_unwind(AssertUnwindSafe(callback)) {
+    Ok(Ok(_)) => return,
+    Ok(Err(error)) => format!("{} errored: {:?}", name, error),
+    Err(error) => format!("{} panicked: {:?}", name, error),
+  };
+
+  let failures = context.add_failure();
+
+  error!("{} ({} callback failures so far)", failed, failures);
+}
+
+// disables every hook if an access violation comes from hook.dll or one of our trampolines
+// the exception is still passed on, so the game crashes or recovers as it would have without us, but unhooked
+// safety
+// - module must be the base of hook.dll
+pub unsafe fn add_exception_handler(module: Pvoid) -> BoxResult<()> {
+  let headers = slice::from_raw_parts(module as *const u8, MAPPED_HEADERS_SIZE);
+  let image = PeImage::parse(headers, Layout::Mapped)?;
+
+  let base = module as usize;
+  let _ = MODULE_RANGE.set(base..base + image.size_of_image() as usize);
+
+  let handle = AddVectoredExceptionHandler(1, exception_handler);
+
+  if handle.is_null() {
+    let error = get_last_error("AddVectoredExceptionHandler");
+
+    return Err(Box::new(error));
+  }
+
+  EXCEPTION_HANDLER.store(handle, Ordering::SeqCst);
+
+  Ok(())
+}
+
+// has to happen before hook.dll is unloaded, the handler would be left pointing at unmapped code
+pub fn remove_exception_handler() {
+  let handle = EXCEPTION_HANDLER.swap(ptr::null_mut(), Ordering::SeqCst);
+
+  if !handle.is_null() {
+    unsafe { RemoveVectoredExceptionHandler(handle) };
+  }
+}
+
+// runs on whichever thread faulted, possibly with locks held, so it only touches atomics and code bytes
+extern "system" fn exception_handler(info: *mut ExceptionPointers) -> i32 {
+  let record = unsafe {
+    // safety
+    // - the os hands us valid exception pointers for the duration of the call
+    &*(*info).exception_record
+  };
+
+  if record.exception_code != EXCEPTION_ACCESS_VIOLATION {
+    return EXCEPTION_CONTINUE_SEARCH;
+  }
+
+  let addr = record.exception_address as usize;
+
+  let context = match context::get() {
+    Some(context) => context,
+    None => return EXCEPTION_CONTINUE_SEARCH,
+  };
+
+  let ours = MODULE_RANGE.get().is_some_and(|range| range.contains(&addr)) || context.owns_trampoline(addr);
+
+  if ours {
+    context.set_state(HookState::ShuttingDown);
+
+    unsafe {
+      // safety
+      // - best effort, dll_attach could still be enabling hooks on another thread
+      context.disable_hooks();
+    }
+  }
+
+  EXCEPTION_CONTINUE_SEARCH
+}
+
+#[cfg(test)]
+mod tests {
+  use ipc::config::HookConfig;
+
+  use super::*;
+  use crate::{detour::Detour, frame::FrameClock};
+
+  extern "system" fn target() {}
+
+  // a context with nothing enabled, the detour is only built, never written
+  fn context() -> HookContext {
+    unsafe {
+      // safety
+      // - the detour only copies target's first bytes, it is never enabled
+      // - the frame clock is never enabled either
+      HookContext::new(
+        HookConfig::default(),
+        None,
+        Detour::new(target as extern "system" fn() as usize).unwrap(),
+        Vec::new(),
+        FrameClock::new(0),
+      )
+    }
+  }
+
+  fn failures(context: &HookContext) -> u32 {
+    context.status().callback_failures
+  }
+
+  #[test]
+  fn runs_callbacks() {
+    let context = context();
+    let mut ran = false;
+
+    guard(&context, "ok", || {
+      ran = true;
+
+      Ok(())
+    });
+
+    assert!(ran);
+    assert_eq!(failures(&context), 0);
+  }
+
+  #[test]
+  fn counts_errors() {
+    let context = context();
+
+    guard(&context, "error", || Err("no device".into()));
+    guard(&context, "error", || Err("no device".into()));
+
+    assert_eq!(failures(&context), 2);
+  }
+
+  #[test]
+  fn counts_panics() {
+    let context = context();
+
+    guard(&context, "panic", || panic!("bad offset"));
+    guard(&context, "ok", || Ok(()));
+    guard(&context, "error", || Err("no device".into()));
+
+    assert_eq!(failures(&context), 2);
+  }
+}
@@ -0,0 +1,137 @@
+// devices driven from inside the game, see HapticsMode::InProcess
+// without the local-haptics feature every function here is a no-op and the app drives the devices
+
+use std::time::Duration;
+#[cfg(feature = "local-haptics")]
+use std::{mem, sync::PoisonError};
+
+use ipc::message::FrameTick;
+#[cfg(not(feature = "local-haptics"))]
+use log::warn;
+#[cfg(feature = "local-haptics")]
+use vibe::{init_xbone, pattern::Pattern};
+
+use crate::{context::HookContext, BoxResult};
+
+// TODO: better error type
+// blocks until a device shows up
+#[cfg(feature = "local-haptics")]
+pub fn start(context: &HookContext) -> BoxResult<()> {
+  let xbone = init_xbone()?;
+
+  *context.xbone.lock().unwrap_or_else(PoisonError::into_inner) = Some(xbone);
+
+  Ok(())
+}
+
+#[cfg(not(feature = "local-haptics"))]
+pub fn start(_context: &HookContext) -> BoxResult<()> {
+  warn!("built without local-haptics, events are only forwarded to the app");
+
+  Ok(())
+}
+
+// in frames, the game runs at 60 fps
+#[cfg(feature = "local-haptics")]
+const LIFE_CHANGED_FRAMES: u64 = 300;
+
+// TODO: better error type
+#[cfg(feature = "local-haptics")]
+pub fn stop(context: &HookContext) -> BoxResult<()> {
+  context.scheduler.lock().unwrap_or_else(PoisonError::into_inner).stop();
+
+  if let Some(xbone) = &*context.xbone.lock().unwrap_or_else(PoisonError::into_inner) {
+    xbone.stop()?;
+  }
+
+  Ok(())
+}
+
+// the app stops its own devices
+#[cfg(not(feature = "local-haptics"))]
+pub fn stop(_context: &HookContext) -> BoxResult<()> {
+  Ok(())
+}
+
+// TODO: better error type
+// starts on the next frame, see tick
+#[cfg(feature = "local-haptics")]
+pub fn play(context: &HookContext) -> BoxResult<()> {
+  context
+    .scheduler
+    .lock()
+    .unwrap_or_else(PoisonError::into_inner)
+    .play(Pattern::constant(1.0, LIFE_CHANGED_FRAMES));
+
+  Ok(())
+}
+
+#[cfg(not(feature = "local-haptics"))]
+pub fn play(_context: &HookContext) -> BoxResult<()> {
+  Ok(())
+}
+
+// TODO: better error type
+// runs on every frame, only touches the devices when the intensity changes
+#[cfg(feature = "local-haptics")]
+pub fn tick(context: &HookContext, tick: FrameTick) -> BoxResult<()> {
+  let intensity = context
+    .scheduler
+    .lock()
+    .unwrap_or_else(PoisonError::into_inner)
+    .tick(tick.frame, tick.paused);
+
+  if let (Some(intensity), Some(xbone)) = (
+    intensity,
+    &*context.xbone.lock().unwrap_or_else(PoisonError::into_inner),
+  ) {
+    xbone.set(intensity)?;
+  }
+
+  Ok(())
+}
+
+#[cfg(not(feature = "local-haptics"))]
+pub fn tick(_context: &HookContext, _tick: FrameTick) -> BoxResult<()> {
+  Ok(())
+}
+
+// TODO: better error type
+// stops every device and the runtime driving them, giving up on the runtime's threads after timeout
+#[cfg(feature = "local-haptics")]
+pub fn shutdown(context: &HookContext, timeout: Duration) -> BoxResult<()> {
+  let xbone = context.xbone.lock().unwrap_or_else(PoisonError::into_inner).take();
+
+  match xbone {
+    Some(xbone) => xbone.shutdown(timeout),
+    None => Ok(()),
+  }
+}
+
+#[cfg(not(feature = "local-haptics"))]
+pub fn shutdown(_context: &HookContext, _timeout: Duration) -> BoxResult<()> {
+  Ok(())
+}
+
+// TODO: better error type
+// for when the runtime's threads cannot be waited on, ie. under the loader lock
+// asks the devices to stop and leaks the runtime, dropping it would block until its threads exit
+#[cfg(feature = "local-haptics")]
+pub fn abandon(context: &HookContext) -> BoxResult<()> {
+  let xbone = context.xbone.lock().unwrap_or_else(PoisonError::into_inner).take();
+
+  if let Some(xbone) = xbone {
+    let stopped = xbone.stop();
+
+    mem::forget(xbone);
+
+    stopped?;
+  }
+
+  Ok(())
+}
+
+#[cfg(not(feature = "local-haptics"))]
+pub fn abandon(_context: &HookContext) -> BoxResult<()> {
+  Ok(())
+}
@@ -1,17 +1,56 @@
 #![feature(abi_thiscall)]
 
+mod context;
+mod d3d;
+mod detour;
+mod frame;
+mod guard;
+mod haptics;
+mod logger;
+mod midhook;
+mod shutdown;
+mod slot;
+mod worker;
+
 use std::{
-  error, ffi, fmt, mem, panic, ptr, thread,
-  time::{self, Duration},
+  error,
+  ffi::{self, OsString},
+  mem,
+  os::windows::ffi::OsStringExt,
+  panic,
+  path::PathBuf,
+  process, ptr, slice,
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    mpsc, Condvar, Mutex, PoisonError,
+  },
+  time::Duration,
 };
 
-use vibe::{init_xbone, XBone};
+use ipc::{
+  config::{blob_len, HapticsMode, HookConfig, BLOB_HEADER_SIZE},
+  message::{AppMessage, GameEvent, HookMessage},
+  transport::{Connection, HookReceiver, HookSender},
+};
+use log::{debug, error, info, warn};
+use win::error::{util::get_last_error, WinApiCodeResult};
+
+use crate::{
+  context::{AppLink, HookContext},
+  detour::Detour,
+  frame::FrameClock,
+  guard::{add_exception_handler, guard},
+  midhook::{Context, MidHook, MidHookFn},
+  shutdown::Trigger,
+  worker::Worker,
+};
 
 type Pvoid = *mut ffi::c_void;
 type PthreadStartRoutine = extern "system" fn(parameter: Pvoid) -> u32;
 
 type BoxResult<T> = Result<T, Box<dyn error::Error + Send + Sync + 'static>>;
-type WinApiResult<T> = Result<T, WinApiErrorCode>;
+
+type HealthFn = extern "thiscall" fn(object: *mut usize, delta: i32) -> i32;
 
 #[repr(u32)]
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
@@ -37,26 +76,7 @@ enum ThreadCreationFlags {
   StackSizeParamIsAReservation = 0x00010000,
 }
 
-// TODO: make an enum of all error codes
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
-struct WinApiErrorCode(u32);
-
-impl WinApiErrorCode {
-  fn new(code: u32) -> Self {
-    Self(code)
-  }
-}
-
-impl fmt::Display for WinApiErrorCode {
-  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    write!(f, "winapi error code: {}", self.0)
-  }
-}
-
-impl error::Error for WinApiErrorCode {}
-
 extern "system" {
-  fn GetLastError() -> u32;
   fn DisableThreadLibraryCalls(dll_module_handle: Pvoid) -> Bool;
   fn CreateThread(
     thread_attributes: Pvoid,
@@ -69,24 +89,19 @@ extern "system" {
   fn FreeLibraryAndExitThread(module_handle: Pvoid, exit_code: u32);
   fn AllocConsole() -> Bool;
   fn GetModuleHandleA(module_name: *const i8) -> Pvoid;
+  fn GetModuleFileNameW(module_handle: Pvoid, file_name: *mut u16, size: u32) -> u32;
   // TODO: better def
   fn VirtualProtect(addr: Pvoid, size: usize, new_protect: u32, old_protect: *mut u32) -> Bool;
   fn VirtualAlloc(addr: Pvoid, size: usize, alloc_type: u32, protect: u32) -> Pvoid;
 }
 
-fn get_last_error() -> WinApiErrorCode {
-  let code = unsafe { GetLastError() };
-
-  WinApiErrorCode::new(code)
-}
-
 // safety
 // - handle must be valid
-unsafe fn disable_thread_library_calls(dll_module_handle: Pvoid) -> WinApiResult<()> {
+unsafe fn disable_thread_library_calls(dll_module_handle: Pvoid) -> WinApiCodeResult<()> {
   let success_code = DisableThreadLibraryCalls(dll_module_handle);
 
   if success_code == Bool::False {
-    let error = get_last_error();
+    let error = get_last_error("DisableThreadLibraryCalls");
 
     Err(error)
   } else {
@@ -102,7 +117,7 @@ unsafe fn create_thread(
   start_addr: PthreadStartRoutine,
   thread_parameter: Pvoid,
   creation_flags: ThreadCreationFlags,
-) -> WinApiResult<(Pvoid, u32)> {
+) -> WinApiCodeResult<(Pvoid, u32)> {
   let (handle, id) = {
     let mut id = 0;
 
@@ -119,7 +134,7 @@ unsafe fn create_thread(
   };
 
   if handle.is_null() {
-    let error = get_last_error();
+    let error = get_last_error("CreateThread");
 
     Err(error)
   } else {
@@ -133,11 +148,11 @@ unsafe fn free_library_and_exit_thread(module_handle: Pvoid, exit_code: u32) {
   FreeLibraryAndExitThread(module_handle, exit_code);
 }
 
-fn alloc_console() -> WinApiResult<()> {
+fn alloc_console() -> WinApiCodeResult<()> {
   let success_code = unsafe { AllocConsole() };
 
   if success_code == Bool::False {
-    let error = get_last_error();
+    let error = get_last_error("AllocConsole");
 
     Err(error)
   } else {
@@ -167,7 +182,7 @@ fn get_module_handle(module_name: Option<&str>) -> BoxResult<Pvoid> {
   let handle = unsafe { GetModuleHandleA(ptr) };
 
   if handle.is_null() {
-    let error = get_last_error();
+    let error = get_last_error("GetModuleHandleA");
 
     Err(Box::new(error))
   } else {
@@ -175,11 +190,25 @@ fn get_module_handle(module_name: Option<&str>) -> BoxResult<Pvoid> {
   }
 }
 
+// safety
+// - handle must be valid
+unsafe fn get_module_file_name(module_handle: Pvoid) -> WinApiCodeResult<PathBuf> {
+  // long enough for any path, MAX_PATH is not
+  let mut buffer = vec![0; 32768];
+
+  let len = GetModuleFileNameW(module_handle, buffer.as_mut_ptr(), buffer.len() as u32);
+
+  if len == 0 {
+    let error = get_last_error("GetModuleFileNameW");
+
+    Err(error)
+  } else {
+    Ok(OsString::from_wide(&buffer[..len as usize]).into())
+  }
+}
+
 #[no_mangle]
 pub extern "stdcall" fn DllMain(dll_module_handle: Pvoid, call_reason: DllReason, reserved: Pvoid) -> Bool {
-  // TODO: tmp
-  alloc_console();
-
   match call_reason {
     DllReason::DllProcessDetach => {
       // reserved is null when free library has been called or dll load has failed
@@ -189,9 +218,9 @@ pub extern "stdcall" fn DllMain(dll_module_handle: Pvoid, call_reason: DllReason
         match panic::catch_unwind(|| dll_cleanup(dll_module_handle)) {
           Ok(detach_result) => match detach_result {
             Ok(_) => {}
-            Err(error) => eprintln!("dll_cleanup errored: {:?}", error),
+            Err(error) => error!("dll_cleanup errored: {:?}", error),
           },
-          Err(error) => eprintln!("dll_cleanup panicked: {:?}", error),
+          Err(error) => error!("dll_cleanup panicked: {:?}", error),
         }
       } else {
         // process is terminating
@@ -199,39 +228,35 @@ pub extern "stdcall" fn DllMain(dll_module_handle: Pvoid, call_reason: DllReason
         match panic::catch_unwind(|| dll_detach(dll_module_handle)) {
           Ok(detach_result) => match detach_result {
             Ok(_) => {}
-            Err(error) => eprintln!("dll_detach errored: {:?}", error),
+            Err(error) => error!("dll_detach errored: {:?}", error),
           },
-          Err(error) => eprintln!("dll_detach panicked: {:?}", error),
+          Err(error) => error!("dll_detach panicked: {:?}", error),
         }
       }
 
       Bool::True
     }
     DllReason::DllProcessAttach => {
-      match unsafe { disable_thread_library_calls(dll_module_handle) } {
-        Ok(_) => {}
-        Err(error) => {
-          eprintln!("disable_thread_library_calls errored: {:?}", error);
+      // logs go next to the dll until the config says otherwise
+      let module_dir = unsafe { get_module_file_name(dll_module_handle) }
+        .ok()
+        .and_then(|path| path.parent().map(PathBuf::from));
 
-          return Bool::False;
-        }
-      };
-
-      match unsafe {
-        create_thread(
-          ptr::null_mut(),
-          0,
-          pthread_dll_attach_wrapper,
-          dll_module_handle,
-          ThreadCreationFlags::CreateImmediate,
-        )
-      } {
-        Ok(_) => {}
-        Err(error) => {
-          eprintln!("create_thread errored: {:?}", error);
-
-          return Bool::False;
-        }
+      logger::init(module_dir);
+
+      // only saves us the thread attach and detach calls, and fails when we were manually mapped,
+      // as we are not in the loader's module list
+      if let Err(error) = unsafe { disable_thread_library_calls(dll_module_handle) } {
+        warn!("disable_thread_library_calls errored: {:?}", error);
+      }
+
+      // the attach thread holds a reference to us like any worker, so a FreeLibrary cannot unload us under it
+      let module = dll_module_handle as usize;
+
+      if let Err(error) = worker::spawn("dll_attach", move || attach_thread(module as Pvoid)) {
+        error!("spawn dll_attach errored: {:?}", error);
+
+        return Bool::False;
       }
 
       Bool::True
@@ -240,133 +265,179 @@ pub extern "stdcall" fn DllMain(dll_module_handle: Pvoid, call_reason: DllReason
   }
 }
 
-// TODO: probably should terminate this thread if DllProcessDetach is called?
-extern "system" fn pthread_dll_attach_wrapper(dll_module_handle: Pvoid) -> u32 {
-  // it is undefined behaviour to unwind from rust into foreign code
-  // so we need to catch any panics
+// returns once the hooks are in place, anything that blocks for longer runs on a worker the shutdown sequence knows of
+fn attach_thread(dll_module_handle: Pvoid) {
+  // caught here rather than by the worker, attach has to be marked done either way
   match panic::catch_unwind(|| dll_attach(dll_module_handle)) {
     Ok(attach_result) => match attach_result {
       Ok(_) => {}
-      Err(error) => eprintln!("dll_attach errored: {:?}", error),
+      Err(error) => error!("dll_attach errored: {:?}", error),
     },
-    Err(error) => eprintln!("dll_attach panicked: {:?}", error),
+    Err(error) => error!("dll_attach panicked: {:?}", error),
   }
 
-  // unsafe { free_library_and_exit_thread(dll_module_handle, 1) };
+  *ATTACH_DONE.lock().unwrap_or_else(PoisonError::into_inner) = true;
+  ATTACH_DONE_SET.notify_all();
+}
 
-  // unreachable!();
+// set by init, which the injector calls straight after loading us
+// only read once by dll_attach, the config after that lives in the context
+static CONFIG: Mutex<Option<HookConfig>> = Mutex::new(None);
+static CONFIG_SET: Condvar = Condvar::new();
 
-  1
-}
+// how long dll_attach holds off installing hooks for a config, plain LoadLibraryW never sends one
+const CONFIG_TIMEOUT: Duration = Duration::from_secs(5);
+
+// set by the shutdown export and FreeLibrary, so a dll_attach still waiting for its config gives up instead
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+// set once the attach thread is done with our code, the shutdown export waits for it as we are unloaded after
+static ATTACH_DONE: Mutex<bool> = Mutex::new(false);
+static ATTACH_DONE_SET: Condvar = Condvar::new();
 
-static mut ORIG_ADDR: usize = 0;
-static mut XBONE: Option<XBone> = None;
+// dll_attach only has to get past connecting to the app and installing the hooks
+const ATTACH_TIMEOUT: Duration = Duration::from_secs(3);
 
 // tmp
 const HEALTH_FN_OFFSET: usize = 0x3c641;
 const THIS_OFFSET: usize = 0x124d380;
-const JMP_REL_32: u8 = 0xe9;
+
+// hooks in the middle of a function, see MidHook
+struct MidHookSpec {
+  name: &'static str,
+  // from the base address, 0 until it has been found
+  offset: usize,
+  callback: MidHookFn,
+}
+
+// TODO: find where th08 bumps the graze counter and collects an item
+const MID_HOOKS: &[MidHookSpec] = &[
+  MidHookSpec {
+    name: "on_graze",
+    offset: 0,
+    callback: on_graze,
+  },
+  MidHookSpec {
+    name: "on_item",
+    offset: 0,
+    callback: on_item,
+  },
+];
 
 // TODO: better error type
 fn dll_attach(dll_module_handle: Pvoid) -> BoxResult<()> {
-  println!("dll attach");
+  info!("dll attach");
 
-  // let object_addr = unsafe { (*(process_handle.add(THIS_OFFSET) as *mut u32)) as *mut u32 };
-  // let hook_offset = unsafe { (hook as *mut u8).sub(health_fn_addr as usize).sub(5) as usize };
-  // println!("{}", std::mem::size_of::<usize>());
+  let config = wait_for_config(CONFIG_TIMEOUT);
 
-  fn vprotecc(base_addr: *mut usize, size: usize) {
-    unsafe {
-      let mut old_protect = 0;
+  if SHUTDOWN_REQUESTED.load(Ordering::SeqCst) {
+    info!("shut down before attaching, nothing hooked");
 
-      // PAGE_EXECUTE_READWRITE = 0x40
-      let success_code = VirtualProtect(base_addr as Pvoid, size, 0x40, &mut old_protect);
-
-      if success_code == Bool::False {
-        panic!();
-      }
-    }
+    return Ok(());
   }
 
-  fn vallocc(size: usize) -> *mut usize {
-    let mem_ptr = unsafe {
-      // MEM_COMMIT = 0x1000
-      // MEM_RESERVE = 0x2000
-      // PAGE_EXECUTE_READWRITE = 0x40
-      VirtualAlloc(ptr::null_mut(), size, 0x1000 | 0x2000, 0x40)
-    };
-
-    if mem_ptr.is_null() {
-      panic!();
+  if config.log_console {
+    if let Err(error) = alloc_console() {
+      warn!("alloc_console errored: {:?}", error);
     }
-
-    mem_ptr as *mut usize
   }
 
-  fn setup_hook(base_addr: *mut usize, jump_addr: *mut usize) -> Vec<u8> {
-    vprotecc(base_addr, 5);
+  logger::configure(&config);
 
-    let mut stolen_bytes = Vec::with_capacity(5);
+  debug!("config: {:?}", config);
 
-    unsafe {
-      ptr::copy_nonoverlapping(base_addr as *mut u8, stolen_bytes.as_mut_ptr(), 5);
+  let app = match &config.server_addr {
+    Some(endpoint) => match connect_app(endpoint) {
+      Ok(app) => Some(app),
+      Err(error) => {
+        error!("connect_app errored: {:?}", error);
 
-      stolen_bytes.set_len(5);
-    }
-
-    let hook_offset = jump_addr as usize - base_addr as usize - 5;
+        None
+      }
+    },
+    None => None,
+  };
 
-    let hook_bytes = Some(JMP_REL_32)
-      .into_iter()
-      .chain(hook_offset.to_le_bytes())
-      .collect::<Vec<_>>();
+  // let object_addr = unsafe { (*(process_handle.add(THIS_OFFSET) as *mut u32)) as *mut u32 };
+  // let hook_offset = unsafe { (hook as *mut u8).sub(health_fn_addr as usize).sub(5) as usize };
+  // println!("{}", std::mem::size_of::<usize>());
 
-    unsafe {
-      ptr::copy_nonoverlapping(hook_bytes.as_ptr(), base_addr as *mut u8, 5);
-    }
+  // base addr
+  let process_addr = get_module_handle(None)? as usize;
 
-    stolen_bytes
-  }
+  debug!("base addr: {:x?}", process_addr);
 
-  fn setup_trampoline(base_addr: *mut usize, jump_addr: *mut usize) -> extern "thiscall" fn(*mut usize, i32) -> i32 {
-    const ALLOC_SIZE: usize = 1024;
+  // hook health fn
+  let health_fn_addr = process_addr + HEALTH_FN_OFFSET;
 
-    let stolen_bytes = setup_hook(base_addr, jump_addr);
-    let alloc = vallocc(ALLOC_SIZE);
+  let health = unsafe {
+    // safety
+    // - tmp offset of the health fn, which starts with whole instructions
+    Detour::new(health_fn_addr)?
+  };
 
-    // (base + 5) - (alloc + 5) - 5
-    let base_offset = (base_addr as isize + 5) - (alloc as isize + 5) - 5;
+  let mid_hooks = MID_HOOKS
+    .iter()
+    .filter(|spec| spec.offset != 0)
+    .map(|spec| unsafe {
+      // safety
+      // - offsets are only filled in once they point at the start of an instruction nothing jumps past
+      MidHook::new(spec.name, process_addr + spec.offset, spec.callback)
+    })
+    .collect::<BoxResult<Vec<_>>>()?;
+
+  let frame_clock = unsafe {
+    // safety
+    // - process_addr is the base of the game, and this is the attach thread, not DllMain
+    FrameClock::new(process_addr)
+  };
 
-    let tramp_bytes = stolen_bytes
-      .into_iter()
-      .chain(Some(JMP_REL_32))
-      .chain(base_offset.to_le_bytes())
-      .collect::<Vec<_>>();
+  let (app, workers) = match app {
+    Some((app, receiver, writer)) => (Some(app), Some((receiver, writer))),
+    None => (None, None),
+  };
 
-    let orig = unsafe {
-      ptr::copy_nonoverlapping(tramp_bytes.as_ptr(), alloc as *mut u8, 10);
+  let haptics = config.haptics;
 
-      mem::transmute(alloc)
-    };
+  // the hooks find their trampolines and callbacks through the context, so it has to be set before they are enabled
+  let context = match context::set(HookContext::new(config, app, health, mid_hooks, frame_clock)) {
+    Some(context) => context,
+    None => return Err("dll_attach ran twice".into()),
+  };
 
-    // tmp
-    unsafe {
-      ORIG_ADDR = alloc as usize;
-    };
-    //
+  // commands need the context too
+  if let Some((receiver, writer)) = workers {
+    context.add_worker(writer);
+    context.add_worker(worker::spawn("read_commands", move || {
+      read_commands(receiver, context)
+    })?);
+  }
 
-    orig
+  unsafe {
+    // safety
+    // - dll_module_handle is our own base address
+    add_exception_handler(dll_module_handle)?;
   }
 
-  // base addr
-  let process_addr = get_module_handle(None)? as usize;
+  unsafe {
+    // safety
+    // - hook has the signature and calling convention of the health fn, frame::present that of IDirect3DDevice8::Present
+    // - a shutdown disabling them at the same time is caught below
+    context.health.enable(hook as HealthFn as usize)?;
 
-  println!("base addr: {:x?}", process_addr);
+    for mid_hook in &context.mid_hooks {
+      mid_hook.enable()?;
+    }
 
-  // hook health fn
-  let health_fn_addr = process_addr + HEALTH_FN_OFFSET;
+    context.frame_clock.enable()?;
 
-  let orig = setup_trampoline(health_fn_addr as *mut usize, hook as *mut usize);
+    // the shutdown export can come in at any point, and would have found nothing to disable
+    if context.is_shutting_down() {
+      context.disable_hooks();
+
+      return Ok(());
+    }
+  }
 
   // test
   let object_addr = process_addr + THIS_OFFSET;
@@ -417,42 +488,276 @@ fn dll_attach(dll_module_handle: Pvoid) -> BoxResult<()> {
 
   // f(other as *const usize, -1);
 
-  let xbone = init_xbone()?;
+  context.set_ready();
+  context.post(HookMessage::Status(context.status()));
 
-  unsafe {
-    XBONE = Some(xbone);
-  }
+  frame::start(context);
 
-  thread::sleep(time::Duration::from_millis(5000));
+  // blocks until a device shows up, which may be never
+  // shutdown gives up on the thread at its deadline, as it cannot be interrupted
+  if haptics == HapticsMode::InProcess {
+    match worker::spawn("haptics::start", move || {
+      guard(context, "haptics::start", || haptics::start(context))
+    }) {
+      Ok(worker) => context.add_worker(worker),
+      Err(error) => error!("spawn haptics::start errored: {:?}", error),
+    }
+  }
 
   Ok(())
 }
 
 // TODO: better error type
 fn dll_detach(dll_module_handle: Pvoid) -> BoxResult<()> {
-  println!("dll detach");
+  info!("dll detach");
+
+  // the game is exiting and every other thread is already gone, along with our runtime and the pipe
+  // restoring the hooks or stopping devices from here would only touch dead state
 
   Ok(())
 }
 
 // TODO: better error type
 fn dll_cleanup(dll_module_handle: Pvoid) -> BoxResult<()> {
-  println!("dll cleanup");
+  info!("dll cleanup");
+
+  // the attach thread and every worker hold a reference to us, so they have all exited by the time we get here
+  request_shutdown();
+
+  // a no-op if the injector already called the shutdown export
+  match context::get() {
+    Some(context) => shutdown::shutdown(context, Trigger::Unload),
+    None => Ok(()),
+  }
+}
+
+// wakes dll_attach if it is still waiting for its config
+fn request_shutdown() {
+  let _config = CONFIG.lock().unwrap_or_else(PoisonError::into_inner);
+
+  SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+  CONFIG_SET.notify_all();
+}
+
+// cut short by request_shutdown
+fn wait_for_config(timeout: Duration) -> HookConfig {
+  let config = CONFIG.lock().unwrap_or_else(PoisonError::into_inner);
+
+  let (config, _) = CONFIG_SET
+    .wait_timeout_while(config, timeout, |config| {
+      config.is_none() && !SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+    })
+    .unwrap_or_else(PoisonError::into_inner);
+
+  match &*config {
+    Some(config) => config.clone(),
+    None => {
+      warn!("no config received, using defaults");
+
+      HookConfig::default()
+    }
+  }
+}
+
+// our end of the connection, where the app's commands come in, and the thread writing our messages out
+fn connect_app(endpoint: &str) -> BoxResult<(AppLink, HookReceiver, Worker)> {
+  let connection = Connection::connect(endpoint)?;
+  let (mut sender, receiver) = connection.try_clone()?.split()?;
+
+  sender.send(&HookMessage::Hello {
+    pid: process::id(),
+    version: env!("CARGO_PKG_VERSION").to_string(),
+  })?;
+
+  let (outbox, inbox) = mpsc::channel();
+
+  let writer = worker::spawn("write_messages", move || write_messages(sender, inbox))?;
+
+  Ok((AppLink { outbox, connection }, receiver, writer))
+}
+
+fn write_messages(mut sender: HookSender, inbox: mpsc::Receiver<HookMessage>) {
+  for message in inbox {
+    if let Err(error) = sender.send(&message) {
+      error!("write_messages errored: {:?}", error);
+
+      break;
+    }
+  }
+}
+
+fn read_commands(mut receiver: HookReceiver, context: &'static HookContext) {
+  loop {
+    match receiver.recv() {
+      Ok(command) => {
+        if let Err(error) = handle_command(command, context) {
+          error!("handle_command errored: {:?}", error);
+        }
+      }
+      Err(error) if error.is_closed() => {
+        info!("app disconnected");
+
+        break;
+      }
+      Err(error) => {
+        error!("read_commands errored: {:?}", error);
+
+        break;
+      }
+    }
+  }
+}
+
+// TODO: better error type
+fn handle_command(command: AppMessage, context: &'static HookContext) -> BoxResult<()> {
+  debug!("command: {:?}", command);
+
+  match command {
+    AppMessage::ReloadConfig(config) => {
+      *context.config.lock().unwrap_or_else(PoisonError::into_inner) = config;
+    }
+    AppMessage::StopAll => haptics::stop(context)?,
+    // shutdown waits for this thread, so it cannot run on it
+    AppMessage::Unhook => {
+      worker::spawn("shutdown", move || {
+        if let Err(error) = shutdown::shutdown(context, Trigger::App) {
+          error!("shutdown errored: {:?}", error);
+        }
+      })?;
+    }
+    AppMessage::QueryStatus => {}
+  }
+
+  context.post(HookMessage::Status(context.status()));
 
   Ok(())
 }
 
-extern "thiscall" fn hook(object: *mut usize, delta: i32) {
-  println!("owo hooked {:?} {:?}", object, delta);
+// called by the injector on a remote thread with our config blob, straight after loading us
+#[no_mangle]
+pub extern "system" fn init(blob: *const u8) -> Bool {
+  match panic::catch_unwind(|| unsafe { dll_init(blob) }) {
+    Ok(init_result) => match init_result {
+      Ok(_) => Bool::True,
+      Err(error) => {
+        error!("dll_init errored: {:?}", error);
+
+        Bool::False
+      }
+    },
+    Err(error) => {
+      error!("dll_init panicked: {:?}", error);
 
-  unsafe {
-    match &XBONE {
-      Some(xbone) => xbone.vibe(1.0, Duration::from_millis(5000)).unwrap(),
-      None => {}
+      Bool::False
     }
   }
+}
 
-  let orig: extern "thiscall" fn(*mut usize, i32) -> i32 = unsafe { mem::transmute(ORIG_ADDR as *mut usize) };
+// TODO: better error type
+// safety
+// - blob must point to a whole config blob, which is only valid until we return
+unsafe fn dll_init(blob: *const u8) -> BoxResult<()> {
+  info!("dll init");
+
+  let len = blob_len(slice::from_raw_parts(blob, BLOB_HEADER_SIZE))?;
+  let config = HookConfig::from_blob(slice::from_raw_parts(blob, len))?;
 
-  orig(object, delta);
+  *CONFIG.lock().unwrap_or_else(PoisonError::into_inner) = Some(config);
+  CONFIG_SET.notify_all();
+
+  Ok(())
+}
+
+// called by the injector on a remote thread before it unloads us
+#[no_mangle]
+pub extern "system" fn shutdown(_parameter: Pvoid) -> Bool {
+  match panic::catch_unwind(dll_shutdown) {
+    Ok(shutdown_result) => match shutdown_result {
+      Ok(_) => Bool::True,
+      Err(error) => {
+        error!("dll_shutdown errored: {:?}", error);
+
+        Bool::False
+      }
+    },
+    Err(error) => {
+      error!("dll_shutdown panicked: {:?}", error);
+
+      Bool::False
+    }
+  }
+}
+
+// TODO: better error type
+fn dll_shutdown() -> BoxResult<()> {
+  info!("dll shutdown");
+
+  request_shutdown();
+
+  // dll_attach may still be setting up, and would be unloaded under it once we return
+  let attach_done = ATTACH_DONE.lock().unwrap_or_else(PoisonError::into_inner);
+
+  let (attach_done, _) = ATTACH_DONE_SET
+    .wait_timeout_while(attach_done, ATTACH_TIMEOUT, |attach_done| !*attach_done)
+    .unwrap_or_else(PoisonError::into_inner);
+
+  if !*attach_done {
+    warn!("dll_attach still running after {:?}", ATTACH_TIMEOUT);
+  }
+
+  drop(attach_done);
+
+  // nothing was hooked yet
+  match context::get() {
+    Some(context) => shutdown::shutdown(context, Trigger::Export),
+    None => Ok(()),
+  }
+}
+
+// runs on the game thread, so everything past getting the context goes through guard
+extern "thiscall" fn hook(object: *mut usize, delta: i32) -> i32 {
+  // unreachable, the detour is only enabled once the context is set
+  let context = match context::get() {
+    Some(context) => context,
+    None => return 0,
+  };
+
+  // held until the original returns, so shutdown knows when no game thread is left in here
+  let _call = context.enter_call();
+
+  guard(context, "on_health", || on_health(context, object, delta));
+
+  let orig: HealthFn = unsafe { mem::transmute(context.health.trampoline()) };
+
+  orig(object, delta)
+}
+
+// TODO: better error type
+fn on_health(context: &HookContext, object: *mut usize, delta: i32) -> BoxResult<()> {
+  // calls before we are ready or while shutting down go straight through
+  if !context.is_ready() {
+    return Ok(());
+  }
+
+  debug!("owo hooked {:?} {:?}", object, delta);
+
+  context.post(HookMessage::Event(GameEvent::LifeChanged { delta }));
+
+  haptics::play(context)?;
+
+  Ok(())
+}
+
+// TODO: better error type
+fn on_graze(context: &HookContext, _registers: &mut Context) -> BoxResult<()> {
+  context.post(HookMessage::Event(GameEvent::Graze));
+
+  Ok(())
+}
+
+// TODO: better error type
+fn on_item(context: &HookContext, _registers: &mut Context) -> BoxResult<()> {
+  context.post(HookMessage::Event(GameEvent::ItemCollected));
+
+  Ok(())
 }
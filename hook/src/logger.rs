@@ -0,0 +1,396 @@
+use std::{
+  fs::{self, File, OpenOptions},
+  io::{self, Write},
+  panic,
+  path::{Path, PathBuf},
+  sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex, PoisonError,
+  },
+  time::{SystemTime, UNIX_EPOCH},
+};
+
+use ipc::{
+  config::HookConfig,
+  message::{HookMessage, LogLevel, LogRecord},
+};
+use log::{error, Level, LevelFilter, Log, Metadata, Record};
+
+use crate::context;
+
+// hook.log is the current file, hook.1.log the one before it and so on
+const LOG_FILE_STEM: &str = "hook";
+const MAX_LOG_SIZE: u64 = 1024 * 1024;
+const MAX_LOG_FILES: usize = 3;
+
+// until the config says otherwise
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+static LOGGER: HookLogger = HookLogger {
+  file: Mutex::new(LogFile {
+    dir: None,
+    file: None,
+    size: 0,
+  }),
+  console: AtomicBool::new(false),
+  to_app: AtomicBool::new(false),
+};
+
+// writes every record to a rotating file, and optionally to the console and the app
+// the console goes away with the game, so it is only a debug aid
+struct HookLogger {
+  file: Mutex<LogFile>,
+  console: AtomicBool,
+  to_app: AtomicBool,
+}
+
+impl Log for HookLogger {
+  fn enabled(&self, metadata: &Metadata) -> bool {
+    metadata.level() <= log::max_level()
+  }
+
+  fn log(&self, record: &Record) {
+    if !self.enabled(record.metadata()) {
+      return;
+    }
+
+    let timestamp_ms = timestamp_ms();
+    let message = record.args().to_string();
+
+    let line = format!(
+      "{} {:<5} {}: {}\n",
+      format_timestamp(timestamp_ms),
+      record.level(),
+      record.target(),
+      message
+    );
+
+    // there is nowhere left to report failing to log
+    if self.console.load(Ordering::SeqCst) {
+      let _ = io::stderr().write_all(line.as_bytes());
+    }
+
+    let _ = self.file.lock().unwrap_or_else(PoisonError::into_inner).write(&line);
+
+    if self.to_app.load(Ordering::SeqCst) {
+      if let Some(context) = context::get() {
+        context.post(HookMessage::Log(LogRecord {
+          level: to_ipc_level(record.level()),
+          target: record.target().to_string(),
+          message,
+          timestamp_ms,
+        }));
+      }
+    }
+  }
+
+  fn flush(&self) {
+    if let Some(file) = &mut self.file.lock().unwrap_or_else(PoisonError::into_inner).file {
+      let _ = file.flush();
+    }
+  }
+}
+
+// the file is opened on the first record, and reopened once it is full
+struct LogFile {
+  dir: Option<PathBuf>,
+  file: Option<File>,
+  size: u64,
+}
+
+impl LogFile {
+  fn set_dir(&mut self, dir: PathBuf) {
+    if self.dir.as_ref() != Some(&dir) {
+      self.dir = Some(dir);
+      self.file = None;
+    }
+  }
+
+  fn write(&mut self, line: &str) -> io::Result<()> {
+    let dir = match &self.dir {
+      Some(dir) => dir.clone(),
+      None => return Ok(()),
+    };
+
+    if self.file.is_none() || self.size >= MAX_LOG_SIZE {
+      self.open(&dir)?;
+    }
+
+    if let Some(file) = &mut self.file {
+      file.write_all(line.as_bytes())?;
+
+      self.size += line.len() as u64;
+    }
+
+    Ok(())
+  }
+
+  // rotates first if the current file is already full
+  fn open(&mut self, dir: &Path) -> io::Result<()> {
+    self.file = None;
+
+    let path = log_path(dir, 0);
+    let size = fs::metadata(&path).map(|metadata| metadata.len()).unwrap_or(0);
+
+    if size >= MAX_LOG_SIZE {
+      rotate(dir);
+    }
+
+    let file = OpenOptions::new().create(true).append(true).open(&path)?;
+
+    self.size = file.metadata()?.len();
+    self.file = Some(file);
+
+    Ok(())
+  }
+}
+
+// logs next to the dll at the default level until configure is called
+pub fn init(module_dir: Option<PathBuf>) {
+  if let Some(dir) = module_dir {
+    LOGGER.file.lock().unwrap_or_else(PoisonError::into_inner).set_dir(dir);
+  }
+
+  // only fails if a logger is already set, ie. init ran twice
+  if log::set_logger(&LOGGER).is_ok() {
+    log::set_max_level(DEFAULT_LEVEL);
+
+    // the default hook prints to a console that is usually not there
+    panic::set_hook(Box::new(|info| error!("{}", info)));
+  }
+}
+
+pub fn configure(config: &HookConfig) {
+  if let Some(dir) = &config.log_dir {
+    LOGGER
+      .file
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner)
+      .set_dir(dir.clone());
+  }
+
+  LOGGER.console.store(config.log_console, Ordering::SeqCst);
+  LOGGER.to_app.store(config.log_to_app, Ordering::SeqCst);
+
+  log::set_max_level(to_level_filter(config.log_level));
+}
+
+fn log_path(dir: &Path, index: usize) -> PathBuf {
+  match index {
+    0 => dir.join(format!("{}.log", LOG_FILE_STEM)),
+    index => dir.join(format!("{}.{}.log", LOG_FILE_STEM, index)),
+  }
+}
+
+// hook.log becomes hook.1.log and so on, dropping the oldest
+fn rotate(dir: &Path) {
+  for index in (1..MAX_LOG_FILES).rev() {
+    let _ = fs::rename(log_path(dir, index - 1), log_path(dir, index));
+  }
+}
+
+fn to_ipc_level(level: Level) -> LogLevel {
+  match level {
+    Level::Error => LogLevel::Error,
+    Level::Warn => LogLevel::Warn,
+    Level::Info => LogLevel::Info,
+    Level::Debug => LogLevel::Debug,
+    Level::Trace => LogLevel::Trace,
+  }
+}
+
+fn to_level_filter(level: LogLevel) -> LevelFilter {
+  match level {
+    LogLevel::Error => LevelFilter::Error,
+    LogLevel::Warn => LevelFilter::Warn,
+    LogLevel::Info => LevelFilter::Info,
+    LogLevel::Debug => LevelFilter::Debug,
+    LogLevel::Trace => LevelFilter::Trace,
+  }
+}
+
+fn timestamp_ms() -> u64 {
+  SystemTime::now()
+    .duration_since(UNIX_EPOCH)
+    .map(|duration| duration.as_millis() as u64)
+    .unwrap_or(0)
+}
+
+// utc, eg. 2022-05-14T18:03:07.042Z
+fn format_timestamp(timestamp_ms: u64) -> String {
+  let secs = timestamp_ms / 1000;
+  let (year, month, day) = civil_from_days((secs / 86400) as i64);
+  let secs_of_day = secs % 86400;
+
+  format!(
+    "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+    year,
+    month,
+    day,
+    secs_of_day / 3600,
+    secs_of_day / 60 % 60,
+    secs_of_day % 60,
+    timestamp_ms % 1000
+  )
+}
+
+// days since 1970-01-01 to a gregorian year, month and day, see howard hinnant's chrono-compatible date algorithms
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+  let days = days + 719468;
+  let era = days.div_euclid(146097);
+  let day_of_era = days.rem_euclid(146097);
+  let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  // months counted from march, so the leap day is last
+  let month_from_march = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+  let month = if month_from_march < 10 {
+    month_from_march + 3
+  } else {
+    month_from_march - 9
+  };
+  let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+  (year, month as u32, day as u32)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::process;
+
+  use super::*;
+
+  // an empty directory of its own under the temp directory, removed when dropped
+  struct TempDir(PathBuf);
+
+  impl TempDir {
+    fn new(name: &str) -> Self {
+      let dir = std::env::temp_dir().join(format!("hook-{}-{}", name, process::id()));
+
+      let _ = fs::remove_dir_all(&dir);
+      fs::create_dir_all(&dir).unwrap();
+
+      Self(dir)
+    }
+  }
+
+  impl Drop for TempDir {
+    fn drop(&mut self) {
+      let _ = fs::remove_dir_all(&self.0);
+    }
+  }
+
+  fn log_file(dir: &Path) -> LogFile {
+    let mut log_file = LogFile {
+      dir: None,
+      file: None,
+      size: 0,
+    };
+
+    log_file.set_dir(dir.to_path_buf());
+
+    log_file
+  }
+
+  // a line that fills a log file on its own, starting with tag
+  fn full_line(tag: char) -> String {
+    format!("{}{}\n", tag, ".".repeat(MAX_LOG_SIZE as usize - 2))
+  }
+
+  fn first_char(path: &Path) -> Option<char> {
+    fs::read_to_string(path).ok()?.chars().next()
+  }
+
+  #[test]
+  fn formats_timestamps() {
+    assert_eq!(format_timestamp(0), "1970-01-01T00:00:00.000Z");
+    assert_eq!(format_timestamp(1_652_551_387_042), "2022-05-14T18:03:07.042Z");
+    assert_eq!(format_timestamp(951_868_799_999), "2000-02-29T23:59:59.999Z");
+    assert_eq!(format_timestamp(951_868_800_000), "2000-03-01T00:00:00.000Z");
+  }
+
+  #[test]
+  fn converts_days_to_dates() {
+    let dates = [
+      (0, (1970, 1, 1)),
+      (-1, (1969, 12, 31)),
+      (11016, (2000, 2, 29)),
+      (11017, (2000, 3, 1)),
+      (19782, (2024, 2, 29)),
+      // 1900 is not a leap year, 1600 is
+      (-25509, (1900, 2, 28)),
+      (-25508, (1900, 3, 1)),
+      (-135081, (1600, 2, 29)),
+      (-719162, (1, 1, 1)),
+    ];
+
+    for (days, date) in dates {
+      assert_eq!(civil_from_days(days), date, "{} days", days);
+    }
+  }
+
+  #[test]
+  fn writes_nothing_without_a_dir() {
+    let mut log_file = LogFile {
+      dir: None,
+      file: None,
+      size: 0,
+    };
+
+    log_file.write("dropped\n").unwrap();
+
+    assert!(log_file.file.is_none());
+  }
+
+  #[test]
+  fn appends_to_the_current_file() {
+    let dir = TempDir::new("append");
+
+    fs::write(log_path(&dir.0, 0), "earlier\n").unwrap();
+
+    let mut log_file = log_file(&dir.0);
+
+    log_file.write("later\n").unwrap();
+    log_file.file = None;
+
+    assert_eq!(fs::read_to_string(log_path(&dir.0, 0)).unwrap(), "earlier\nlater\n");
+    assert!(!log_path(&dir.0, 1).exists());
+  }
+
+  #[test]
+  fn rotates_full_files() {
+    let dir = TempDir::new("rotate");
+    let mut log_file = log_file(&dir.0);
+
+    // every line fills a file, so the next one rotates
+    let tags = ['a', 'b', 'c', 'd', 'e'];
+
+    for tag in tags {
+      log_file.write(&full_line(tag)).unwrap();
+    }
+
+    log_file.file = None;
+
+    // the newest MAX_LOG_FILES lines are kept, newest first
+    for index in 0..MAX_LOG_FILES {
+      assert_eq!(first_char(&log_path(&dir.0, index)), Some(tags[tags.len() - 1 - index]));
+    }
+
+    assert!(!log_path(&dir.0, MAX_LOG_FILES).exists());
+  }
+
+  #[test]
+  fn rotates_a_full_file_left_from_before() {
+    let dir = TempDir::new("reopen");
+
+    fs::write(log_path(&dir.0, 0), full_line('a')).unwrap();
+
+    let mut log_file = log_file(&dir.0);
+
+    log_file.write("b\n").unwrap();
+    log_file.file = None;
+
+    assert_eq!(first_char(&log_path(&dir.0, 0)), Some('b'));
+    assert_eq!(first_char(&log_path(&dir.0, 1)), Some('a'));
+  }
+}
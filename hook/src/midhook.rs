@@ -0,0 +1,236 @@
+use std::{
+  ptr,
+  sync::atomic::{AtomicBool, Ordering},
+};
+
+use win::{
+  decode::{relocate, MAX_INSTRUCTION_SIZE},
+  error::WinApiCodeResult,
+};
+
+use crate::{
+  context::{self, HookContext},
+  detour::{jmp_rel_32, valloc, vprotect, JMP_SIZE},
+  guard::guard,
+  BoxResult,
+};
+
+const NOP: u8 = 0x90;
+const STUB_SIZE: usize = 1024;
+// the most a jmp can cover, when its last byte is the first of the longest instruction
+const MAX_STOLEN_SIZE: usize = JMP_SIZE - 1 + MAX_INSTRUCTION_SIZE;
+
+// TODO: better error type
+// runs on the game thread with the registers as they were at the hooked instruction
+pub type MidHookFn = fn(context: &HookContext, registers: &mut Context) -> BoxResult<()>;
+
+// the general purpose registers and flags, laid out as pushfd then pushad leave them on the stack
+// anything the callback writes is what the game resumes with, except esp
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Context {
+  pub edi: u32,
+  pub esi: u32,
+  pub ebp: u32,
+  // esp after pushfd, so the game's is 4 more, popad skips it
+  pub esp: u32,
+  pub ebx: u32,
+  pub edx: u32,
+  pub ecx: u32,
+  pub eax: u32,
+  pub eflags: u32,
+}
+
+// baked into the stub, and leaked along with it
+struct MidHookData {
+  name: &'static str,
+  callback: MidHookFn,
+}
+
+// a jmp over whole instructions anywhere in a function, to a stub that saves the registers, calls back into us,
+// restores them and runs the relocated instructions before jumping back past them
+#[derive(Debug)]
+pub struct MidHook {
+  target: usize,
+  stub: usize,
+  // everything the jmp covers, the tail of the last instruction included
+  stolen_bytes: Vec<u8>,
+  enabled: AtomicBool,
+}
+
+impl MidHook {
+  // TODO: better error type
+  // builds the stub without touching target, see enable
+  // safety
+  // - target must be the start of an instruction, and nothing may jump into the instructions after it that the jmp covers
+  pub unsafe fn new(name: &'static str, target: usize, callback: MidHookFn) -> BoxResult<Self> {
+    let mut code = [0; MAX_STOLEN_SIZE];
+
+    ptr::copy_nonoverlapping(target as *const u8, code.as_mut_ptr(), MAX_STOLEN_SIZE);
+
+    let stub = valloc(STUB_SIZE)?;
+    let data = Box::leak(Box::new(MidHookData { name, callback })) as *const MidHookData as usize;
+
+    let mut stub_bytes = prologue(data, dispatch as DispatchFn as usize);
+
+    let (relocated, stolen_len) = relocate(&code, target, stub + stub_bytes.len(), JMP_SIZE)?;
+
+    stub_bytes.extend_from_slice(&relocated);
+    stub_bytes.extend_from_slice(&jmp_rel_32(stub + stub_bytes.len(), target + stolen_len));
+
+    ptr::copy_nonoverlapping(stub_bytes.as_ptr(), stub as *mut u8, stub_bytes.len());
+
+    Ok(Self {
+      target,
+      stub,
+      stolen_bytes: code[..stolen_len].to_vec(),
+      enabled: AtomicBool::new(false),
+    })
+  }
+
+  pub fn owns_stub(&self, addr: usize) -> bool {
+    (self.stub..self.stub + STUB_SIZE).contains(&addr)
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.enabled.load(Ordering::SeqCst)
+  }
+
+  // does nothing if already enabled
+  // safety
+  // - must not run concurrently with disable
+  pub unsafe fn enable(&self) -> WinApiCodeResult<()> {
+    if self.is_enabled() {
+      return Ok(());
+    }
+
+    vprotect(self.target, self.stolen_bytes.len())?;
+
+    // the nops are never run, they only keep the tail of the last stolen instruction from being decoded as garbage
+    let mut hook_bytes = vec![NOP; self.stolen_bytes.len()];
+    hook_bytes[..JMP_SIZE].copy_from_slice(&jmp_rel_32(self.target, self.stub));
+
+    ptr::copy_nonoverlapping(hook_bytes.as_ptr(), self.target as *mut u8, hook_bytes.len());
+
+    self.enabled.store(true, Ordering::SeqCst);
+
+    Ok(())
+  }
+
+  // puts the stolen bytes back, does nothing if not enabled
+  // the stub is leaked on purpose, a game thread may still be inside it
+  // safety
+  // - must not run concurrently with enable
+  pub unsafe fn disable(&self) {
+    // the page is still writable from enable
+    if self.enabled.swap(false, Ordering::SeqCst) {
+      ptr::copy_nonoverlapping(
+        self.stolen_bytes.as_ptr(),
+        self.target as *mut u8,
+        self.stolen_bytes.len(),
+      );
+    }
+  }
+}
+
+type DispatchFn = extern "C" fn(data: *const MidHookData, registers: *mut Context);
+
+// pushfd; pushad; then dispatch(data, esp) and back to the state pushad saved
+fn prologue(data: usize, dispatch: usize) -> Vec<u8> {
+  let mut bytes = Vec::new();
+
+  // pushfd; pushad
+  bytes.extend_from_slice(&[0x9c, 0x60]);
+  // cld, the game may have the direction flag set and rust code expects it clear, popfd puts it back
+  bytes.push(0xfc);
+  // mov eax, esp; push eax
+  bytes.extend_from_slice(&[0x89, 0xe0, 0x50]);
+  // push data
+  bytes.push(0x68);
+  bytes.extend_from_slice(&(data as u32).to_le_bytes());
+  // mov eax, dispatch; call eax
+  bytes.push(0xb8);
+  bytes.extend_from_slice(&(dispatch as u32).to_le_bytes());
+  bytes.extend_from_slice(&[0xff, 0xd0]);
+  // add esp, 8 (cdecl, the caller pops the arguments)
+  bytes.extend_from_slice(&[0x83, 0xc4, 0x08]);
+  // popad; popfd
+  bytes.extend_from_slice(&[0x61, 0x9d]);
+
+  bytes
+}
+
+// runs on the game thread, so everything past getting the context goes through guard
+// the call guard only covers hook.dll, the stub is never freed so it is safe to return into after a shutdown
+extern "C" fn dispatch(data: *const MidHookData, registers: *mut Context) {
+  let (data, registers) = unsafe {
+    // safety
+    // - data was leaked by new, registers points at what the stub just pushed
+    (&*data, &mut *registers)
+  };
+
+  // unreachable, mid hooks are only enabled once the context is set
+  let context = match context::get() {
+    Some(context) => context,
+    None => return,
+  };
+
+  let _call = context.enter_call();
+
+  // calls before we are ready or while shutting down go straight through
+  if !context.is_ready() {
+    return;
+  }
+
+  guard(context, data.name, || (data.callback)(context, registers));
+}
+
+#[cfg(test)]
+mod tests {
+  use std::mem::{self, offset_of};
+
+  use super::*;
+
+  const DATA: usize = 0x1122_3344;
+  const DISPATCH: usize = 0x5566_7788;
+
+  #[test]
+  fn prologue_bytes() {
+    assert_eq!(
+      prologue(DATA, DISPATCH),
+      [
+        &[0x9c, 0x60][..],
+        &[0xfc],
+        &[0x89, 0xe0, 0x50],
+        &[0x68, 0x44, 0x33, 0x22, 0x11],
+        &[0xb8, 0x88, 0x77, 0x66, 0x55],
+        &[0xff, 0xd0],
+        &[0x83, 0xc4, 0x08],
+        &[0x61, 0x9d],
+      ]
+      .concat()
+    );
+  }
+
+  #[test]
+  fn context_matches_pushfd_pushad() {
+    // in push order, pushfd first then pushad's eax through edi, so each one ends up 4 bytes below the last
+    let pushed = [
+      offset_of!(Context, eflags),
+      offset_of!(Context, eax),
+      offset_of!(Context, ecx),
+      offset_of!(Context, edx),
+      offset_of!(Context, ebx),
+      offset_of!(Context, esp),
+      offset_of!(Context, ebp),
+      offset_of!(Context, esi),
+      offset_of!(Context, edi),
+    ];
+
+    for (i, offset) in pushed.into_iter().enumerate() {
+      assert_eq!(offset, (pushed.len() - 1 - i) * 4, "register {} in push order", i);
+    }
+
+    assert_eq!(mem::size_of::<Context>(), pushed.len() * 4);
+  }
+}
@@ -0,0 +1,132 @@
+use std::{
+  thread,
+  time::{Duration, Instant},
+};
+
+use ipc::message::{HookMessage, HookState};
+use log::{info, warn};
+
+use crate::{context::HookContext, guard::remove_exception_handler, haptics, BoxResult};
+
+// the whole sequence, shared between the steps that have to wait on something
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(3);
+const POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+  // the shutdown export, which the injector calls before unloading us
+  Export,
+  // the app asked over ipc, we stay loaded but go quiet
+  App,
+  // FreeLibrary without the export being called first
+  // every worker holds a reference to us, so they have all exited by now, or we were manually mapped and this never runs
+  Unload,
+}
+
+// disables every hook, waits for calls already inside them, stops the devices and closes the ipc threads
+// only the first call runs the sequence, whatever triggered it, later ones wait for it to finish
+// a failure to stop the devices does not stop the rest of the sequence, it is returned at the end
+pub fn shutdown(context: &HookContext, trigger: Trigger) -> BoxResult<()> {
+  if !context.begin_shutdown() {
+    info!("already shutting down ({:?})", trigger);
+
+    // the app trigger comes in on the ipc reader, which the first sequence may be joining
+    if trigger == Trigger::App {
+      return Ok(());
+    }
+
+    // we are unloaded as soon as the export or DllMain returns, which must not happen halfway through the sequence
+    if !context.wait_for_shutdown(SHUTDOWN_TIMEOUT) {
+      return Err(format!("shutdown still running after {:?}", SHUTDOWN_TIMEOUT).into());
+    }
+
+    return Ok(());
+  }
+
+  info!("shutting down ({:?})", trigger);
+
+  let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+
+  // hooks still running stop acting on calls from here on
+  context.set_state(HookState::ShuttingDown);
+
+  unsafe {
+    // safety
+    // - dll_attach checks for a shutdown after enabling them, and disables them itself if it raced this
+    context.disable_hooks();
+  }
+
+  context.post(HookMessage::Status(context.status()));
+
+  // a game thread can still be in a detour, or in the original called from one, and would return into unmapped code
+  if !wait_until(deadline, || context.calls_in_flight() == 0) {
+    warn!("{} calls still in flight after shutdown", context.calls_in_flight());
+  }
+
+  let stopped = match trigger {
+    Trigger::Unload => haptics::abandon(context),
+    Trigger::Export | Trigger::App => haptics::shutdown(context, remaining(deadline)),
+  };
+
+  match trigger {
+    Trigger::Unload => stop_workers(context),
+    Trigger::Export | Trigger::App => join_workers(context, deadline),
+  }
+
+  remove_exception_handler();
+
+  info!("shut down");
+
+  context.finish_shutdown();
+
+  stopped
+}
+
+// tells the ipc threads to stop without waiting for them, we hold the loader lock so they could not exit anyway
+fn stop_workers(context: &HookContext) {
+  context.close_outbox();
+
+  if let Err(error) = context.interrupt_app() {
+    warn!("interrupt_app errored: {:?}", error);
+  }
+}
+
+// the writer stops once it has flushed the outbox, the reader once its recv is interrupted
+fn join_workers(context: &HookContext, deadline: Instant) {
+  context.close_outbox();
+
+  let workers = context.take_workers();
+
+  // an interrupt only fails the recv already in progress, the reader can start another one in between
+  let finished = wait_until(deadline, || {
+    if let Err(error) = context.interrupt_app() {
+      warn!("interrupt_app errored: {:?}", error);
+    }
+
+    workers.iter().all(|worker| worker.is_finished())
+  });
+
+  // those still running keep us loaded until they exit, see worker
+  if !finished {
+    warn!("worker threads still running after shutdown");
+  }
+}
+
+// false if done still is not true by deadline
+fn wait_until(deadline: Instant, mut done: impl FnMut() -> bool) -> bool {
+  loop {
+    if done() {
+      return true;
+    }
+
+    if Instant::now() >= deadline {
+      return false;
+    }
+
+    thread::sleep(POLL_INTERVAL);
+  }
+}
+
+fn remaining(deadline: Instant) -> Duration {
+  deadline.saturating_duration_since(Instant::now())
+}
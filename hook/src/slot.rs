@@ -0,0 +1,101 @@
+use std::{
+  mem, slice,
+  sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use win::{
+  error::WinApiCodeResult,
+  pe::{import::ImportName, Layout, PeImage, MAPPED_HEADERS_SIZE},
+};
+
+use crate::{detour::vprotect, BoxResult};
+
+// a function pointer swapped for our own, in a vtable or an import address table
+// unlike a detour nothing in the function itself is touched, so only calls made through the slot are hooked
+#[derive(Debug)]
+pub struct SlotHook {
+  slot: usize,
+  original: usize,
+  enabled: AtomicBool,
+}
+
+impl SlotHook {
+  // safety
+  // - slot must be an aligned function pointer that stays mapped for as long as we are loaded
+  pub unsafe fn new(slot: usize) -> Self {
+    Self {
+      slot,
+      original: (*(slot as *const AtomicUsize)).load(Ordering::SeqCst),
+      enabled: AtomicBool::new(false),
+    }
+  }
+
+  // every object of the same class shares the vtable, so this hooks all of them
+  // safety
+  // - object must be a com object, or anything else whose first field is a vtable pointer
+  // - index must be within the vtable
+  pub unsafe fn vtable(object: usize, index: usize) -> Self {
+    let vtable = *(object as *const usize);
+
+    Self::new(vtable + index * mem::size_of::<usize>())
+  }
+
+  // TODO: better error type
+  // calls module makes to module_name!import, ie. the slot the loader bound when module was loaded
+  // nothing hooks an import yet, it is here for th08's timeGetTime, dinput and dsound calls
+  // safety
+  // - module must be the base of a loaded image
+  #[allow(dead_code)]
+  pub unsafe fn import(module: usize, module_name: &str, import: &ImportName) -> BoxResult<Self> {
+    let headers = slice::from_raw_parts(module as *const u8, MAPPED_HEADERS_SIZE);
+    let size_of_image = PeImage::parse(headers, Layout::Mapped)?.size_of_image() as usize;
+
+    let image = PeImage::parse(
+      slice::from_raw_parts(module as *const u8, size_of_image),
+      Layout::Mapped,
+    )?;
+
+    match image.find_import(module_name, import)? {
+      Some(slot_rva) => Ok(Self::new(module + slot_rva as usize)),
+      None => Err(format!("{:#x} does not import {:?} from {}", module, import, module_name).into()),
+    }
+  }
+
+  // what the slot pointed at before we swapped it, whether or not the hook is enabled
+  pub fn original(&self) -> usize {
+    self.original
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.enabled.load(Ordering::SeqCst)
+  }
+
+  // does nothing if already enabled
+  // the swap is atomic, a game thread calling through the slot gets either the original or detour
+  // safety
+  // - detour must have the same signature and calling convention as the original
+  // - must not run concurrently with disable
+  pub unsafe fn enable(&self, detour: usize) -> WinApiCodeResult<()> {
+    if self.is_enabled() {
+      return Ok(());
+    }
+
+    vprotect(self.slot, mem::size_of::<usize>())?;
+
+    (*(self.slot as *const AtomicUsize)).store(detour, Ordering::SeqCst);
+
+    self.enabled.store(true, Ordering::SeqCst);
+
+    Ok(())
+  }
+
+  // puts the original pointer back, does nothing if not enabled
+  // safety
+  // - must not run concurrently with enable
+  pub unsafe fn disable(&self) {
+    // the page is still writable from enable
+    if self.enabled.swap(false, Ordering::SeqCst) {
+      (*(self.slot as *const AtomicUsize)).store(self.original, Ordering::SeqCst);
+    }
+  }
+}
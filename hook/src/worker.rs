@@ -0,0 +1,140 @@
+// threads running our own code, each holding a reference to hook.dll for as long as it runs
+// FreeLibrary only unloads us once every worker has let go of its reference, so none can resume in unmapped code
+// a worker lets go through FreeLibraryAndExitThread, which never returns into us
+// when we were manually mapped there is no reference to take, but then nothing can unload us either
+
+use std::{
+  panic::{self, AssertUnwindSafe},
+  ptr,
+};
+
+use log::{debug, error};
+use win::error::{util::get_last_error, WinApiCodeResult};
+
+use crate::{create_thread, free_library_and_exit_thread, Bool, Pvoid, ThreadCreationFlags};
+
+// GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS, without _UNCHANGED_REFCOUNT so the reference is ours
+const GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS: u32 = 0x4;
+const WAIT_OBJECT_0: u32 = 0;
+
+extern "system" {
+  fn GetModuleHandleExW(flags: u32, module_name: *const u16, module_handle: *mut Pvoid) -> Bool;
+  fn FreeLibrary(module_handle: Pvoid) -> Bool;
+  fn WaitForSingleObject(handle: Pvoid, milliseconds: u32) -> u32;
+  fn CloseHandle(handle: Pvoid) -> Bool;
+}
+
+// what worker_main needs, boxed and handed over as the thread parameter
+struct WorkerStart {
+  name: &'static str,
+  // none when we were manually mapped
+  module: Option<Pvoid>,
+  run: Box<dyn FnOnce() + Send>,
+}
+
+// a handle to a worker thread, dropping it leaves the thread running
+#[derive(Debug)]
+pub struct Worker {
+  handle: Pvoid,
+}
+
+// safety
+// - a thread handle can be waited on and closed from any thread
+unsafe impl Send for Worker {}
+unsafe impl Sync for Worker {}
+
+impl Worker {
+  pub fn is_finished(&self) -> bool {
+    unsafe { WaitForSingleObject(self.handle, 0) == WAIT_OBJECT_0 }
+  }
+}
+
+impl Drop for Worker {
+  fn drop(&mut self) {
+    unsafe { CloseHandle(self.handle) };
+  }
+}
+
+// like thread::spawn, name is only used to report a panic
+pub fn spawn(name: &'static str, run: impl FnOnce() + Send + 'static) -> WinApiCodeResult<Worker> {
+  // taken before the thread exists, a FreeLibrary in between would otherwise unload us under it
+  let module = own_module_reference();
+
+  let start = Box::into_raw(Box::new(WorkerStart {
+    name,
+    module,
+    run: Box::new(run),
+  }));
+
+  match unsafe {
+    // safety
+    // - worker_main takes back ownership of start, and only runs once the thread does
+    create_thread(
+      ptr::null_mut(),
+      0,
+      worker_main,
+      start as Pvoid,
+      ThreadCreationFlags::CreateImmediate,
+    )
+  } {
+    Ok((handle, _)) => Ok(Worker { handle }),
+    Err(error) => {
+      // the thread never started, so start is still ours
+      let start = unsafe { Box::from_raw(start) };
+
+      if let Some(module) = start.module {
+        unsafe { FreeLibrary(module) };
+      }
+
+      Err(error)
+    }
+  }
+}
+
+// none when the loader does not know of us
+fn own_module_reference() -> Option<Pvoid> {
+  let mut module = ptr::null_mut();
+
+  let success_code = unsafe {
+    GetModuleHandleExW(
+      GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+      worker_main as *const () as *const u16,
+      &mut module,
+    )
+  };
+
+  if success_code == Bool::False {
+    // expected when manually mapped, see the module comment
+    let error = get_last_error("GetModuleHandleExW");
+
+    debug!("no module reference for workers: {:?}", error);
+
+    return None;
+  }
+
+  Some(module)
+}
+
+extern "system" fn worker_main(parameter: Pvoid) -> u32 {
+  let start = unsafe {
+    // safety
+    // - parameter is the WorkerStart spawn leaked for us
+    Box::from_raw(parameter as *mut WorkerStart)
+  };
+
+  let WorkerStart { name, module, run } = *start;
+
+  // it is undefined behaviour to unwind from rust into foreign code
+  if let Err(error) = panic::catch_unwind(AssertUnwindSafe(run)) {
+    error!("{} panicked: {:?}", name, error);
+  }
+
+  match module {
+    Some(module) => {
+      unsafe { free_library_and_exit_thread(module, 0) };
+
+      unreachable!();
+    }
+    None => 0,
+  }
+}
@@ -1,5 +1,688 @@
-mod lib;
+use std::{
+  error,
+  ffi::OsString,
+  fmt,
+  path::{Path, PathBuf},
+  process,
+  sync::atomic::AtomicBool,
+  time::Duration,
+};
+
+use clap::{Arg, ArgGroup, ArgMatches, Command};
+use injector::{
+  eject_module, inject,
+  launch::{launch_and_inject, LaunchOptions},
+  watch::{WatchEvent, WatchOptions, Watcher},
+  InjectError, InjectionMethod, Shutdown,
+};
+use serde::Serialize;
+use win::{
+  error::{code::ErrorCode, WinApiError},
+  process::{access::ProcessAccess, util::processes, WinApiProcess},
+  wintype::Pid,
+};
+
+const DEFAULT_MODULE_PATH: &str = "hook.dll";
+const DEFAULT_MODULE_NAME: &str = "hook.dll";
+const DEFAULT_TIMEOUT_MS: &str = "5000";
+const DEFAULT_LAUNCH_TIMEOUT_MS: &str = "30000";
+const DEFAULT_POLL_INTERVAL_MS: &str = "500";
+const DEFAULT_SETTLE_MS: &str = "2000";
+
+// exit codes, for scripts to tell failures apart without parsing the output
+// clap exits with 2 on usage errors
+const EXIT_FAILURE: i32 = 1;
+// no such process, module or dll
+const EXIT_NOT_FOUND: i32 = 3;
+const EXIT_ACCESS_DENIED: i32 = 4;
+// the target, injector and dll are not all the same bitness
+const EXIT_ARCH_MISMATCH: i32 = 5;
+// the dll was written into the target but failed to load or initialise there
+const EXIT_LOAD_FAILED: i32 = 6;
+const EXIT_TIMED_OUT: i32 = 7;
+
+#[derive(Debug)]
+pub enum CliError {
+  InjectError(InjectError),
+  ProcessListError(WinApiError),
+  ProcessNotFoundError(String),
+  // more than one process with the name, the user has to pick with --pid
+  AmbiguousProcessError { name: String, pids: Vec<Pid> },
+  OutputError(serde_json::Error),
+}
+
+impl CliError {
+  fn exit_code(&self) -> i32 {
+    match self {
+      CliError::InjectError(err) => inject_exit_code(err),
+      CliError::ProcessNotFoundError(_) => EXIT_NOT_FOUND,
+      CliError::ProcessListError(_) | CliError::AmbiguousProcessError { .. } | CliError::OutputError(_) => EXIT_FAILURE,
+    }
+  }
+
+  fn hint(&self) -> Option<&'static str> {
+    match self {
+      CliError::InjectError(err) => err.hint(),
+      CliError::ProcessNotFoundError(_) => {
+        Some("check the game is running, `injector list` shows what can be injected")
+      }
+      CliError::AmbiguousProcessError { .. } => Some("pick one with --pid"),
+      CliError::ProcessListError(_) | CliError::OutputError(_) => None,
+    }
+  }
+}
+
+impl fmt::Display for CliError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      CliError::InjectError(err) => err.fmt(f),
+      CliError::ProcessListError(err) => write!(f, "failed to list processes - {}", err),
+      CliError::ProcessNotFoundError(name) => write!(f, "no process named {}", name),
+      CliError::AmbiguousProcessError { name, pids } => {
+        write!(f, "{} processes named {} - {:?}", pids.len(), name, pids)
+      }
+      CliError::OutputError(err) => write!(f, "failed to write output - {}", err),
+    }
+  }
+}
+
+impl error::Error for CliError {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      CliError::InjectError(err) => Some(err),
+      CliError::ProcessListError(err) => Some(err),
+      CliError::OutputError(err) => Some(err),
+      CliError::ProcessNotFoundError(_) | CliError::AmbiguousProcessError { .. } => None,
+    }
+  }
+}
+
+impl From<InjectError> for CliError {
+  fn from(err: InjectError) -> Self {
+    CliError::InjectError(err)
+  }
+}
+
+impl From<serde_json::Error> for CliError {
+  fn from(err: serde_json::Error) -> Self {
+    CliError::OutputError(err)
+  }
+}
+
+type CliResult<T> = Result<T, CliError>;
+
+fn inject_exit_code(err: &InjectError) -> i32 {
+  match err {
+    InjectError::OpenProcessError { source, .. }
+    | InjectError::AllocMemoryError { source, .. }
+    | InjectError::CreateThreadError { source, .. }
+    | InjectError::ThreadControlError { source, .. }
+      if source.kind() == ErrorCode::AccessDenied =>
+    {
+      EXIT_ACCESS_DENIED
+    }
+    // OpenProcess fails with invalid parameter when there is no such pid
+    InjectError::OpenProcessError { source, .. } if source.kind() == ErrorCode::InvalidParameter => EXIT_NOT_FOUND,
+    InjectError::ModulePathError { .. } | InjectError::ModuleNotLoadedError { .. } => EXIT_NOT_FOUND,
+    InjectError::ArchMismatchError { .. } | InjectError::ImageArchError { .. } => EXIT_ARCH_MISMATCH,
+    InjectError::LoadLibraryError { .. }
+    | InjectError::ModuleMissingError { .. }
+    | InjectError::DllMainError { .. }
+    | InjectError::InitError { .. } => EXIT_LOAD_FAILED,
+    InjectError::ThreadTimeoutError { .. }
+    | InjectError::StartupTimeoutError { .. }
+    | InjectError::ChildNotFoundError { .. } => EXIT_TIMED_OUT,
+    _ => EXIT_FAILURE,
+  }
+}
+
+// everything the commands print, either as text or as json with --json
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+enum Output {
+  Processes(Vec<ProcessEntry>),
+  Modules(Vec<ModuleEntry>),
+  Injected(InjectedEntry),
+  Ejected(EjectedEntry),
+  Event(EventEntry),
+}
+
+#[derive(Debug, Serialize)]
+struct ProcessEntry {
+  pid: Pid,
+  parent_pid: Pid,
+  name: String,
+  // none if the process could not be opened, it cannot be injected either
+  arch: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ModuleEntry {
+  name: String,
+  path: PathBuf,
+  base: usize,
+  size: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct InjectedEntry {
+  pid: Pid,
+  path: PathBuf,
+  base: usize,
+}
+
+#[derive(Debug, Serialize)]
+struct EjectedEntry {
+  pid: Pid,
+  module: String,
+  shutdown: &'static str,
+}
+
+#[derive(Debug, Serialize)]
+struct EventEntry {
+  event: &'static str,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pid: Option<Pid>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  name: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  base: Option<usize>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ErrorEntry {
+  error: String,
+  hint: Option<&'static str>,
+  code: i32,
+}
+
+impl fmt::Display for Output {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Output::Processes(entries) => {
+        for entry in entries {
+          let arch = entry.arch.as_deref().unwrap_or("-");
+
+          writeln!(f, "{:>8} {:>8} {:<8} {}", entry.pid, entry.parent_pid, arch, entry.name)?;
+        }
+
+        Ok(())
+      }
+      Output::Modules(entries) => {
+        for entry in entries {
+          writeln!(f, "{:#018x} {:#10x} {:?}", entry.base, entry.size, entry.path)?;
+        }
+
+        Ok(())
+      }
+      Output::Injected(entry) => writeln!(f, "loaded {:?} at {:#x} (pid {})", entry.path, entry.base, entry.pid),
+      Output::Ejected(entry) => writeln!(f, "unloaded {} (shutdown {})", entry.module, entry.shutdown),
+      Output::Event(entry) => {
+        write!(f, "{}", entry.event)?;
+
+        if let Some(pid) = entry.pid {
+          write!(f, " pid {}", pid)?;
+        }
+
+        if let Some(name) = &entry.name {
+          write!(f, " {}", name)?;
+        }
+
+        if let Some(base) = entry.base {
+          write!(f, " at {:#x}", base)?;
+        }
+
+        if let Some(error) = &entry.error {
+          write!(f, " - {}", error)?;
+        }
+
+        writeln!(f)
+      }
+    }
+  }
+}
+
+fn print(output: &Output, json: bool) -> CliResult<()> {
+  if json {
+    println!("{}", serde_json::to_string(output)?);
+  } else {
+    print!("{}", output);
+  }
+
+  Ok(())
+}
+
+fn pid_arg() -> Arg<'static> {
+  Arg::new("pid")
+    .long("pid")
+    .takes_value(true)
+    .help("id of the target process")
+}
+
+fn name_arg() -> Arg<'static> {
+  Arg::new("name")
+    .long("name")
+    .takes_value(true)
+    .help("exe name of the target process, eg. th08.exe")
+}
+
+// exactly one of --pid and --name
+fn target_group() -> ArgGroup<'static> {
+  ArgGroup::new("target").args(&["pid", "name"]).required(true)
+}
+
+fn dll_arg() -> Arg<'static> {
+  Arg::new("dll")
+    .default_value(DEFAULT_MODULE_PATH)
+    .help("path of the dll to load")
+}
+
+fn timeout_arg(default: &'static str) -> Arg<'static> {
+  Arg::new("timeout")
+    .long("timeout")
+    .takes_value(true)
+    .default_value(default)
+    .help("milliseconds to wait before giving up")
+}
 
 fn main() {
-  println!("rawrxd");
+  let method_names = InjectionMethod::ALL.map(InjectionMethod::name);
+
+  let matches =
+    Command::new("injector")
+      .about("loads and unloads dlls in running processes")
+      .subcommand_required(true)
+      .arg_required_else_help(true)
+      .arg(Arg::new("json").long("json").global(true).help(
+        "print results and errors as json, one value per line: an array for list and modules, an object otherwise",
+      ))
+      .subcommand(
+        Command::new("list").about("list processes that can be injected").arg(
+          Arg::new("all")
+            .long("all")
+            .help("include processes that cannot be opened"),
+        ),
+      )
+      .subcommand(
+        Command::new("modules")
+          .about("list the modules loaded in a process")
+          .arg(pid_arg())
+          .arg(name_arg())
+          .group(target_group()),
+      )
+      .subcommand(
+        Command::new("inject")
+          .about("load a dll into a process")
+          .arg(pid_arg())
+          .arg(name_arg())
+          .group(target_group())
+          .arg(dll_arg())
+          .arg(
+            Arg::new("method")
+              .long("method")
+              .takes_value(true)
+              .possible_values(method_names)
+              .default_value(InjectionMethod::default().name())
+              .help("how to load the dll"),
+          ),
+      )
+      .subcommand(
+        Command::new("eject")
+          .about("shut down and unload a dll from a process")
+          .arg(pid_arg())
+          .arg(name_arg())
+          .group(target_group())
+          .arg(
+            Arg::new("module")
+              .long("module")
+              .takes_value(true)
+              .default_value(DEFAULT_MODULE_NAME)
+              .help("name of the loaded dll"),
+          )
+          .arg(timeout_arg(DEFAULT_TIMEOUT_MS)),
+      )
+      .subcommand(
+        Command::new("launch")
+          .about("start a game and load a dll into it before it runs")
+          .arg(
+            Arg::new("exe")
+              .required(true)
+              .help("path of the game, or of its launcher"),
+          )
+          .arg(dll_arg())
+          .arg(
+            Arg::new("child")
+              .long("child")
+              .takes_value(true)
+              .help("exe name of the game when exe is a launcher that starts it, eg. thcrap_loader"),
+          )
+          .arg(
+            Arg::new("cwd")
+              .long("cwd")
+              .takes_value(true)
+              .help("working directory of the game, defaults to ours"),
+          )
+          .arg(timeout_arg(DEFAULT_LAUNCH_TIMEOUT_MS))
+          .arg(
+            Arg::new("args")
+              .multiple_values(true)
+              .last(true)
+              .help("arguments passed to exe"),
+          ),
+      )
+      .subcommand(
+        Command::new("watch")
+          .about("wait for games to start and load a dll into each of them")
+          .arg(
+            Arg::new("name")
+              .long("name")
+              .takes_value(true)
+              .multiple_occurrences(true)
+              .required(true)
+              .help("exe name of a game to watch for, can be given more than once"),
+          )
+          .arg(dll_arg())
+          .arg(
+            Arg::new("interval")
+              .long("interval")
+              .takes_value(true)
+              .default_value(DEFAULT_POLL_INTERVAL_MS)
+              .help("milliseconds between checks for new games"),
+          )
+          .arg(
+            Arg::new("settle")
+              .long("settle")
+              .takes_value(true)
+              .default_value(DEFAULT_SETTLE_MS)
+              .help("milliseconds a new game gets to initialise before it is injected"),
+          )
+          .arg(timeout_arg(DEFAULT_LAUNCH_TIMEOUT_MS)),
+      )
+      .get_matches();
+
+  // subcommand_required
+  let (command, args) = matches.subcommand().unwrap();
+  let json = args.is_present("json");
+
+  let res = match command {
+    "list" => list(args),
+    "modules" => modules(args),
+    "inject" => inject_cmd(args),
+    "eject" => eject(args),
+    "launch" => launch(args),
+    "watch" => watch(args, json),
+    _ => unreachable!(),
+  }
+  .and_then(|output| match output {
+    Some(output) => print(&output, json),
+    None => Ok(()),
+  });
+
+  if let Err(err) = res {
+    report(&err, json);
+
+    process::exit(err.exit_code());
+  }
+}
+
+fn report(err: &CliError, json: bool) {
+  if json {
+    let entry = ErrorEntry {
+      error: err.to_string(),
+      hint: err.hint(),
+      code: err.exit_code(),
+    };
+
+    // nothing left to report a failure to print the error with
+    if let Ok(line) = serde_json::to_string(&entry) {
+      println!("{}", line);
+
+      return;
+    }
+  }
+
+  eprintln!("{}", err);
+
+  if let Some(hint) = err.hint() {
+    eprintln!("hint: {}", hint);
+  }
+}
+
+// the pid given with --pid, or that of the only process called --name
+fn target_pid(args: &ArgMatches) -> CliResult<Pid> {
+  if args.is_present("pid") {
+    return Ok(args.value_of_t_or_exit("pid"));
+  }
+
+  // target_group requires one of them
+  let name = args.value_of("name").unwrap();
+
+  let pids = processes()
+    .and_then(|processes| processes.collect::<Result<Vec<_>, _>>())
+    .map_err(CliError::ProcessListError)?
+    .into_iter()
+    .filter(|info| info.is_named(name))
+    .map(|info| info.pid)
+    .collect::<Vec<_>>();
+
+  match pids[..] {
+    [] => Err(CliError::ProcessNotFoundError(name.to_string())),
+    [pid] => Ok(pid),
+    _ => Err(CliError::AmbiguousProcessError {
+      name: name.to_string(),
+      pids,
+    }),
+  }
+}
+
+fn millis(args: &ArgMatches, name: &str) -> Duration {
+  Duration::from_millis(args.value_of_t_or_exit(name))
+}
+
+// the name a dll shows up as once loaded, to tell if it is already there
+fn module_name(dll: &str) -> String {
+  Path::new(dll)
+    .file_name()
+    .map(|name| name.to_string_lossy().into_owned())
+    .unwrap_or_else(|| dll.to_string())
+}
+
+fn list(args: &ArgMatches) -> CliResult<Option<Output>> {
+  let all = args.is_present("all");
+  let mut entries = Vec::new();
+
+  for info in processes().map_err(CliError::ProcessListError)? {
+    // an entry that cannot be read ends the walk, everything listed before it is still worth printing
+    let info = match info {
+      Ok(info) => info,
+      Err(_) => continue,
+    };
+
+    let arch = WinApiProcess::new(info.pid, ProcessAccess::QUERY_LIMITED_INFORMATION)
+      .ok()
+      .and_then(|process| process.arch().ok());
+
+    if arch.is_none() && !all {
+      continue;
+    }
+
+    entries.push(ProcessEntry {
+      pid: info.pid,
+      parent_pid: info.parent_pid,
+      name: info.exe_name,
+      arch: arch.map(|arch| arch.to_string()),
+    });
+  }
+
+  Ok(Some(Output::Processes(entries)))
+}
+
+fn modules(args: &ArgMatches) -> CliResult<Option<Output>> {
+  let pid = target_pid(args)?;
+
+  let process = WinApiProcess::new(pid, ProcessAccess::QUERY_LIMITED_INFORMATION)
+    .map_err(|source| InjectError::OpenProcessError { pid, source })?;
+
+  let entries = process
+    .modules()
+    .map_err(|source| InjectError::QueryProcessError { pid, source })?
+    .into_iter()
+    .map(|module| ModuleEntry {
+      name: module.name,
+      path: module.path,
+      base: module.base,
+      size: module.size,
+    })
+    .collect();
+
+  Ok(Some(Output::Modules(entries)))
+}
+
+fn inject_cmd(args: &ArgMatches) -> CliResult<Option<Output>> {
+  let pid = target_pid(args)?;
+  let method = args.value_of_t_or_exit("method");
+  // cannot fail, dll has a default value
+  let dll = args.value_of("dll").unwrap();
+
+  let module = inject(dll, pid, method, None)?;
+
+  Ok(Some(Output::Injected(InjectedEntry {
+    pid,
+    path: module.path,
+    base: module.base,
+  })))
+}
+
+fn eject(args: &ArgMatches) -> CliResult<Option<Output>> {
+  let pid = target_pid(args)?;
+  let timeout = millis(args, "timeout");
+  // cannot fail, module has a default value
+  let module = args.value_of("module").unwrap();
+
+  let shutdown = match eject_module(module, pid, timeout)? {
+    Shutdown::Completed => "completed",
+    Shutdown::NotExported => "not exported",
+    Shutdown::Failed => "failed",
+    Shutdown::TimedOut => "timed out",
+  };
+
+  Ok(Some(Output::Ejected(EjectedEntry {
+    pid,
+    module: module.to_string(),
+    shutdown,
+  })))
+}
+
+fn launch(args: &ArgMatches) -> CliResult<Option<Output>> {
+  // cannot fail, exe is required and dll has a default value
+  let exe = args.value_of("exe").unwrap();
+  let dll = args.value_of("dll").unwrap();
+
+  let options = LaunchOptions {
+    exe: PathBuf::from(exe),
+    args: args
+      .values_of("args")
+      .map(|values| values.map(OsString::from).collect())
+      .unwrap_or_default(),
+    cwd: args.value_of("cwd").map(PathBuf::from),
+    env: Vec::new(),
+    child: args.value_of("child").map(str::to_string),
+    timeout: millis(args, "timeout"),
+    init: None,
+  };
+
+  let launched = launch_and_inject(dll, &options)?;
+
+  Ok(Some(Output::Injected(InjectedEntry {
+    pid: launched.pid,
+    path: launched.module.path,
+    base: launched.module.base,
+  })))
+}
+
+// runs until killed, printing events as they happen
+fn watch(args: &ArgMatches, json: bool) -> CliResult<Option<Output>> {
+  // cannot fail, name is required and dll has a default value
+  let exe_names = args.values_of("name").unwrap().map(str::to_string).collect();
+  let dll = args.value_of("dll").unwrap();
+
+  let mut watcher = Watcher::new(WatchOptions {
+    exe_names,
+    module_path: dll.to_string(),
+    module_name: module_name(dll),
+    poll_interval: millis(args, "interval"),
+    settle: millis(args, "settle"),
+    startup_timeout: millis(args, "timeout"),
+    init: None,
+  });
+
+  let stop = AtomicBool::new(false);
+  let mut res = Ok(());
+
+  watcher
+    .run(&stop, |event| {
+      let entry = match event {
+        WatchEvent::Found { pid, exe_name } => EventEntry {
+          event: "found",
+          pid: Some(pid),
+          name: Some(exe_name),
+          base: None,
+          error: None,
+        },
+        WatchEvent::Injected { pid, module } => EventEntry {
+          event: "injected",
+          pid: Some(pid),
+          name: Some(module.path.display().to_string()),
+          base: Some(module.base),
+          error: None,
+        },
+        WatchEvent::AlreadyLoaded { pid, base } => EventEntry {
+          event: "already loaded",
+          pid: Some(pid),
+          name: None,
+          base: Some(base),
+          error: None,
+        },
+        WatchEvent::Failed { pid, err } => EventEntry {
+          event: "failed",
+          pid: Some(pid),
+          name: None,
+          base: None,
+          error: Some(match err.hint() {
+            Some(hint) => format!("{} (hint: {})", err, hint),
+            None => err.to_string(),
+          }),
+        },
+        WatchEvent::Exited { pid } => EventEntry {
+          event: "exited",
+          pid: Some(pid),
+          name: None,
+          base: None,
+          error: None,
+        },
+        WatchEvent::ProcessSkipped { err } => EventEntry {
+          event: "skipped",
+          pid: None,
+          name: None,
+          base: None,
+          error: Some(err.to_string()),
+        },
+        WatchEvent::SnapshotFailed { err } => EventEntry {
+          event: "snapshot failed",
+          pid: None,
+          name: None,
+          base: None,
+          error: Some(err.to_string()),
+        },
+      };
+
+      if res.is_ok() {
+        res = print(&Output::Event(entry), json);
+      }
+    })
+    .map_err(CliError::ProcessListError)?;
+
+  res.map(|_| None)
 }
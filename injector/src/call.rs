@@ -0,0 +1,242 @@
+use std::{
+  ffi::{self, OsStr},
+  os::windows::prelude::OsStrExt,
+  time::Duration,
+};
+
+use win::{
+  arch::Arch,
+  error::{util::get_last_error, WinApiCodeResult, WinApiErrorCode},
+  memory::{region::Protection, ProcessMemory},
+  process::{remote::RemoteAllocation, WinApiProcess},
+};
+use winapi::um::libloaderapi::{GetModuleHandleA, GetProcAddress};
+
+use crate::{
+  error::{InjectError, InjectResult},
+  resolve::{remote_export_addr, ResolveError},
+  stub::{call_stub, RESULT_LAST_ERROR_OFFSET, RESULT_RETURN_OFFSET, RESULT_SIZE},
+};
+
+const KERNEL_MODULE_NAME: &str = "Kernel32";
+const KERNEL_MODULE_FILE_NAME: &str = "kernel32.dll";
+const LOADLIB_PROC_NAME: &str = "LoadLibraryW";
+const GET_LAST_ERROR_PROC_NAME: &str = "GetLastError";
+const FREE_LIBRARY_PROC_NAME: &str = "FreeLibrary";
+
+// a target stuck under the loader lock would otherwise hang whoever is injecting, the watcher included
+// generous, as LoadLibraryW also loads the module's dependencies and runs every DllMain on the way
+const LOAD_LIBRARY_TIMEOUT: Duration = Duration::from_secs(30);
+
+// kernel32 functions as mapped in the target
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct KernelProcs {
+  pub load_library: usize,
+  pub get_last_error: usize,
+  pub free_library: usize,
+}
+
+impl KernelProcs {
+  // our own kernel32 is only valid in targets of the same bitness, anything else has its own kernel32 we need to look at
+  pub(crate) fn resolve(process: &WinApiProcess, target: Arch) -> Result<Self, ResolveError> {
+    if target == Arch::current() {
+      let procs = Self {
+        load_library: get_local_proc_addr(LOADLIB_PROC_NAME).map_err(ResolveError::LocalLookupError)?,
+        get_last_error: get_local_proc_addr(GET_LAST_ERROR_PROC_NAME).map_err(ResolveError::LocalLookupError)?,
+        free_library: get_local_proc_addr(FREE_LIBRARY_PROC_NAME).map_err(ResolveError::LocalLookupError)?,
+      };
+
+      return Ok(procs);
+    }
+
+    let modules = process.modules().map_err(ResolveError::ModuleEnumError)?;
+
+    Ok(Self {
+      load_library: remote_export_addr(&modules, target, KERNEL_MODULE_FILE_NAME, LOADLIB_PROC_NAME)?,
+      get_last_error: remote_export_addr(&modules, target, KERNEL_MODULE_FILE_NAME, GET_LAST_ERROR_PROC_NAME)?,
+      free_library: remote_export_addr(&modules, target, KERNEL_MODULE_FILE_NAME, FREE_LIBRARY_PROC_NAME)?,
+    })
+  }
+}
+
+// a single kernel32 function as mapped in the target, for the ones only some callers need
+// forwarders to ntdll are followed, eg. RtlAddFunctionTable
+pub(crate) fn resolve_kernel_proc(
+  process: &WinApiProcess,
+  target: Arch,
+  proc_name: &str,
+) -> Result<usize, ResolveError> {
+  if target == Arch::current() {
+    return get_local_proc_addr(proc_name).map_err(ResolveError::LocalLookupError);
+  }
+
+  let modules = process.modules().map_err(ResolveError::ModuleEnumError)?;
+
+  remote_export_addr(&modules, target, KERNEL_MODULE_FILE_NAME, proc_name)
+}
+
+// what a remote call returned, and GetLastError straight after it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct RemoteCall {
+  pub ret: u64,
+  pub last_error: u32,
+}
+
+// LoadLibraryW(name) in the target, returning the module handle
+// a call still running after LOAD_LIBRARY_TIMEOUT fails with ThreadTimeoutError, and the thread is left to it
+pub(crate) fn load_library_remote(
+  process: &WinApiProcess,
+  target: Arch,
+  procs: &KernelProcs,
+  name: &OsStr,
+) -> InjectResult<usize> {
+  let pid = process.pid();
+  let name_mem = write_wide_str(process, name)?;
+
+  let res = match call_remote(
+    process,
+    target,
+    procs,
+    procs.load_library,
+    name_mem.addr(),
+    Some(LOAD_LIBRARY_TIMEOUT),
+  ) {
+    Ok(res) => res,
+    Err(err @ (InjectError::ThreadTimeoutError { .. } | InjectError::WaitThreadError { .. })) => {
+      // the thread may still be reading the name
+      name_mem.leak();
+
+      return Err(err);
+    }
+    Err(err) => return Err(err),
+  };
+
+  if res.ret == 0 {
+    return Err(InjectError::LoadLibraryError {
+      pid,
+      source: WinApiErrorCode::new(res.last_error, "LoadLibraryW"),
+    });
+  }
+
+  Ok(res.ret as usize)
+}
+
+// copies a nul terminated utf-16 string into the target, eg. the argument for LoadLibraryW
+pub(crate) fn write_wide_str<'a>(process: &'a WinApiProcess, str: &OsStr) -> InjectResult<RemoteAllocation<'a>> {
+  let pid = process.pid();
+
+  let buffer = str
+    .encode_wide()
+    .chain(Some(0))
+    .flat_map(u16::to_le_bytes)
+    .collect::<Vec<_>>();
+
+  let str_mem = process
+    .alloc(buffer.len(), Protection::READWRITE)
+    .map_err(|source| InjectError::AllocMemoryError { pid, source })?;
+
+  process
+    .write_all(str_mem.addr(), &buffer)
+    .map_err(|source| InjectError::WriteMemoryError { pid, source })?;
+
+  Ok(str_mem)
+}
+
+// runs func(arg) on a new thread in the target and waits for it to return, or for timeout
+// func must be a single argument stdcall function (or the x64 calling convention), eg. LoadLibraryW
+pub(crate) fn call_remote(
+  process: &WinApiProcess,
+  target: Arch,
+  procs: &KernelProcs,
+  func: usize,
+  arg: usize,
+  timeout: Option<Duration>,
+) -> InjectResult<RemoteCall> {
+  run_stub(
+    process,
+    |result| call_stub(target, func, arg, procs.get_last_error, result),
+    timeout,
+  )
+}
+
+// runs a stub built by build_stub(result block address) on a new thread in the target, and reads back its results
+// build_stub is called twice, once to size the allocation, so it must always produce the same length
+pub(crate) fn run_stub(
+  process: &WinApiProcess,
+  build_stub: impl Fn(usize) -> Vec<u8>,
+  timeout: Option<Duration>,
+) -> InjectResult<RemoteCall> {
+  let pid = process.pid();
+  let stub_size = build_stub(0).len();
+
+  // VirtualAllocEx zeroes the result block for us
+  let stub_mem = process
+    .alloc(RESULT_SIZE + stub_size, Protection::EXECUTE_READWRITE)
+    .map_err(|source| InjectError::AllocMemoryError { pid, source })?;
+
+  let result = stub_mem.addr();
+  let code_addr = result + RESULT_SIZE;
+  let code = build_stub(result);
+
+  process
+    .write_all(code_addr, &code)
+    .map_err(|source| InjectError::WriteMemoryError { pid, source })?;
+
+  let thread = unsafe {
+    // safety
+    // - the stub is a complete thread proc built for this exact address
+    process.create_remote_thread(code_addr, 0)
+  }
+  .map_err(|source| InjectError::CreateThreadError { pid, source })?;
+
+  match thread.wait(timeout) {
+    Ok(Some(_)) => {}
+    Ok(None) => {
+      // the thread is still running the stub, freeing it now could crash the target
+      stub_mem.leak();
+
+      return Err(InjectError::ThreadTimeoutError { pid });
+    }
+    Err(source) => {
+      stub_mem.leak();
+
+      return Err(InjectError::WaitThreadError { pid, source });
+    }
+  }
+
+  let read_err = |source| InjectError::ReadMemoryError { pid, source };
+
+  Ok(RemoteCall {
+    ret: process.read(result + RESULT_RETURN_OFFSET).map_err(read_err)?,
+    last_error: process.read(result + RESULT_LAST_ERROR_OFFSET).map_err(read_err)?,
+  })
+}
+
+// kernel32 is mapped at the same address in every process of the same bitness, so our own addresses work in the target
+fn get_local_proc_addr(proc_name: &str) -> WinApiCodeResult<usize> {
+  let kernel = unsafe {
+    let kernel_cstr = ffi::CString::new(KERNEL_MODULE_NAME).unwrap();
+
+    GetModuleHandleA(kernel_cstr.as_ptr())
+  };
+
+  if kernel.is_null() {
+    let err = get_last_error("GetModuleHandleA");
+
+    return Err(err);
+  }
+
+  let proc = unsafe {
+    let proc_cstr = ffi::CString::new(proc_name).unwrap();
+
+    GetProcAddress(kernel, proc_cstr.as_ptr())
+  };
+
+  if proc.is_null() {
+    let err = get_last_error("GetProcAddress");
+
+    return Err(err);
+  }
+
+  Ok(proc as usize)
+}
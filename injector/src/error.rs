@@ -0,0 +1,287 @@
+use std::{error, fmt, io, path::PathBuf};
+
+use win::{
+  arch::Arch,
+  error::{code::ErrorCode, WinApiError, WinApiErrorCode},
+  pe::PeError,
+  wintype::Pid,
+};
+
+use crate::{resolve::ResolveError, InjectionMethod};
+
+pub type InjectResult<T> = Result<T, InjectError>;
+
+// which stage of an injection failed, and why
+#[derive(Debug)]
+pub enum InjectError {
+  ModulePathError { path: PathBuf, source: io::Error },
+  LaunchError { exe: PathBuf, source: WinApiErrorCode },
+  ModuleImageError { path: PathBuf, source: PeError },
+  OpenProcessError { pid: Pid, source: WinApiErrorCode },
+  QueryProcessError { pid: Pid, source: WinApiError },
+  ArchMismatchError { pid: Pid, target: Arch },
+  // the module being mapped is not the same arch as the target
+  ImageArchError { pid: Pid, found: Arch },
+  // the dll that sets up a mapped module's thread_local data could not be written, see tls_carrier
+  TlsCarrierError { path: PathBuf, source: io::Error },
+  ResolveProcError { pid: Pid, source: ResolveError },
+  AllocMemoryError { pid: Pid, source: WinApiErrorCode },
+  WriteMemoryError { pid: Pid, source: WinApiError },
+  ReadMemoryError { pid: Pid, source: WinApiError },
+  ProtectMemoryError { pid: Pid, source: WinApiErrorCode },
+  ThreadControlError { pid: Pid, source: WinApiErrorCode },
+  // the target has no threads left to hijack
+  NoThreadError { pid: Pid },
+  CreateThreadError { pid: Pid, source: WinApiErrorCode },
+  WaitThreadError { pid: Pid, source: WinApiErrorCode },
+  // the remote thread did not finish in time and was left running
+  ThreadTimeoutError { pid: Pid },
+  // LoadLibraryW ran in the target but returned null, source is GetLastError in the target
+  LoadLibraryError { pid: Pid, source: WinApiErrorCode },
+  VerifyModuleError { pid: Pid, source: WinApiError },
+  // LoadLibraryW returned a handle that is not in the target's module list
+  ModuleMissingError { pid: Pid, base: usize },
+  // RtlAddFunctionTable refused the manually mapped module's unwind info
+  FunctionTableError { pid: Pid },
+  // the manually mapped module's DllMain returned false
+  DllMainError { pid: Pid },
+  InitExportError { pid: Pid, source: ResolveError },
+  // the module's init export returned false for the config it was given
+  InitError { pid: Pid },
+  ModuleNotLoadedError { pid: Pid, name: String },
+  FreeLibraryError { pid: Pid, source: WinApiErrorCode },
+  // still mapped after FreeLibrary dropped every reference we could
+  UnloadError { pid: Pid, base: usize },
+  // the game did not get far enough through startup in time
+  StartupTimeoutError { pid: Pid },
+  // the launcher did not start the game in time, pid is the launcher's
+  ChildNotFoundError { pid: Pid, name: String },
+}
+
+impl InjectError {
+  pub fn pid(&self) -> Option<Pid> {
+    match self {
+      InjectError::ModulePathError { .. }
+      | InjectError::LaunchError { .. }
+      | InjectError::ModuleImageError { .. }
+      | InjectError::TlsCarrierError { .. } => None,
+      InjectError::OpenProcessError { pid, .. }
+      | InjectError::QueryProcessError { pid, .. }
+      | InjectError::ArchMismatchError { pid, .. }
+      | InjectError::ImageArchError { pid, .. }
+      | InjectError::ResolveProcError { pid, .. }
+      | InjectError::AllocMemoryError { pid, .. }
+      | InjectError::WriteMemoryError { pid, .. }
+      | InjectError::ReadMemoryError { pid, .. }
+      | InjectError::ProtectMemoryError { pid, .. }
+      | InjectError::ThreadControlError { pid, .. }
+      | InjectError::NoThreadError { pid }
+      | InjectError::CreateThreadError { pid, .. }
+      | InjectError::WaitThreadError { pid, .. }
+      | InjectError::ThreadTimeoutError { pid }
+      | InjectError::LoadLibraryError { pid, .. }
+      | InjectError::VerifyModuleError { pid, .. }
+      | InjectError::ModuleMissingError { pid, .. }
+      | InjectError::FunctionTableError { pid }
+      | InjectError::DllMainError { pid }
+      | InjectError::InitExportError { pid, .. }
+      | InjectError::InitError { pid }
+      | InjectError::ModuleNotLoadedError { pid, .. }
+      | InjectError::FreeLibraryError { pid, .. }
+      | InjectError::UnloadError { pid, .. }
+      | InjectError::StartupTimeoutError { pid }
+      | InjectError::ChildNotFoundError { pid, .. } => Some(*pid),
+    }
+  }
+
+  // something the user can do about it, if we know of anything
+  pub fn hint(&self) -> Option<&'static str> {
+    match self {
+      InjectError::ModulePathError { .. } => Some("check that the dll exists and the path is correct"),
+      InjectError::LaunchError { source, .. } if source.kind() == ErrorCode::ElevationRequired => {
+        Some("the game asks to run elevated, run as administrator")
+      }
+      InjectError::LaunchError { source, .. }
+        if matches!(source.kind(), ErrorCode::FileNotFound | ErrorCode::PathNotFound) =>
+      {
+        Some("check the game path and working directory")
+      }
+      InjectError::ChildNotFoundError { .. } => Some("check the child exe name matches what the launcher starts"),
+      InjectError::OpenProcessError { source, .. }
+      | InjectError::AllocMemoryError { source, .. }
+      | InjectError::CreateThreadError { source, .. }
+        if source.kind() == ErrorCode::AccessDenied =>
+      {
+        Some("run as administrator, the game may be running elevated or protected by anti-cheat")
+      }
+      InjectError::OpenProcessError { source, .. } if source.kind() == ErrorCode::InvalidParameter => {
+        Some("the process has exited")
+      }
+      // a 32 bit injector cannot create threads in a 64 bit process, the other way round is fine
+      InjectError::ArchMismatchError { .. } => Some("target is 64 bit, use a 64 bit build of the injector"),
+      InjectError::ImageArchError { .. } => {
+        Some("the dll does not match the target's bitness, build it for the same arch as the game")
+      }
+      InjectError::TlsCarrierError { .. } => Some("check that the temp directory exists and is writable"),
+      InjectError::DllMainError { .. } => Some("the dll's DllMain failed, check its log"),
+      InjectError::InitExportError {
+        source: ResolveError::ExportNotFoundError { .. },
+        ..
+      } => Some("the dll does not take a config, rebuild it or inject it without one"),
+      InjectError::InitError { .. } => Some("the dll could not read its config, check its log"),
+      InjectError::ResolveProcError {
+        source: ResolveError::ModuleNotFoundError(_),
+        ..
+      } => Some("the target has not finished starting up, try again once the game window is open"),
+      InjectError::LoadLibraryError { source, .. } => match source.kind() {
+        ErrorCode::ModNotFound => Some("the dll or one of its dependencies was not found, put them next to the game"),
+        ErrorCode::BadExeFormat | ErrorCode::ExeMachineTypeMismatch => {
+          Some("the dll does not match the target's bitness, build it for the same arch as the game")
+        }
+        ErrorCode::DllInitFailed => Some("the dll's DllMain failed, check its log"),
+        _ => None,
+      },
+      InjectError::UnloadError { .. } => {
+        Some("something in the target still holds the dll, restart the game to get rid of it")
+      }
+      _ => None,
+    }
+  }
+}
+
+impl fmt::Display for InjectError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      InjectError::ModulePathError { path, source } => write!(f, "invalid module path {:?} - {}", path, source),
+      InjectError::LaunchError { exe, source } => write!(f, "failed to launch {:?} - {}", exe, source),
+      InjectError::ModuleImageError { path, source } => write!(f, "invalid module {:?} - {}", path, source),
+      InjectError::OpenProcessError { pid, source } => write!(f, "failed to open process {} - {}", pid, source),
+      InjectError::QueryProcessError { pid, source } => write!(f, "failed to query process {} - {}", pid, source),
+      InjectError::ArchMismatchError { pid, target } => {
+        write!(f, "process {} is {}, the injector is {}", pid, target, Arch::current())
+      }
+      InjectError::ImageArchError { pid, found } => {
+        write!(f, "module is {}, process {} is not", found, pid)
+      }
+      InjectError::TlsCarrierError { path, source } => {
+        write!(f, "failed to write tls carrier {:?} - {}", path, source)
+      }
+      InjectError::ResolveProcError { pid, source } => {
+        write!(
+          f,
+          "failed to resolve kernel32 functions for process {} - {}",
+          pid, source
+        )
+      }
+      InjectError::AllocMemoryError { pid, source } => {
+        write!(f, "failed to allocate memory in process {} - {}", pid, source)
+      }
+      InjectError::WriteMemoryError { pid, source } => {
+        write!(f, "failed to write memory in process {} - {}", pid, source)
+      }
+      InjectError::ReadMemoryError { pid, source } => {
+        write!(f, "failed to read memory in process {} - {}", pid, source)
+      }
+      InjectError::ProtectMemoryError { pid, source } => {
+        write!(f, "failed to change memory protection in process {} - {}", pid, source)
+      }
+      InjectError::ThreadControlError { pid, source } => {
+        write!(f, "failed to control thread of process {} - {}", pid, source)
+      }
+      InjectError::NoThreadError { pid } => write!(f, "process {} has no threads to hijack", pid),
+      InjectError::CreateThreadError { pid, source } => {
+        write!(f, "failed to create remote thread in process {} - {}", pid, source)
+      }
+      InjectError::WaitThreadError { pid, source } => {
+        write!(f, "failed to wait for remote thread in process {} - {}", pid, source)
+      }
+      InjectError::ThreadTimeoutError { pid } => write!(f, "remote thread in process {} timed out", pid),
+      InjectError::LoadLibraryError { pid, source } => {
+        write!(f, "failed to load module in process {} - {}", pid, source)
+      }
+      InjectError::VerifyModuleError { pid, source } => {
+        write!(f, "failed to verify module in process {} - {}", pid, source)
+      }
+      InjectError::ModuleMissingError { pid, base } => {
+        write!(f, "module at {:#x} is not loaded in process {}", base, pid)
+      }
+      InjectError::FunctionTableError { pid } => {
+        write!(f, "failed to register unwind info in process {}", pid)
+      }
+      InjectError::DllMainError { pid } => write!(f, "DllMain failed in process {}", pid),
+      InjectError::ModuleNotLoadedError { pid, name } => write!(f, "{} is not loaded in process {}", name, pid),
+      InjectError::InitExportError { pid, source } => {
+        write!(
+          f,
+          "failed to find init export of module in process {} - {}",
+          pid, source
+        )
+      }
+      InjectError::InitError { pid } => write!(f, "module in process {} rejected its config", pid),
+      InjectError::FreeLibraryError { pid, source } => {
+        write!(f, "failed to unload module in process {} - {}", pid, source)
+      }
+      InjectError::UnloadError { pid, base } => {
+        write!(f, "module at {:#x} is still loaded in process {}", base, pid)
+      }
+      InjectError::StartupTimeoutError { pid } => write!(f, "process {} did not finish starting up in time", pid),
+      InjectError::ChildNotFoundError { pid, name } => {
+        write!(f, "launcher {} did not start {} in time", pid, name)
+      }
+    }
+  }
+}
+
+impl error::Error for InjectError {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      InjectError::ModulePathError { source, .. } | InjectError::TlsCarrierError { source, .. } => Some(source),
+      InjectError::LaunchError { source, .. }
+      | InjectError::OpenProcessError { source, .. }
+      | InjectError::AllocMemoryError { source, .. }
+      | InjectError::ProtectMemoryError { source, .. }
+      | InjectError::ThreadControlError { source, .. }
+      | InjectError::CreateThreadError { source, .. }
+      | InjectError::WaitThreadError { source, .. }
+      | InjectError::LoadLibraryError { source, .. }
+      | InjectError::FreeLibraryError { source, .. } => Some(source),
+      InjectError::QueryProcessError { source, .. }
+      | InjectError::WriteMemoryError { source, .. }
+      | InjectError::ReadMemoryError { source, .. }
+      | InjectError::VerifyModuleError { source, .. } => Some(source),
+      InjectError::ResolveProcError { source, .. } | InjectError::InitExportError { source, .. } => Some(source),
+      InjectError::ModuleImageError { source, .. } => Some(source),
+      InjectError::ArchMismatchError { .. }
+      | InjectError::ImageArchError { .. }
+      | InjectError::FunctionTableError { .. }
+      | InjectError::DllMainError { .. }
+      | InjectError::InitError { .. }
+      | InjectError::ThreadTimeoutError { .. }
+      | InjectError::NoThreadError { .. }
+      | InjectError::ModuleMissingError { .. }
+      | InjectError::ModuleNotLoadedError { .. }
+      | InjectError::UnloadError { .. }
+      | InjectError::StartupTimeoutError { .. }
+      | InjectError::ChildNotFoundError { .. } => None,
+    }
+  }
+}
+
+// an injection method name that is not one of InjectionMethod::ALL
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseMethodError(pub String);
+
+impl fmt::Display for ParseMethodError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let names = InjectionMethod::ALL.map(InjectionMethod::name);
+
+    write!(
+      f,
+      "unknown injection method {:?}, expected one of {}",
+      self.0,
+      names.join(", ")
+    )
+  }
+}
+
+impl error::Error for ParseMethodError {}
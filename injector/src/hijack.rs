@@ -0,0 +1,216 @@
+use std::{
+  fs,
+  thread::sleep,
+  time::{Duration, Instant},
+};
+
+use win::{
+  arch::Arch,
+  context::Register,
+  error::{WinApiError, WinApiErrorCode},
+  memory::{region::Protection, ProcessMemory},
+  process::WinApiProcess,
+  thread::{access::ThreadAccess, WinApiThread},
+  wintype::Pid,
+};
+
+use crate::{
+  call::{write_wide_str, KernelProcs},
+  open_target,
+  stub::{hijack_stub, RESULT_DONE_OFFSET, RESULT_LAST_ERROR_OFFSET, RESULT_RETURN_OFFSET, RESULT_SIZE},
+  verify_module, InjectError, InjectResult, RemoteModule,
+};
+
+const HIJACK_ACCESS: ThreadAccess = ThreadAccess::SUSPEND_RESUME
+  .union(ThreadAccess::GET_CONTEXT)
+  .union(ThreadAccess::SET_CONTEXT);
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// loads module_path with LoadLibraryW on one of the target's existing threads instead of a new one, for targets that
+// watch for or block CreateRemoteThread
+// the thread is suspended, pointed at a stub that calls LoadLibraryW and jumps back to where it was, and resumed
+// the stub only runs once the thread is next scheduled, so threads blocked on a wait can hold it up until timeout
+pub fn hijack_thread(module_path: &str, pid: Pid, timeout: Duration) -> InjectResult<RemoteModule> {
+  let path = fs::canonicalize(module_path).map_err(|source| InjectError::ModulePathError {
+    path: module_path.into(),
+    source,
+  })?;
+
+  let deadline = Instant::now() + timeout;
+
+  let (process, target) = open_target(pid)?;
+
+  let procs = KernelProcs::resolve(&process, target).map_err(|source| InjectError::ResolveProcError { pid, source })?;
+
+  let name_mem = write_wide_str(&process, path.as_os_str())?;
+
+  let build_stub = |return_ip, result| {
+    hijack_stub(
+      target,
+      return_ip,
+      procs.load_library,
+      name_mem.addr(),
+      procs.get_last_error,
+      result,
+    )
+  };
+
+  // VirtualAllocEx zeroes the result block for us
+  let stub_size = build_stub(0, 0).len();
+
+  let stub_mem = process
+    .alloc(RESULT_SIZE + stub_size, Protection::EXECUTE_READWRITE)
+    .map_err(|source| InjectError::AllocMemoryError { pid, source })?;
+
+  let result = stub_mem.addr();
+  let code_addr = result + RESULT_SIZE;
+
+  let thread = open_hijack_thread(&process)?;
+
+  let thread_err = |source| InjectError::ThreadControlError { pid, source };
+
+  thread.suspend().map_err(thread_err)?;
+
+  let redirected = redirect(&process, &thread, target, code_addr, |return_ip| {
+    build_stub(return_ip, result)
+  });
+
+  // resumed either way, a thread left suspended could hang the target
+  let resumed = thread.resume();
+
+  redirected?;
+
+  if let Err(source) = resumed {
+    // the thread is already pointed at the stub and will run it if anything else resumes it
+    stub_mem.leak();
+    name_mem.leak();
+
+    return Err(thread_err(source));
+  }
+
+  let read_err = |source| InjectError::ReadMemoryError { pid, source };
+
+  loop {
+    let done: u32 = process.read(result + RESULT_DONE_OFFSET).map_err(read_err)?;
+
+    if done != 0 {
+      break;
+    }
+
+    if Instant::now() >= deadline {
+      // the thread can still wake up and run the stub at any point
+      stub_mem.leak();
+      name_mem.leak();
+
+      return Err(InjectError::ThreadTimeoutError { pid });
+    }
+
+    sleep(POLL_INTERVAL);
+  }
+
+  let ret: u64 = process.read(result + RESULT_RETURN_OFFSET).map_err(read_err)?;
+  let last_error: u32 = process.read(result + RESULT_LAST_ERROR_OFFSET).map_err(read_err)?;
+
+  // the done flag is set a few instructions before the stub returns, so it is only safe to free once the thread is out
+  if !left_stub(&thread, target, code_addr, stub_size, deadline) {
+    stub_mem.leak();
+  }
+
+  if ret == 0 {
+    return Err(InjectError::LoadLibraryError {
+      pid,
+      source: WinApiErrorCode::new(last_error, "LoadLibraryW"),
+    });
+  }
+
+  verify_module(&process, ret as usize)
+}
+
+// the first thread we are allowed to hijack, toolhelp lists the main thread first which is usually the busiest
+fn open_hijack_thread(process: &WinApiProcess) -> InjectResult<WinApiThread> {
+  let pid = process.pid();
+
+  let thread_ids = process
+    .thread_ids()
+    .map_err(|source| InjectError::QueryProcessError { pid, source })?;
+
+  let mut last_err = None;
+
+  for id in thread_ids {
+    match WinApiThread::open(id, HIJACK_ACCESS) {
+      Ok(thread) => return Ok(thread),
+      Err(err) => last_err = Some(err),
+    }
+  }
+
+  match last_err {
+    Some(source) => Err(InjectError::ThreadControlError { pid, source }),
+    None => Err(InjectError::NoThreadError { pid }),
+  }
+}
+
+// writes the stub and points the suspended thread at it
+fn redirect(
+  process: &WinApiProcess,
+  thread: &WinApiThread,
+  target: Arch,
+  code_addr: usize,
+  build_stub: impl Fn(usize) -> Vec<u8>,
+) -> InjectResult<()> {
+  let pid = process.pid();
+  let thread_err = |source| InjectError::ThreadControlError { pid, source };
+
+  let mut context = thread.context(target).map_err(thread_err)?;
+  let code = build_stub(context.reg(Register::Ip));
+
+  process
+    .write_all(code_addr, &code)
+    .map_err(|source| InjectError::WriteMemoryError { pid, source })?;
+
+  process
+    .flush_instruction_cache(code_addr, code.len())
+    .map_err(|source| InjectError::WriteMemoryError {
+      pid,
+      source: WinApiError::from(source),
+    })?;
+
+  context.set_reg(Register::Ip, code_addr);
+
+  unsafe {
+    // safety
+    // - the thread is suspended and the context was just read from it
+    // - the stub preserves every register it touches and returns to the original ip
+    thread.set_context(&context)
+  }
+  .map_err(thread_err)
+}
+
+// waits for the thread to run past the end of the stub, false if it could not be confirmed before deadline
+fn left_stub(thread: &WinApiThread, target: Arch, code_addr: usize, code_size: usize, deadline: Instant) -> bool {
+  let code = code_addr..code_addr + code_size;
+
+  loop {
+    if thread.suspend().is_err() {
+      return false;
+    }
+
+    let ip = thread.context(target).map(|context| context.reg(Register::Ip));
+
+    if thread.resume().is_err() {
+      return false;
+    }
+
+    match ip {
+      Ok(ip) if !code.contains(&ip) => return true,
+      Ok(_) => {}
+      Err(_) => return false,
+    }
+
+    if Instant::now() >= deadline {
+      return false;
+    }
+
+    sleep(POLL_INTERVAL);
+  }
+}
@@ -0,0 +1,242 @@
+use std::{
+  ffi::OsString,
+  path::PathBuf,
+  thread,
+  time::{Duration, Instant},
+};
+
+use win::{
+  arch::Arch,
+  context::Register,
+  error::WinApiError,
+  memory::{region::Protection, ProcessMemory},
+  module::find_module,
+  process::{
+    access::ProcessAccess,
+    create::{create_process, CreateOptions, CreatedProcess},
+    util::find_by_name,
+    WinApiProcess,
+  },
+  wintype::Pid,
+};
+
+use crate::{init_module, load_module, InjectError, InjectResult, RemoteModule};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+// jmp to itself, parks the main thread on the entry point until we are done
+const SPIN_LOOP: [u8; 2] = [0xeb, 0xfe];
+
+// static imports are initialised by the loader before anything else runs, so kernel32 being there means LoadLibraryW is safe to call
+const READY_MODULE_NAME: &str = "kernel32.dll";
+
+#[derive(Debug, Clone)]
+pub struct LaunchOptions {
+  pub exe: PathBuf,
+  pub args: Vec<OsString>,
+  pub cwd: Option<PathBuf>,
+  // added to, or replacing, the variables inherited from us
+  pub env: Vec<(OsString, OsString)>,
+  // exe is a launcher, eg. thcrap_loader, that starts the game as a child with this exe name
+  // the child is injected instead of exe
+  pub child: Option<String>,
+  // how long the game may take to reach its entry point, or the launcher to start the game
+  pub timeout: Duration,
+  // config blob for the module's init export, see init_module
+  pub init: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Launched {
+  pub pid: Pid,
+  pub module: RemoteModule,
+}
+
+// starts the game and loads module_path into it before any of the game's own code runs
+pub fn launch_and_inject(module_path: &str, options: &LaunchOptions) -> InjectResult<Launched> {
+  match &options.child {
+    Some(child) => launch_child(module_path, options, child),
+    None => launch_suspended(module_path, options),
+  }
+}
+
+fn launch_suspended(module_path: &str, options: &LaunchOptions) -> InjectResult<Launched> {
+  let created = create(options, true)?;
+  let pid = created.process.pid();
+
+  match inject_at_entry(module_path, &created, options) {
+    Ok(module) => Ok(Launched { pid, module }),
+    Err(err) => {
+      // a half started game with the entry point patched is no use to anyone
+      if let Err(kill_err) = created.process.terminate(1) {
+        eprintln!("{:?}", kill_err);
+      }
+
+      Err(err)
+    }
+  }
+}
+
+fn inject_at_entry(module_path: &str, created: &CreatedProcess, options: &LaunchOptions) -> InjectResult<RemoteModule> {
+  let CreatedProcess { process, main_thread } = created;
+  let pid = process.pid();
+  let thread_err = |source| InjectError::ThreadControlError { pid, source };
+
+  let arch = process
+    .arch()
+    .map_err(|source| InjectError::QueryProcessError { pid, source })?;
+
+  // new threads start in RtlUserThreadStart, which gets the entry point in rcx on x64 and eax on x86
+  let entry_reg = match arch {
+    Arch::X86 => Register::Ax,
+    Arch::X64 => Register::Cx,
+  };
+
+  let entry = main_thread.context(arch).map_err(thread_err)?.reg(entry_reg);
+
+  let orig = patch_code(process, entry, &SPIN_LOOP)?;
+
+  main_thread.resume().map_err(thread_err)?;
+
+  wait_for_entry(created, arch, entry, options.timeout)?;
+
+  // the main thread is suspended on the entry point until the very end, so the game cant get ahead of the hook
+  let module = load_module(module_path, pid)?;
+
+  if let Some(init) = &options.init {
+    init_module(pid, &module, init)?;
+  }
+
+  patch_code(process, entry, &orig)?;
+
+  main_thread.resume().map_err(thread_err)?;
+
+  Ok(module)
+}
+
+// overwrites code in the target, returning what was there before
+fn patch_code<const N: usize>(process: &WinApiProcess, addr: usize, code: &[u8; N]) -> InjectResult<[u8; N]> {
+  let pid = process.pid();
+  let protect_err = |source| InjectError::ProtectMemoryError { pid, source };
+
+  let old_protection = process
+    .protect(addr, N, Protection::EXECUTE_READWRITE)
+    .map_err(protect_err)?;
+
+  let orig = process
+    .read(addr)
+    .map_err(|source| InjectError::ReadMemoryError { pid, source })?;
+
+  process
+    .write(addr, code)
+    .map_err(|source| InjectError::WriteMemoryError { pid, source })?;
+
+  process
+    .flush_instruction_cache(addr, N)
+    .map_err(|source| InjectError::WriteMemoryError {
+      pid,
+      source: WinApiError::from(source),
+    })?;
+
+  process.protect(addr, N, old_protection).map_err(protect_err)?;
+
+  Ok(orig)
+}
+
+// returns with the main thread suspended on the entry point
+fn wait_for_entry(created: &CreatedProcess, arch: Arch, entry: usize, timeout: Duration) -> InjectResult<()> {
+  let CreatedProcess { process, main_thread } = created;
+  let pid = process.pid();
+  let thread_err = |source| InjectError::ThreadControlError { pid, source };
+  let deadline = Instant::now() + timeout;
+
+  loop {
+    main_thread.suspend().map_err(thread_err)?;
+
+    if main_thread.context(arch).map_err(thread_err)?.reg(Register::Ip) == entry {
+      return Ok(());
+    }
+
+    main_thread.resume().map_err(thread_err)?;
+
+    if Instant::now() >= deadline {
+      return Err(InjectError::StartupTimeoutError { pid });
+    }
+
+    thread::sleep(POLL_INTERVAL);
+  }
+}
+
+// the launcher creates and sets up the game itself, so we can only wait for it to show up and finish loading
+fn launch_child(module_path: &str, options: &LaunchOptions, child: &str) -> InjectResult<Launched> {
+  let launcher = create(options, false)?;
+  let launcher_pid = launcher.process.pid();
+  let deadline = Instant::now() + options.timeout;
+
+  let pid = loop {
+    let children = find_by_name(child).map_err(|source| InjectError::QueryProcessError {
+      pid: launcher_pid,
+      source,
+    })?;
+
+    if let Some(info) = children.iter().find(|info| info.parent_pid == launcher_pid) {
+      break info.pid;
+    }
+
+    if Instant::now() >= deadline {
+      return Err(InjectError::ChildNotFoundError {
+        pid: launcher_pid,
+        name: child.to_string(),
+      });
+    }
+
+    thread::sleep(POLL_INTERVAL);
+  };
+
+  wait_until_ready(pid, deadline)?;
+
+  let module = load_module(module_path, pid)?;
+
+  if let Some(init) = &options.init {
+    init_module(pid, &module, init)?;
+  }
+
+  Ok(Launched { pid, module })
+}
+
+// waits for the process to get far enough through startup for LoadLibraryW to work
+pub fn wait_until_ready(pid: Pid, deadline: Instant) -> InjectResult<()> {
+  loop {
+    if is_ready(pid) {
+      return Ok(());
+    }
+
+    if Instant::now() >= deadline {
+      return Err(InjectError::StartupTimeoutError { pid });
+    }
+
+    thread::sleep(POLL_INTERVAL);
+  }
+}
+
+// module snapshots of a process the loader has not finished with fail, so errors just mean not yet
+pub fn is_ready(pid: Pid) -> bool {
+  WinApiProcess::new(pid, ProcessAccess::QUERY_LIMITED_INFORMATION)
+    .ok()
+    .and_then(|process| process.modules().ok())
+    .is_some_and(|modules| find_module(&modules, READY_MODULE_NAME).is_some())
+}
+
+fn create(options: &LaunchOptions, suspended: bool) -> InjectResult<CreatedProcess> {
+  let create_options = CreateOptions {
+    args: options.args.clone(),
+    cwd: options.cwd.clone(),
+    env: options.env.clone(),
+    suspended,
+  };
+
+  create_process(&options.exe, &create_options).map_err(|source| InjectError::LaunchError {
+    exe: options.exe.clone(),
+    source,
+  })
+}
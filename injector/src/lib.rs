@@ -1,271 +1,269 @@
-use std::{error, ffi, fs, mem, os::windows::prelude::OsStrExt, ptr};
+use std::{fmt, fs, os::windows::prelude::OsStrExt, path::PathBuf, str::FromStr, time::Duration};
 
 use win::{
-  error::util::get_last_error,
-  wintype::{Pid, Wchar},
+  arch::Arch,
+  error::WinApiErrorCode,
+  memory::{region::Protection, ProcessMemory},
+  module::find_module,
+  process::{access::ProcessAccess, WinApiProcess},
+  wintype::Pid,
 };
-use winapi::um::{
-  handleapi::CloseHandle,
-  libloaderapi::{GetModuleHandleA, GetProcAddress},
-  memoryapi::{VirtualAllocEx, VirtualFreeEx, WriteProcessMemory},
-  processthreadsapi::{CreateRemoteThread, GetExitCodeThread, OpenProcess},
-  synchapi::WaitForSingleObject,
-  winbase::INFINITE,
-  winnt::{MEM_COMMIT, MEM_RESERVE, PAGE_READWRITE, PROCESS_ALL_ACCESS},
-};
-
-const KERNEL_MODULE_NAME: &str = "Kernel32";
-const LOADLIB_PROC_NAME: &str = "LoadLibraryW";
-
-type BoxResult<T> = Result<T, Box<dyn error::Error + Send + Sync + 'static>>;
 
-pub fn load_module(module_path: &str, pid: u32) -> BoxResult<()> {
-  let path = fs::canonicalize(module_path)?;
-
-  let buffer = path.as_os_str().encode_wide().chain(Some(0)).collect::<Vec<_>>();
-  let buffer_bytelen = buffer.len() * mem::size_of::<u16>();
+use self::call::{call_remote, load_library_remote, KernelProcs};
+pub use self::error::{InjectError, InjectResult, ParseMethodError};
+use self::hijack::hijack_thread;
+use self::manual_map::manual_map;
+use self::resolve::{remote_export_addr, remote_module_export, ResolveError};
+
+mod call;
+pub mod error;
+pub mod hijack;
+pub mod launch;
+pub mod manual_map;
+pub mod resolve;
+pub mod stub;
+pub mod watch;
+
+// everything needed to write the module path and start a thread in the target, nothing more
+const INJECT_ACCESS: ProcessAccess = ProcessAccess::CREATE_THREAD
+  .union(ProcessAccess::QUERY_INFORMATION)
+  .union(ProcessAccess::VM_OPERATION)
+  .union(ProcessAccess::VM_READ)
+  .union(ProcessAccess::VM_WRITE);
+
+// exported by modules that want to clean up (unhook, stop threads) before being unloaded
+// extern "system" fn shutdown(_: *mut c_void) -> i32, returning non zero on success
+const SHUTDOWN_PROC_NAME: &str = "shutdown";
+
+// exported by modules that take a config blob, called with the blob's address straight after loading
+// extern "system" fn init(blob: *const u8) -> i32, returning non zero if the config was accepted
+const INIT_PROC_NAME: &str = "init";
+
+// init only has to copy the blob, anything slower is the module misbehaving
+const INIT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// a hijacked thread only runs the stub once the target schedules it, which can take a while if it is waiting on something
+const HIJACK_TIMEOUT: Duration = Duration::from_secs(10);
+
+// FreeLibrary only drops a single reference, a module loaded more than once needs more
+const MAX_FREE_ATTEMPTS: usize = 8;
+
+// a module we loaded into another process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteModule {
+  pub base: usize,
+  pub path: PathBuf,
+}
 
-  let handle = unsafe { OpenProcess(PROCESS_ALL_ACCESS, 0, pid) };
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum InjectionMethod {
+  // LoadLibraryW on a remote thread, the module ends up in the loader's module list like any other
+  #[default]
+  LoadLibrary,
+  // maps the module ourselves, see manual_map
+  ManualMap,
+  // LoadLibraryW on one of the target's own threads, see hijack_thread
+  ThreadHijack,
+}
 
-  if handle.is_null() {
-    panic!();
+impl InjectionMethod {
+  pub const ALL: [InjectionMethod; 3] = [
+    InjectionMethod::LoadLibrary,
+    InjectionMethod::ManualMap,
+    InjectionMethod::ThreadHijack,
+  ];
+
+  // what the method is called on the command line
+  pub fn name(self) -> &'static str {
+    match self {
+      InjectionMethod::LoadLibrary => "load-library",
+      InjectionMethod::ManualMap => "manual-map",
+      InjectionMethod::ThreadHijack => "thread-hijack",
+    }
   }
+}
 
-  let proc_mem = unsafe {
-    VirtualAllocEx(
-      handle,
-      ptr::null_mut(),
-      buffer_bytelen,
-      MEM_COMMIT | MEM_RESERVE,
-      PAGE_READWRITE,
-    )
-  };
-
-  if proc_mem.is_null() {
-    panic!();
+impl fmt::Display for InjectionMethod {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.name())
   }
+}
 
-  let success_code = unsafe {
-    WriteProcessMemory(
-      handle,
-      proc_mem,
-      buffer.as_ptr() as *const _,
-      buffer_bytelen,
-      ptr::null_mut(),
-    )
-  };
+impl FromStr for InjectionMethod {
+  type Err = ParseMethodError;
 
-  if success_code == 0 {
-    panic!();
+  fn from_str(name: &str) -> Result<Self, Self::Err> {
+    InjectionMethod::ALL
+      .into_iter()
+      .find(|method| method.name().eq_ignore_ascii_case(name))
+      .ok_or_else(|| ParseMethodError(name.to_string()))
   }
+}
 
-  // TODO
-  let loadlib_addr = get_loadlib_addr();
-
-  let thread = unsafe {
-    CreateRemoteThread(
-      handle,
-      ptr::null_mut(),
-      0,
-      // TODO
-      Some(mem::transmute(loadlib_addr)),
-      proc_mem,
-      0,
-      ptr::null_mut(),
-    )
+// init is the config blob for the module's init export, see init_module
+pub fn inject(module_path: &str, pid: Pid, method: InjectionMethod, init: Option<&[u8]>) -> InjectResult<RemoteModule> {
+  let module = match method {
+    InjectionMethod::LoadLibrary => load_module(module_path, pid)?,
+    InjectionMethod::ManualMap => manual_map(module_path, pid)?,
+    InjectionMethod::ThreadHijack => hijack_thread(module_path, pid, HIJACK_TIMEOUT)?,
   };
 
-  if thread.is_null() {
-    panic!();
+  if let Some(init) = init {
+    init_module(pid, &module, init)?;
   }
 
-  let exit_code = unsafe {
-    let mut exit_code = 0;
+  Ok(module)
+}
 
-    WaitForSingleObject(thread, INFINITE);
-    GetExitCodeThread(thread, &mut exit_code);
+// anything allocated in the target is released when it goes out of scope, whichever stage fails
+pub fn load_module(module_path: &str, pid: Pid) -> InjectResult<RemoteModule> {
+  let path = fs::canonicalize(module_path).map_err(|source| InjectError::ModulePathError {
+    path: module_path.into(),
+    source,
+  })?;
 
-    exit_code
-  };
+  let (process, target) = open_target(pid)?;
 
-  if exit_code == 0 {
-    let error = get_last_error();
+  let procs = KernelProcs::resolve(&process, target).map_err(|source| InjectError::ResolveProcError { pid, source })?;
 
-    // panic!("{:?}", error);
-  }
+  let base = load_library_remote(&process, target, &procs, path.as_os_str())?;
 
-  unsafe {
-    CloseHandle(thread);
-    VirtualFreeEx(handle, proc_mem, 0, MEM_RESERVE);
-    CloseHandle(handle);
-  };
-
-  Ok(())
+  verify_module(&process, base)
 }
 
-// TODO: other result type
-// TODO: attributes
-// TODO: better types (enum) and checks for other params
-
-// safety
-// - handle must be a valid handle to an open object
-// - addr and param need to be ok
-unsafe fn create_remote_thread(
-  handle: *mut ffi::c_void,
-  attributes: (),
-  stack_size: usize,
-  addr: extern "system" fn(*mut ffi::c_void) -> u32,
-  param: *mut ffi::c_void,
-  creation_flags: u32,
-) -> BoxResult<(*mut ffi::c_void, u32)> {
-  let (thread, id) = {
-    let mut id = 0;
-
-    let thread = CreateRemoteThread(
-      handle,
-      ptr::null_mut(),
-      stack_size,
-      Some(addr),
-      param,
-      creation_flags,
-      &mut id,
-    );
-
-    (thread, id)
-  };
+// LoadLibraryW returning something is not proof it is our module, so check the target agrees
+fn verify_module(process: &WinApiProcess, base: usize) -> InjectResult<RemoteModule> {
+  let pid = process.pid();
+
+  let module = process
+    .modules()
+    .map_err(|source| InjectError::VerifyModuleError { pid, source })?
+    .into_iter()
+    .find(|module| module.base == base)
+    .ok_or(InjectError::ModuleMissingError { pid, base })?;
+
+  Ok(RemoteModule {
+    base: module.base,
+    path: module.path,
+  })
+}
 
-  if thread.is_null() {
-    let error = get_last_error();
+// hands a freshly loaded module its config, before it installs any hooks
+// the blob is freed once init returns, so the module must copy out whatever it needs
+pub fn init_module(pid: Pid, module: &RemoteModule, blob: &[u8]) -> InjectResult<()> {
+  let (process, target) = open_target(pid)?;
 
-    Err(Box::new(error))
-  } else {
-    Ok((thread, id))
-  }
-}
+  let procs = KernelProcs::resolve(&process, target).map_err(|source| InjectError::ResolveProcError { pid, source })?;
 
-// TODO: other result type
+  // straight from the module's base, so it works however the module was injected
+  let init_addr = remote_module_export(&process, module.base, INIT_PROC_NAME)
+    .map_err(|source| InjectError::InitExportError { pid, source })?;
 
-// safety
-// - handle must be a valid handle to an open object
-unsafe fn close_handle(handle: *mut ffi::c_void) -> BoxResult<()> {
-  let success_code = CloseHandle(handle);
+  let blob_mem = process
+    .alloc(blob.len(), Protection::READWRITE)
+    .map_err(|source| InjectError::AllocMemoryError { pid, source })?;
 
-  if success_code == 0 {
-    let error = get_last_error();
+  process
+    .write_all(blob_mem.addr(), blob)
+    .map_err(|source| InjectError::WriteMemoryError { pid, source })?;
 
-    Err(Box::new(error))
-  } else {
-    Ok(())
-  }
-}
+  let res = match call_remote(&process, target, &procs, init_addr, blob_mem.addr(), Some(INIT_TIMEOUT)) {
+    Ok(res) => res,
+    Err(err @ (InjectError::ThreadTimeoutError { .. } | InjectError::WaitThreadError { .. })) => {
+      // init may still be reading the blob
+      blob_mem.leak();
 
-// TODO: other result type
-// TODO: can be safer if alloc_type and protection are enums
-// TODO: is byte_len == 0 valid?
-
-// safety
-// - handle must be a valid handle to an open object
-unsafe fn virtual_alloc_ex(
-  handle: *mut ffi::c_void,
-  addr: *mut ffi::c_void,
-  byte_len: usize,
-  alloc_type: u32,
-  protection: u32,
-) -> BoxResult<*mut ffi::c_void> {
-  let mem_addr = VirtualAllocEx(handle, addr, byte_len, alloc_type, protection);
-
-  if mem_addr.is_null() {
-    let error = get_last_error();
-
-    Err(Box::new(error))
-  } else {
-    Ok(mem_addr)
-  }
-}
+      return Err(err);
+    }
+    Err(err) => return Err(err),
+  };
 
-// TODO: other result type
-// TODO: some checks can be done here based on size and free_type
-
-// safety
-// - handle must be a valid handle to an open object
-// - must point to valid memory allocated by the process
-// - size must follow rules based on free_type and earlier call to virtual_alloc_ex
-unsafe fn virtual_free_ex(
-  handle: *mut ffi::c_void,
-  addr: *mut ffi::c_void,
-  size: usize,
-  free_type: u32,
-) -> BoxResult<()> {
-  let success_code = VirtualFreeEx(handle, addr, size, free_type);
-
-  if success_code == 0 {
-    let error = get_last_error();
-
-    Err(Box::new(error))
-  } else {
-    Ok(())
+  if res.ret as u32 == 0 {
+    return Err(InjectError::InitError { pid });
   }
-}
 
-// TODO: other result type
-// TODO: can have checks around buffer and byte len
-
-// safety
-// - handle must be a valid handle to an open object
-// - must point to valid memory allocated by the process
-// - byte_len must not exceed buffer size
-unsafe fn write_process_memory<T>(
-  handle: *mut ffi::c_void,
-  addr: *mut ffi::c_void,
-  buffer: &[T],
-  byte_len: usize,
-) -> BoxResult<()> {
-  let success_code = WriteProcessMemory(handle, addr, buffer.as_ptr() as *const _, byte_len, ptr::null_mut());
-
-  if success_code == 0 {
-    let error = get_last_error();
-
-    Err(Box::new(error))
-  } else {
-    Ok(())
-  }
+  Ok(())
 }
 
-// TODO: other result type
-// TODO: this is safe if we restrict possible values of access with an enum
-unsafe fn open_process(access: u32, inherit: bool, pid: u32) -> BoxResult<*mut ffi::c_void> {
-  // the only valid values for bool are 0 or 1
-  let handle = OpenProcess(access, inherit as i32, pid);
+// opens the target for injection and checks we can inject into it at all
+fn open_target(pid: Pid) -> InjectResult<(WinApiProcess, Arch)> {
+  let process =
+    WinApiProcess::new(pid, INJECT_ACCESS).map_err(|source| InjectError::OpenProcessError { pid, source })?;
 
-  if handle.is_null() {
-    let error = get_last_error();
+  let target = process
+    .arch()
+    .map_err(|source| InjectError::QueryProcessError { pid, source })?;
 
-    Err(Box::new(error))
-  } else {
-    Ok(handle)
+  // wow64 cant create threads in 64 bit processes
+  if target == Arch::X64 && Arch::current() == Arch::X86 {
+    return Err(InjectError::ArchMismatchError { pid, target });
   }
-}
 
-fn get_loadlib_addr() -> fn(*mut ffi::c_void) -> u32 {
-  let kernel = unsafe {
-    let kernel_cstr = ffi::CString::new(KERNEL_MODULE_NAME).unwrap();
-
-    GetModuleHandleA(kernel_cstr.as_ptr())
-  };
-
-  if kernel.is_null() {
-    panic!();
-  }
+  Ok((process, target))
+}
 
-  let loadlib = unsafe {
-    let loadlib_cstr = ffi::CString::new(LOADLIB_PROC_NAME).unwrap();
+// how a module's own shutdown went before it was unloaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shutdown {
+  Completed,
+  // the module has no shutdown export, it was unloaded straight away
+  NotExported,
+  // shutdown returned false, the module was unloaded anyway
+  Failed,
+  // shutdown did not return in time, the module was unloaded anyway
+  TimedOut,
+}
 
-    GetProcAddress(kernel, loadlib_cstr.as_ptr())
+// asks the module to shut down, then unloads it
+// timeout applies to each remote call, not to the whole ejection
+pub fn eject_module(module_name: &str, pid: Pid, timeout: Duration) -> InjectResult<Shutdown> {
+  let (process, target) = open_target(pid)?;
+
+  let procs = KernelProcs::resolve(&process, target).map_err(|source| InjectError::ResolveProcError { pid, source })?;
+
+  let modules = process
+    .modules()
+    .map_err(|source| InjectError::QueryProcessError { pid, source })?;
+
+  let module = find_module(&modules, module_name)
+    .cloned()
+    .ok_or_else(|| InjectError::ModuleNotLoadedError {
+      pid,
+      name: module_name.to_string(),
+    })?;
+
+  let shutdown = match remote_export_addr(&modules, target, &module.name, SHUTDOWN_PROC_NAME) {
+    Ok(shutdown_addr) => match call_remote(&process, target, &procs, shutdown_addr, 0, Some(timeout)) {
+      Ok(res) if res.ret as u32 != 0 => Shutdown::Completed,
+      Ok(_) => Shutdown::Failed,
+      Err(InjectError::ThreadTimeoutError { .. }) => Shutdown::TimedOut,
+      Err(err) => return Err(err),
+    },
+    Err(ResolveError::ExportNotFoundError { .. }) => Shutdown::NotExported,
+    Err(source) => return Err(InjectError::ResolveProcError { pid, source }),
   };
 
-  if loadlib.is_null() {
-    panic!();
+  // forced from here on, if shutdown timed out its thread may still be running code we are about to unmap
+  for _ in 0..MAX_FREE_ATTEMPTS {
+    let res = call_remote(&process, target, &procs, procs.free_library, module.base, Some(timeout))?;
+
+    if res.ret as u32 == 0 {
+      return Err(InjectError::FreeLibraryError {
+        pid,
+        source: WinApiErrorCode::new(res.last_error, "FreeLibrary"),
+      });
+    }
+
+    let loaded = process
+      .modules()
+      .map_err(|source| InjectError::VerifyModuleError { pid, source })?
+      .iter()
+      .any(|loaded| loaded.base == module.base);
+
+    if !loaded {
+      return Ok(shutdown);
+    }
   }
 
-  unsafe { mem::transmute(loadlib) }
+  Err(InjectError::UnloadError { pid, base: module.base })
 }
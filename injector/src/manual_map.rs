@@ -0,0 +1,443 @@
+use std::{collections::HashMap, env, fs, path::Path, time::Duration};
+
+use win::{
+  arch::Arch,
+  error::WinApiError,
+  memory::{region::Protection, ProcessMemory},
+  module::find_module,
+  pe::{
+    carrier::{tls_carrier, TlsCarrier},
+    export::ForwardedExport,
+    import::{bind_import, ImportModule, ImportName},
+    reloc::apply_relocations,
+    DataDirectoryKind, Layout, PeError, PeImage, Section,
+  },
+  process::WinApiProcess,
+  wintype::Pid,
+};
+
+use crate::{
+  call::{call_remote, load_library_remote, resolve_kernel_proc, run_stub, KernelProcs},
+  open_target,
+  resolve::RemoteExports,
+  stub::{call3_stub, dll_main_stub},
+  InjectError, InjectResult, RemoteModule,
+};
+
+const ADD_FUNCTION_TABLE_PROC_NAME: &str = "RtlAddFunctionTable";
+const DELETE_FUNCTION_TABLE_PROC_NAME: &str = "RtlDeleteFunctionTable";
+
+// RUNTIME_FUNCTION, three rvas
+const RUNTIME_FUNCTION_SIZE: usize = 12;
+
+// for the small calls made while setting up or cleaning up, none of them take the loader lock for long
+const CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+// maps module_path into the target ourselves instead of going through LoadLibraryW
+// the module never shows up in the loader's module list, and its dependencies are looked up by the
+// target's usual search rules, not from the module's own directory
+//
+// limitations, compared to LoadLibraryW:
+// - thread_local data is set up by a small carrier dll the loader does load, see tls_carrier
+//   it shows up in the module list instead, and is left behind in the temp directory
+// - tls callbacks get thread notifications through the carrier, DllMain only ever sees DLL_PROCESS_ATTACH
+// - x86 exception handlers are checked against the loader's images, whether ours are accepted depends on the
+//   target's dep settings, x64 unwind info is registered with RtlAddFunctionTable
+// - the module cannot be ejected, there is nothing for FreeLibrary to unload
+pub fn manual_map(module_path: &str, pid: Pid) -> InjectResult<RemoteModule> {
+  let path = fs::canonicalize(module_path).map_err(|source| InjectError::ModulePathError {
+    path: module_path.into(),
+    source,
+  })?;
+
+  let data = fs::read(&path).map_err(|source| InjectError::ModulePathError {
+    path: path.clone(),
+    source,
+  })?;
+
+  let image = PeImage::parse(&data, Layout::File).map_err(|source| InjectError::ModuleImageError {
+    path: path.clone(),
+    source,
+  })?;
+
+  let (process, target) = open_target(pid)?;
+
+  if image.arch() != target {
+    return Err(InjectError::ImageArchError {
+      pid,
+      found: image.arch(),
+    });
+  }
+
+  let procs = KernelProcs::resolve(&process, target).map_err(|source| InjectError::ResolveProcError { pid, source })?;
+
+  // writable until everything is written, see protect_sections
+  let image_mem = process
+    .alloc(image.size_of_image() as usize, Protection::READWRITE)
+    .map_err(|source| InjectError::AllocMemoryError { pid, source })?;
+
+  let base = image_mem.addr();
+
+  // dependencies and the tls carrier, unloaded again if mapping fails
+  let mut loaded = Vec::new();
+
+  if let Err(err) = map_image(&process, target, &procs, &image, &path, base, &mut loaded) {
+    if leaves_thread_running(&err) {
+      // the thread may still be running code in the image or one of its dependencies
+      image_mem.leak();
+    } else {
+      for &module in loaded.iter().rev() {
+        if call_remote(&process, target, &procs, procs.free_library, module, Some(CALL_TIMEOUT)).is_err() {
+          break;
+        }
+      }
+    }
+
+    return Err(err);
+  }
+
+  // the module is live from here on, it stays until the target exits
+  let base = image_mem.leak();
+
+  Ok(RemoteModule { base, path })
+}
+
+// everything after allocating the image, any module loaded on the way is added to loaded
+fn map_image(
+  process: &WinApiProcess,
+  target: Arch,
+  procs: &KernelProcs,
+  image: &PeImage,
+  path: &Path,
+  base: usize,
+  loaded: &mut Vec<usize>,
+) -> InjectResult<()> {
+  let pid = process.pid();
+
+  let image_err = |source| InjectError::ModuleImageError {
+    path: path.to_path_buf(),
+    source,
+  };
+
+  let mut mapped = image.map().map_err(image_err)?;
+  let relocations = image.relocations().map_err(image_err)?;
+
+  apply_relocations(
+    &mut mapped,
+    &relocations,
+    (base as u64).wrapping_sub(image.image_base()),
+  )
+  .map_err(image_err)?;
+
+  let tls = image.tls_directory().map_err(image_err)?;
+  let tls_callbacks = image.tls_callbacks().map_err(image_err)?;
+
+  // the loader gives every thread its own copy of the tls data, in a per thread array only it can add to
+  // so it loads a carrier dll with the same template and the image takes over the index it hands out
+  // (carrier base, where the callbacks that should get thread notifications go)
+  let carrier = match tls {
+    Some(directory) if directory.size() != 0 => {
+      // relocated already, every thread's copy is used by the image as is
+      let template_start = directory.template_rva as usize;
+      let template = mapped
+        .get(template_start..template_start + directory.template_size as usize)
+        .ok_or(PeError::RvaError(directory.template_rva))
+        .map_err(image_err)?;
+
+      let carrier = tls_carrier(
+        target,
+        template,
+        directory.zero_fill,
+        directory.characteristics,
+        tls_callbacks.len(),
+      );
+
+      // the loader keeps the file open for as long as the carrier is loaded, so every injection gets its own
+      let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+      let carrier_path = env::temp_dir().join(format!("{}.{}.{:x}.tls.dll", stem, pid, base));
+
+      let (carrier_base, index) = load_tls_carrier(process, target, procs, &carrier, &carrier_path, loaded)?;
+
+      let index_start = directory.index_rva as usize;
+
+      mapped
+        .get_mut(index_start..index_start + 4)
+        .ok_or(PeError::RvaError(directory.index_rva))
+        .map_err(image_err)?
+        .copy_from_slice(&index.to_le_bytes());
+
+      Some((carrier_base, carrier.callbacks_rva))
+    }
+    _ => None,
+  };
+
+  let imports = image.imports().map_err(image_err)?;
+
+  bind_imports(process, target, procs, &imports, path, &mut mapped, loaded)?;
+
+  process
+    .write_all(base, &mapped)
+    .map_err(|source| InjectError::WriteMemoryError { pid, source })?;
+
+  protect_sections(process, base, image.sections())?;
+
+  process
+    .flush_instruction_cache(base, mapped.len())
+    .map_err(|source| InjectError::WriteMemoryError {
+      pid,
+      source: WinApiError::from(source),
+    })?;
+
+  let function_table = register_function_table(process, target, image, base)?;
+
+  // the loader calls tls callbacks before the entry point, with the same arguments
+  let callbacks = tls_callbacks
+    .into_iter()
+    .map(|rva| base + rva as usize)
+    .collect::<Vec<_>>();
+
+  if let Err(err) = run_entry_points(process, target, image, base, &callbacks) {
+    if let Some(table) = function_table {
+      if !leaves_thread_running(&err) {
+        delete_function_table(process, target, procs, table);
+      }
+    }
+
+    return Err(err);
+  }
+
+  // from here on the loader forwards thread attach and detach to our callbacks
+  if let Some((carrier_base, callbacks_rva)) = carrier {
+    let slots = carrier_base + callbacks_rva as usize;
+    let pointer_size = target.pointer_size();
+
+    for (i, &callback) in callbacks.iter().enumerate() {
+      let bytes = (callback as u64).to_le_bytes();
+
+      process
+        .write_all(slots + i * pointer_size, &bytes[..pointer_size])
+        .map_err(|source| InjectError::WriteMemoryError { pid, source })?;
+    }
+  }
+
+  Ok(())
+}
+
+// runs the tls callbacks then DllMain with DLL_PROCESS_ATTACH, in one thread like the loader
+fn run_entry_points(
+  process: &WinApiProcess,
+  target: Arch,
+  image: &PeImage,
+  base: usize,
+  callbacks: &[usize],
+) -> InjectResult<()> {
+  let mut entries = callbacks.to_vec();
+
+  // resource only dlls have no entry point
+  let has_entry_point = image.entry_point() != 0;
+
+  if has_entry_point {
+    entries.push(base + image.entry_point() as usize);
+  }
+
+  if !entries.is_empty() {
+    let res = run_stub(process, |result| dll_main_stub(target, base, &entries, result), None)?;
+
+    // DllMain returns BOOL
+    if has_entry_point && res.ret as u32 == 0 {
+      return Err(InjectError::DllMainError { pid: process.pid() });
+    }
+  }
+
+  Ok(())
+}
+
+// writes the carrier to carrier_path and loads it, returning its base and the tls index the loader gave it
+fn load_tls_carrier(
+  process: &WinApiProcess,
+  target: Arch,
+  procs: &KernelProcs,
+  carrier: &TlsCarrier,
+  carrier_path: &Path,
+  loaded: &mut Vec<usize>,
+) -> InjectResult<(usize, u32)> {
+  let pid = process.pid();
+
+  fs::write(carrier_path, &carrier.image).map_err(|source| InjectError::TlsCarrierError {
+    path: carrier_path.to_path_buf(),
+    source,
+  })?;
+
+  let carrier_base = load_library_remote(process, target, procs, carrier_path.as_os_str())?;
+
+  loaded.push(carrier_base);
+
+  let index = process
+    .read::<u32>(carrier_base + carrier.index_rva as usize)
+    .map_err(|source| InjectError::ReadMemoryError { pid, source })?;
+
+  Ok((carrier_base, index))
+}
+
+// x64 code cannot be unwound through, exceptions and panics included, without its .pdata registered
+// returns the registered table, for delete_function_table
+fn register_function_table(
+  process: &WinApiProcess,
+  target: Arch,
+  image: &PeImage,
+  base: usize,
+) -> InjectResult<Option<usize>> {
+  let pid = process.pid();
+
+  let directory = match (target, image.directory(DataDirectoryKind::Exception)) {
+    (Arch::X64, Some(directory)) if !directory.is_empty() => directory,
+    _ => return Ok(None),
+  };
+
+  let add_function_table = resolve_kernel_proc(process, target, ADD_FUNCTION_TABLE_PROC_NAME)
+    .map_err(|source| InjectError::ResolveProcError { pid, source })?;
+
+  let table = base + directory.rva as usize;
+  let count = directory.size as usize / RUNTIME_FUNCTION_SIZE;
+
+  let res = run_stub(
+    process,
+    |result| call3_stub(target, add_function_table, [table, count, base], result),
+    Some(CALL_TIMEOUT),
+  )?;
+
+  // RtlAddFunctionTable returns BOOLEAN
+  if res.ret as u8 == 0 {
+    return Err(InjectError::FunctionTableError { pid });
+  }
+
+  Ok(Some(table))
+}
+
+// best effort, the image is freed straight after so the table must not outlive it
+fn delete_function_table(process: &WinApiProcess, target: Arch, procs: &KernelProcs, table: usize) {
+  if let Ok(delete_function_table) = resolve_kernel_proc(process, target, DELETE_FUNCTION_TABLE_PROC_NAME) {
+    let _ = call_remote(process, target, procs, delete_function_table, table, Some(CALL_TIMEOUT));
+  }
+}
+
+// a remote thread that did not finish may still be running code we would otherwise free
+fn leaves_thread_running(err: &InjectError) -> bool {
+  matches!(
+    err,
+    InjectError::ThreadTimeoutError { .. } | InjectError::WaitThreadError { .. }
+  )
+}
+
+// gives the headers and each section the protection the loader would, now that relocations and imports are written
+fn protect_sections(process: &WinApiProcess, base: usize, sections: &[Section]) -> InjectResult<()> {
+  let pid = process.pid();
+  let protect_err = |source| InjectError::ProtectMemoryError { pid, source };
+
+  // the headers take up everything before the first section
+  let headers_size = sections
+    .iter()
+    .map(|section| section.virtual_address)
+    .min()
+    .unwrap_or(0);
+
+  if headers_size != 0 {
+    process
+      .protect(base, headers_size as usize, Protection::READONLY)
+      .map_err(protect_err)?;
+  }
+
+  for section in sections {
+    let size = section.mapped_size() as usize;
+
+    // uninitialised sections with nothing in them take up no pages
+    if size == 0 {
+      continue;
+    }
+
+    process
+      .protect(
+        base + section.virtual_address as usize,
+        size,
+        section_protection(section),
+      )
+      .map_err(protect_err)?;
+  }
+
+  Ok(())
+}
+
+fn section_protection(section: &Section) -> Protection {
+  match (section.is_executable(), section.is_readable(), section.is_writable()) {
+    (true, _, true) => Protection::EXECUTE_READWRITE,
+    (true, true, false) => Protection::EXECUTE_READ,
+    (true, false, false) => Protection::EXECUTE,
+    (false, _, true) => Protection::READWRITE,
+    (false, true, false) => Protection::READONLY,
+    (false, false, false) => Protection::NO_ACCESS,
+  }
+}
+
+// fills in the import address table of a mapped image with addresses from the target
+fn bind_imports(
+  process: &WinApiProcess,
+  target: Arch,
+  procs: &KernelProcs,
+  imports: &[ImportModule],
+  path: &Path,
+  mapped: &mut [u8],
+  loaded_modules: &mut Vec<usize>,
+) -> InjectResult<()> {
+  let pid = process.pid();
+  let modules_err = |source| InjectError::QueryProcessError { pid, source };
+
+  let modules = process.modules().map_err(modules_err)?;
+
+  // dependencies the target has not loaded are loaded the normal way
+  // api sets (api-ms-win-*) never show up under their own name, LoadLibraryW gives us the module they map to
+  let mut loaded = HashMap::new();
+
+  for import in imports {
+    if find_module(&modules, &import.name).is_none() && !loaded.contains_key(&import.name) {
+      let base = load_library_remote(process, target, procs, import.name.as_ref())?;
+
+      loaded.insert(import.name.clone(), base);
+      loaded_modules.push(base);
+    }
+  }
+
+  let modules = match loaded.is_empty() {
+    true => modules,
+    false => process.modules().map_err(modules_err)?,
+  };
+
+  let mut exports = RemoteExports::new(&modules, target);
+
+  for import in imports {
+    let module_name = match loaded.get(&import.name) {
+      Some(&base) => match modules.iter().find(|module| module.base == base) {
+        Some(module) => module.name.as_str(),
+        None => return Err(InjectError::ModuleMissingError { pid, base }),
+      },
+      None => import.name.as_str(),
+    };
+
+    for function in &import.functions {
+      let export = match &function.import {
+        ImportName::Name(name) => ForwardedExport::Name(name),
+        ImportName::Ordinal(ordinal) => ForwardedExport::Ordinal(*ordinal),
+      };
+
+      let addr = exports
+        .resolve(module_name, export)
+        .map_err(|source| InjectError::ResolveProcError { pid, source })?;
+
+      bind_import(mapped, target, function.slot_rva, addr as u64).map_err(|source| InjectError::ModuleImageError {
+        path: path.to_path_buf(),
+        source,
+      })?;
+    }
+  }
+
+  Ok(())
+}
@@ -0,0 +1,215 @@
+use std::{collections::HashMap, error, fmt, fs, io, path::PathBuf};
+
+use win::{
+  arch::Arch,
+  error::{WinApiError, WinApiErrorCode},
+  memory::ProcessMemory,
+  module::Module,
+  pe::{
+    export::{ExportTarget, ForwardedExport, Forwarder},
+    Layout, PeError, PeImage, MAPPED_HEADERS_SIZE,
+  },
+  process::WinApiProcess,
+};
+
+// kernel32 forwards to kernelbase, which can forward again, anything deeper is a loop
+const MAX_FORWARDS: usize = 4;
+
+pub type ResolveResult<T> = Result<T, ResolveError>;
+
+#[derive(Debug)]
+pub enum ResolveError {
+  // looking the export up in our own process failed
+  LocalLookupError(WinApiErrorCode),
+  ModuleEnumError(WinApiError),
+  // the module is not loaded in the target, or not yet
+  ModuleNotFoundError(String),
+  ModuleReadError { path: PathBuf, source: io::Error },
+  ModuleParseError { path: PathBuf, source: PeError },
+  ModuleArchError { path: PathBuf, expected: Arch, found: Arch },
+  // reading the image mapped in the target failed
+  RemoteReadError { base: usize, source: WinApiError },
+  RemoteParseError { base: usize, source: PeError },
+  ExportNotFoundError { module: String, export: String },
+  ForwarderError(String),
+}
+
+impl fmt::Display for ResolveError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ResolveError::LocalLookupError(err) => err.fmt(f),
+      ResolveError::ModuleEnumError(err) => write!(f, "failed to enumerate modules - {}", err),
+      ResolveError::ModuleNotFoundError(name) => write!(f, "module {} is not loaded", name),
+      ResolveError::ModuleReadError { path, source } => write!(f, "failed to read {:?} - {}", path, source),
+      ResolveError::ModuleParseError { path, source } => write!(f, "failed to parse {:?} - {}", path, source),
+      ResolveError::ModuleArchError { path, expected, found } => {
+        write!(f, "{:?} is {}, expected {}", path, found, expected)
+      }
+      ResolveError::RemoteReadError { base, source } => {
+        write!(f, "failed to read module at {:#x} - {}", base, source)
+      }
+      ResolveError::RemoteParseError { base, source } => {
+        write!(f, "failed to parse module at {:#x} - {}", base, source)
+      }
+      ResolveError::ExportNotFoundError { module, export } => write!(f, "{} does not export {}", module, export),
+      ResolveError::ForwarderError(forwarder) => write!(f, "unresolvable export forwarder {}", forwarder),
+    }
+  }
+}
+
+impl error::Error for ResolveError {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      ResolveError::LocalLookupError(source) => Some(source),
+      ResolveError::ModuleEnumError(source) => Some(source),
+      ResolveError::ModuleReadError { source, .. } => Some(source),
+      ResolveError::ModuleParseError { source, .. } => Some(source),
+      ResolveError::RemoteReadError { source, .. } => Some(source),
+      ResolveError::RemoteParseError { source, .. } => Some(source),
+      _ => None,
+    }
+  }
+}
+
+// resolves an export of a module loaded in another process, which may be of a different bitness than us
+// the export table is parsed from the module's file on disk and rebased onto where the target mapped it
+pub fn remote_export_addr(modules: &[Module], arch: Arch, module_name: &str, export: &str) -> ResolveResult<usize> {
+  RemoteExports::new(modules, arch).resolve(module_name, ForwardedExport::Name(export))
+}
+
+// resolves an export from the image mapped at base in the target itself, rather than its file and the module list
+// works for manually mapped modules too, which the loader knows nothing about
+// forwarders are not followed, they would need the module list after all
+pub fn remote_module_export(process: &WinApiProcess, base: usize, export: &str) -> ResolveResult<usize> {
+  let read_err = |source| ResolveError::RemoteReadError { base, source };
+  let parse_err = |source| ResolveError::RemoteParseError { base, source };
+
+  let headers = process.read_bytes(base, MAPPED_HEADERS_SIZE).map_err(read_err)?;
+  let header_image = PeImage::parse(&headers, Layout::Mapped).map_err(parse_err)?;
+
+  // only the headers and readable sections are copied, whatever else there is reads as zeroes
+  let mut mapped = vec![0; (header_image.size_of_image() as usize).max(MAPPED_HEADERS_SIZE)];
+  mapped[..MAPPED_HEADERS_SIZE].copy_from_slice(&headers);
+
+  for section in header_image.sections().iter().filter(|section| section.is_readable()) {
+    let start = (section.virtual_address as usize).min(mapped.len());
+    let end = (start + section.mapped_size() as usize).min(mapped.len());
+
+    process
+      .read_exact(base + start, &mut mapped[start..end])
+      .map_err(read_err)?;
+  }
+
+  let image = PeImage::parse(&mapped, Layout::Mapped).map_err(parse_err)?;
+
+  match image.find_export(export).map_err(parse_err)? {
+    Some(ExportTarget::Rva(rva)) => Ok(base + rva as usize),
+    Some(ExportTarget::Forwarded(forwarder)) => Err(ResolveError::ForwarderError(forwarder)),
+    None => Err(ResolveError::ExportNotFoundError {
+      module: format!("module at {:#x}", base),
+      export: export.to_string(),
+    }),
+  }
+}
+
+// resolves any number of exports against one module list, reading each module's file only once
+#[derive(Debug)]
+pub struct RemoteExports<'a> {
+  modules: &'a [Module],
+  arch: Arch,
+  files: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl<'a> RemoteExports<'a> {
+  pub fn new(modules: &'a [Module], arch: Arch) -> Self {
+    Self {
+      modules,
+      arch,
+      files: HashMap::new(),
+    }
+  }
+
+  pub fn resolve(&mut self, module_name: &str, export: ForwardedExport) -> ResolveResult<usize> {
+    self.resolve_forwarded(module_name, export, 0)
+  }
+
+  fn resolve_forwarded(&mut self, module_name: &str, export: ForwardedExport, depth: usize) -> ResolveResult<usize> {
+    let module = self.find_module(module_name)?;
+    let image = self.image(module)?;
+
+    let target = match export {
+      ForwardedExport::Name(name) => image.find_export(name),
+      ForwardedExport::Ordinal(ordinal) => image.find_export_by_ordinal(ordinal),
+    }
+    .map_err(|source| ResolveError::ModuleParseError {
+      path: module.path.clone(),
+      source,
+    })?;
+
+    match target {
+      Some(ExportTarget::Rva(rva)) => Ok(module.base + rva as usize),
+      Some(ExportTarget::Forwarded(forwarder)) => {
+        let parsed = match Forwarder::parse(&forwarder) {
+          Some(parsed) if depth < MAX_FORWARDS => parsed,
+          _ => return Err(ResolveError::ForwarderError(forwarder)),
+        };
+
+        self.resolve_forwarded(&parsed.module_file_name(), parsed.export, depth + 1)
+      }
+      None => Err(ResolveError::ExportNotFoundError {
+        module: module.name.clone(),
+        export: match export {
+          ForwardedExport::Name(name) => name.to_string(),
+          ForwardedExport::Ordinal(ordinal) => format!("#{}", ordinal),
+        },
+      }),
+    }
+  }
+
+  fn image(&mut self, module: &Module) -> ResolveResult<PeImage<'_>> {
+    if !self.files.contains_key(&module.path) {
+      let data = fs::read(&module.path).map_err(|source| ResolveError::ModuleReadError {
+        path: module.path.clone(),
+        source,
+      })?;
+
+      self.files.insert(module.path.clone(), data);
+    }
+
+    // cannot fail, inserted above
+    let data = &self.files[&module.path];
+
+    PeImage::parse(data, Layout::File).map_err(|source| ResolveError::ModuleParseError {
+      path: module.path.clone(),
+      source,
+    })
+  }
+
+  // a wow64 process has both the 64 bit and 32 bit ntdll loaded under the same name, so the arch must match as well
+  fn find_module(&mut self, name: &str) -> ResolveResult<&'a Module> {
+    let modules = self.modules;
+    let mut candidates = modules.iter().filter(|module| module.is_named(name)).peekable();
+
+    if candidates.peek().is_none() {
+      return Err(ResolveError::ModuleNotFoundError(name.to_string()));
+    }
+
+    let mut mismatch = None;
+
+    for module in candidates {
+      match self.image(module)?.arch() {
+        found if found == self.arch => return Ok(module),
+        found => mismatch = Some((module, found)),
+      }
+    }
+
+    // cannot be none, there was at least one candidate and none of them matched
+    let (module, found) = mismatch.unwrap();
+
+    Err(ResolveError::ModuleArchError {
+      path: module.path.clone(),
+      expected: self.arch,
+      found,
+    })
+  }
+}
@@ -0,0 +1,578 @@
+use win::arch::Arch;
+
+// where a call stub stores its results, relative to the result address it was built with
+pub const RESULT_RETURN_OFFSET: usize = 0;
+pub const RESULT_LAST_ERROR_OFFSET: usize = 8;
+// only written by hijack stubs, which have no thread of their own to wait on
+pub const RESULT_DONE_OFFSET: usize = 12;
+pub const RESULT_SIZE: usize = 16;
+
+// a tiny assembler, just enough to emit opcodes followed by little endian immediates
+#[derive(Debug, Default)]
+struct Code {
+  bytes: Vec<u8>,
+}
+
+impl Code {
+  fn op(mut self, op: &[u8]) -> Self {
+    self.bytes.extend_from_slice(op);
+
+    self
+  }
+
+  fn imm32(mut self, imm: usize) -> Self {
+    self.bytes.extend_from_slice(&(imm as u32).to_le_bytes());
+
+    self
+  }
+
+  fn imm64(mut self, imm: usize) -> Self {
+    self.bytes.extend_from_slice(&(imm as u64).to_le_bytes());
+
+    self
+  }
+}
+
+// thread proc that calls func(arg) followed by GetLastError, storing both in the result block and returning 0
+// everything is baked in as immediates, so the stub only works at the addresses it was built for
+// the result block must be zeroed beforehand, 32 bit stubs only write the low half of the return value
+pub fn call_stub(arch: Arch, func: usize, arg: usize, get_last_error: usize, result: usize) -> Vec<u8> {
+  let ret = result + RESULT_RETURN_OFFSET;
+  let last_error = result + RESULT_LAST_ERROR_OFFSET;
+
+  match arch {
+    Arch::X86 => {
+      Code::default()
+        // push arg
+        .op(&[0x68])
+        .imm32(arg)
+        // mov eax, func; call eax (stdcall, func pops arg)
+        .op(&[0xb8])
+        .imm32(func)
+        .op(&[0xff, 0xd0])
+        // mov [ret], eax
+        .op(&[0xa3])
+        .imm32(ret)
+        // mov eax, get_last_error; call eax
+        .op(&[0xb8])
+        .imm32(get_last_error)
+        .op(&[0xff, 0xd0])
+        // mov [last_error], eax
+        .op(&[0xa3])
+        .imm32(last_error)
+        // xor eax, eax; ret 4
+        .op(&[0x31, 0xc0])
+        .op(&[0xc2, 0x04, 0x00])
+        .bytes
+    }
+    Arch::X64 => {
+      Code::default()
+        // sub rsp, 0x28 (shadow space and 16 byte alignment)
+        .op(&[0x48, 0x83, 0xec, 0x28])
+        // mov rcx, arg
+        .op(&[0x48, 0xb9])
+        .imm64(arg)
+        // mov rax, func; call rax
+        .op(&[0x48, 0xb8])
+        .imm64(func)
+        .op(&[0xff, 0xd0])
+        // mov rcx, ret; mov [rcx], rax
+        .op(&[0x48, 0xb9])
+        .imm64(ret)
+        .op(&[0x48, 0x89, 0x01])
+        // mov rax, get_last_error; call rax
+        .op(&[0x48, 0xb8])
+        .imm64(get_last_error)
+        .op(&[0xff, 0xd0])
+        // mov rcx, last_error; mov [rcx], eax
+        .op(&[0x48, 0xb9])
+        .imm64(last_error)
+        .op(&[0x89, 0x01])
+        // xor eax, eax; add rsp, 0x28; ret
+        .op(&[0x31, 0xc0])
+        .op(&[0x48, 0x83, 0xc4, 0x28])
+        .op(&[0xc3])
+        .bytes
+    }
+  }
+}
+
+// thread proc that calls func(args[0], args[1], args[2]), storing its return value in the result block and returning 0
+// for functions that do not set the last error, eg. RtlAddFunctionTable
+pub fn call3_stub(arch: Arch, func: usize, args: [usize; 3], result: usize) -> Vec<u8> {
+  let ret = result + RESULT_RETURN_OFFSET;
+
+  match arch {
+    Arch::X86 => {
+      Code::default()
+        // push args[2]; push args[1]; push args[0]
+        .op(&[0x68])
+        .imm32(args[2])
+        .op(&[0x68])
+        .imm32(args[1])
+        .op(&[0x68])
+        .imm32(args[0])
+        // mov eax, func; call eax (stdcall, func pops the args)
+        .op(&[0xb8])
+        .imm32(func)
+        .op(&[0xff, 0xd0])
+        // mov [ret], eax
+        .op(&[0xa3])
+        .imm32(ret)
+        // xor eax, eax; ret 4
+        .op(&[0x31, 0xc0])
+        .op(&[0xc2, 0x04, 0x00])
+        .bytes
+    }
+    Arch::X64 => {
+      Code::default()
+        // sub rsp, 0x28 (shadow space and 16 byte alignment)
+        .op(&[0x48, 0x83, 0xec, 0x28])
+        // mov rcx, args[0]; mov rdx, args[1]; mov r8, args[2]
+        .op(&[0x48, 0xb9])
+        .imm64(args[0])
+        .op(&[0x48, 0xba])
+        .imm64(args[1])
+        .op(&[0x49, 0xb8])
+        .imm64(args[2])
+        // mov rax, func; call rax
+        .op(&[0x48, 0xb8])
+        .imm64(func)
+        .op(&[0xff, 0xd0])
+        // mov rcx, ret; mov [rcx], rax
+        .op(&[0x48, 0xb9])
+        .imm64(ret)
+        .op(&[0x48, 0x89, 0x01])
+        // xor eax, eax; add rsp, 0x28; ret
+        .op(&[0x31, 0xc0])
+        .op(&[0x48, 0x83, 0xc4, 0x28])
+        .op(&[0xc3])
+        .bytes
+    }
+  }
+}
+
+// DLL_PROCESS_ATTACH
+const PROCESS_ATTACH: usize = 1;
+
+// thread proc that calls each of entries(module, DLL_PROCESS_ATTACH, null) in order, like the loader does with tls
+// callbacks and DllMain, storing the return value of the last one in the result block and returning 0
+pub fn dll_main_stub(arch: Arch, module: usize, entries: &[usize], result: usize) -> Vec<u8> {
+  let ret = result + RESULT_RETURN_OFFSET;
+
+  match arch {
+    Arch::X86 => {
+      let mut code = Code::default();
+
+      for &entry in entries {
+        code = code
+          // push 0; push 1; push module (stdcall, the callee pops them)
+          .op(&[0x6a, 0x00])
+          .op(&[0x6a, PROCESS_ATTACH as u8])
+          .op(&[0x68])
+          .imm32(module)
+          // mov eax, entry; call eax
+          .op(&[0xb8])
+          .imm32(entry)
+          .op(&[0xff, 0xd0]);
+      }
+
+      code
+        // mov [ret], eax
+        .op(&[0xa3])
+        .imm32(ret)
+        // xor eax, eax; ret 4
+        .op(&[0x31, 0xc0])
+        .op(&[0xc2, 0x04, 0x00])
+        .bytes
+    }
+    Arch::X64 => {
+      // sub rsp, 0x28 (shadow space and 16 byte alignment)
+      let mut code = Code::default().op(&[0x48, 0x83, 0xec, 0x28]);
+
+      for &entry in entries {
+        code = code
+          // mov rcx, module
+          .op(&[0x48, 0xb9])
+          .imm64(module)
+          // mov edx, 1
+          .op(&[0xba])
+          .imm32(PROCESS_ATTACH)
+          // xor r8d, r8d
+          .op(&[0x45, 0x31, 0xc0])
+          // mov rax, entry; call rax
+          .op(&[0x48, 0xb8])
+          .imm64(entry)
+          .op(&[0xff, 0xd0]);
+      }
+
+      code
+        // mov rcx, ret; mov [rcx], rax
+        .op(&[0x48, 0xb9])
+        .imm64(ret)
+        .op(&[0x48, 0x89, 0x01])
+        // xor eax, eax; add rsp, 0x28; ret
+        .op(&[0x31, 0xc0])
+        .op(&[0x48, 0x83, 0xc4, 0x28])
+        .op(&[0xc3])
+        .bytes
+    }
+  }
+}
+
+// volatile xmm registers, saved by hijack stubs since the interrupted code may be using them
+const X86_SAVED_XMM: u8 = 8;
+const X64_SAVED_XMM: u8 = 6;
+
+// movdqu [esp/rsp + i * 16], xmm<i> for each saved register
+fn save_xmm(mut code: Code, count: u8) -> Code {
+  for i in 0..count {
+    code = code.op(&[0xf3, 0x0f, 0x7f, 0x44 | (i << 3), 0x24, i * 16]);
+  }
+
+  code
+}
+
+// movdqu xmm<i>, [esp/rsp + i * 16] for each saved register
+fn restore_xmm(mut code: Code, count: u8) -> Code {
+  for i in 0..count {
+    code = code.op(&[0xf3, 0x0f, 0x6f, 0x44 | (i << 3), 0x24, i * 16]);
+  }
+
+  code
+}
+
+// code for a hijacked thread to run in place of whatever it was doing, which calls func(arg) followed by
+// GetLastError, stores both in the result block, sets the done flag and jumps back to return_ip
+// flags, volatile registers and volatile xmm registers are preserved so the thread carries on none the wiser
+// the result block must be zeroed beforehand, 32 bit stubs only write the low half of the return value
+pub fn hijack_stub(
+  arch: Arch,
+  return_ip: usize,
+  func: usize,
+  arg: usize,
+  get_last_error: usize,
+  result: usize,
+) -> Vec<u8> {
+  let ret = result + RESULT_RETURN_OFFSET;
+  let last_error = result + RESULT_LAST_ERROR_OFFSET;
+  let done = result + RESULT_DONE_OFFSET;
+
+  match arch {
+    Arch::X86 => {
+      let code = Code::default()
+        // push return_ip, the ret at the end pops it as if we had been called from there
+        .op(&[0x68])
+        .imm32(return_ip)
+        // pushfd; pushad
+        .op(&[0x9c])
+        .op(&[0x60])
+        // mov ebp, esp; and esp, -16; sub esp, 0x80
+        .op(&[0x89, 0xe5])
+        .op(&[0x83, 0xe4, 0xf0])
+        .op(&[0x81, 0xec])
+        .imm32(X86_SAVED_XMM as usize * 16);
+
+      let code = save_xmm(code, X86_SAVED_XMM)
+        // push arg
+        .op(&[0x68])
+        .imm32(arg)
+        // mov eax, func; call eax (stdcall, func pops arg)
+        .op(&[0xb8])
+        .imm32(func)
+        .op(&[0xff, 0xd0])
+        // mov [ret], eax
+        .op(&[0xa3])
+        .imm32(ret)
+        // mov eax, get_last_error; call eax
+        .op(&[0xb8])
+        .imm32(get_last_error)
+        .op(&[0xff, 0xd0])
+        // mov [last_error], eax
+        .op(&[0xa3])
+        .imm32(last_error)
+        // mov dword [done], 1
+        .op(&[0xc7, 0x05])
+        .imm32(done)
+        .imm32(1);
+
+      restore_xmm(code, X86_SAVED_XMM)
+        // mov esp, ebp; popad; popfd; ret
+        .op(&[0x89, 0xec])
+        .op(&[0x61])
+        .op(&[0x9d])
+        .op(&[0xc3])
+        .bytes
+    }
+    Arch::X64 => {
+      let code = Code::default()
+        // sub rsp, 8; push rax; mov rax, return_ip; mov [rsp + 8], rax; pop rax
+        // there is no push imm64, so the return address is stored through rax instead
+        .op(&[0x48, 0x83, 0xec, 0x08])
+        .op(&[0x50])
+        .op(&[0x48, 0xb8])
+        .imm64(return_ip)
+        .op(&[0x48, 0x89, 0x44, 0x24, 0x08])
+        .op(&[0x58])
+        // pushfq; push rax, rcx, rdx, r8, r9, r10, r11; push rbp
+        .op(&[0x9c])
+        .op(&[0x50, 0x51, 0x52])
+        .op(&[0x41, 0x50, 0x41, 0x51, 0x41, 0x52, 0x41, 0x53])
+        .op(&[0x55])
+        // mov rbp, rsp; and rsp, -16; sub rsp, 0x60
+        .op(&[0x48, 0x89, 0xe5])
+        .op(&[0x48, 0x83, 0xe4, 0xf0])
+        .op(&[0x48, 0x83, 0xec, X64_SAVED_XMM * 16]);
+
+      let code = save_xmm(code, X64_SAVED_XMM)
+        // sub rsp, 0x20 (shadow space)
+        .op(&[0x48, 0x83, 0xec, 0x20])
+        // mov rcx, arg
+        .op(&[0x48, 0xb9])
+        .imm64(arg)
+        // mov rax, func; call rax
+        .op(&[0x48, 0xb8])
+        .imm64(func)
+        .op(&[0xff, 0xd0])
+        // mov rcx, ret; mov [rcx], rax
+        .op(&[0x48, 0xb9])
+        .imm64(ret)
+        .op(&[0x48, 0x89, 0x01])
+        // mov rax, get_last_error; call rax
+        .op(&[0x48, 0xb8])
+        .imm64(get_last_error)
+        .op(&[0xff, 0xd0])
+        // mov rcx, last_error; mov [rcx], eax
+        .op(&[0x48, 0xb9])
+        .imm64(last_error)
+        .op(&[0x89, 0x01])
+        // mov rcx, done; mov dword [rcx], 1
+        .op(&[0x48, 0xb9])
+        .imm64(done)
+        .op(&[0xc7, 0x01])
+        .imm32(1)
+        // add rsp, 0x20
+        .op(&[0x48, 0x83, 0xc4, 0x20]);
+
+      restore_xmm(code, X64_SAVED_XMM)
+        // mov rsp, rbp; pop rbp; pop r11, r10, r9, r8, rdx, rcx, rax; popfq; ret
+        .op(&[0x48, 0x89, 0xec])
+        .op(&[0x5d])
+        .op(&[0x41, 0x5b, 0x41, 0x5a, 0x41, 0x59, 0x41, 0x58])
+        .op(&[0x5a, 0x59, 0x58])
+        .op(&[0x9d])
+        .op(&[0xc3])
+        .bytes
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const RETURN_IP: usize = 0x1122_3344;
+  const FUNC: usize = 0x5566_7788;
+  const ARG: usize = 0x99aa_bbcc;
+  const GET_LAST_ERROR: usize = 0x0102_0304;
+  const RESULT: usize = 0x1000_0000;
+
+  #[test]
+  fn call_stub_x86() {
+    assert_eq!(
+      call_stub(Arch::X86, FUNC, ARG, GET_LAST_ERROR, RESULT),
+      [
+        &[0x68, 0xcc, 0xbb, 0xaa, 0x99][..],
+        &[0xb8, 0x88, 0x77, 0x66, 0x55],
+        &[0xff, 0xd0],
+        &[0xa3, 0x00, 0x00, 0x00, 0x10],
+        &[0xb8, 0x04, 0x03, 0x02, 0x01],
+        &[0xff, 0xd0],
+        &[0xa3, 0x08, 0x00, 0x00, 0x10],
+        &[0x31, 0xc0],
+        &[0xc2, 0x04, 0x00],
+      ]
+      .concat()
+    );
+  }
+
+  #[test]
+  fn call_stub_x64() {
+    assert_eq!(
+      call_stub(Arch::X64, FUNC, ARG, GET_LAST_ERROR, RESULT),
+      [
+        &[0x48, 0x83, 0xec, 0x28][..],
+        &[0x48, 0xb9, 0xcc, 0xbb, 0xaa, 0x99, 0x00, 0x00, 0x00, 0x00],
+        &[0x48, 0xb8, 0x88, 0x77, 0x66, 0x55, 0x00, 0x00, 0x00, 0x00],
+        &[0xff, 0xd0],
+        &[0x48, 0xb9, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00],
+        &[0x48, 0x89, 0x01],
+        &[0x48, 0xb8, 0x04, 0x03, 0x02, 0x01, 0x00, 0x00, 0x00, 0x00],
+        &[0xff, 0xd0],
+        &[0x48, 0xb9, 0x08, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00],
+        &[0x89, 0x01],
+        &[0x31, 0xc0],
+        &[0x48, 0x83, 0xc4, 0x28],
+        &[0xc3],
+      ]
+      .concat()
+    );
+  }
+
+  #[test]
+  fn call3_stub_x86() {
+    assert_eq!(
+      call3_stub(Arch::X86, FUNC, [ARG, 0x20, 0x3000], RESULT),
+      [
+        &[0x68, 0x00, 0x30, 0x00, 0x00][..],
+        &[0x68, 0x20, 0x00, 0x00, 0x00],
+        &[0x68, 0xcc, 0xbb, 0xaa, 0x99],
+        &[0xb8, 0x88, 0x77, 0x66, 0x55],
+        &[0xff, 0xd0],
+        &[0xa3, 0x00, 0x00, 0x00, 0x10],
+        &[0x31, 0xc0],
+        &[0xc2, 0x04, 0x00],
+      ]
+      .concat()
+    );
+  }
+
+  #[test]
+  fn call3_stub_x64() {
+    assert_eq!(
+      call3_stub(Arch::X64, FUNC, [ARG, 0x20, 0x3000], RESULT),
+      [
+        &[0x48, 0x83, 0xec, 0x28][..],
+        &[0x48, 0xb9, 0xcc, 0xbb, 0xaa, 0x99, 0x00, 0x00, 0x00, 0x00],
+        &[0x48, 0xba, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        &[0x49, 0xb8, 0x00, 0x30, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        &[0x48, 0xb8, 0x88, 0x77, 0x66, 0x55, 0x00, 0x00, 0x00, 0x00],
+        &[0xff, 0xd0],
+        &[0x48, 0xb9, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00],
+        &[0x48, 0x89, 0x01],
+        &[0x31, 0xc0],
+        &[0x48, 0x83, 0xc4, 0x28],
+        &[0xc3],
+      ]
+      .concat()
+    );
+  }
+
+  #[test]
+  fn hijack_stub_x86() {
+    assert_eq!(
+      hijack_stub(Arch::X86, RETURN_IP, FUNC, ARG, GET_LAST_ERROR, RESULT),
+      [
+        &[0x68, 0x44, 0x33, 0x22, 0x11][..],
+        &[0x9c, 0x60],
+        &[0x89, 0xe5],
+        &[0x83, 0xe4, 0xf0],
+        &[0x81, 0xec, 0x80, 0x00, 0x00, 0x00],
+        &[0xf3, 0x0f, 0x7f, 0x44, 0x24, 0x00],
+        &[0xf3, 0x0f, 0x7f, 0x4c, 0x24, 0x10],
+        &[0xf3, 0x0f, 0x7f, 0x54, 0x24, 0x20],
+        &[0xf3, 0x0f, 0x7f, 0x5c, 0x24, 0x30],
+        &[0xf3, 0x0f, 0x7f, 0x64, 0x24, 0x40],
+        &[0xf3, 0x0f, 0x7f, 0x6c, 0x24, 0x50],
+        &[0xf3, 0x0f, 0x7f, 0x74, 0x24, 0x60],
+        &[0xf3, 0x0f, 0x7f, 0x7c, 0x24, 0x70],
+        &[0x68, 0xcc, 0xbb, 0xaa, 0x99],
+        &[0xb8, 0x88, 0x77, 0x66, 0x55],
+        &[0xff, 0xd0],
+        &[0xa3, 0x00, 0x00, 0x00, 0x10],
+        &[0xb8, 0x04, 0x03, 0x02, 0x01],
+        &[0xff, 0xd0],
+        &[0xa3, 0x08, 0x00, 0x00, 0x10],
+        &[0xc7, 0x05, 0x0c, 0x00, 0x00, 0x10, 0x01, 0x00, 0x00, 0x00],
+        &[0xf3, 0x0f, 0x6f, 0x44, 0x24, 0x00],
+        &[0xf3, 0x0f, 0x6f, 0x4c, 0x24, 0x10],
+        &[0xf3, 0x0f, 0x6f, 0x54, 0x24, 0x20],
+        &[0xf3, 0x0f, 0x6f, 0x5c, 0x24, 0x30],
+        &[0xf3, 0x0f, 0x6f, 0x64, 0x24, 0x40],
+        &[0xf3, 0x0f, 0x6f, 0x6c, 0x24, 0x50],
+        &[0xf3, 0x0f, 0x6f, 0x74, 0x24, 0x60],
+        &[0xf3, 0x0f, 0x6f, 0x7c, 0x24, 0x70],
+        &[0x89, 0xec],
+        &[0x61, 0x9d, 0xc3],
+      ]
+      .concat()
+    );
+  }
+
+  #[test]
+  fn hijack_stub_x64() {
+    assert_eq!(
+      hijack_stub(Arch::X64, RETURN_IP, FUNC, ARG, GET_LAST_ERROR, RESULT),
+      [
+        &[0x48, 0x83, 0xec, 0x08][..],
+        &[0x50],
+        &[0x48, 0xb8, 0x44, 0x33, 0x22, 0x11, 0x00, 0x00, 0x00, 0x00],
+        &[0x48, 0x89, 0x44, 0x24, 0x08],
+        &[0x58],
+        &[0x9c],
+        &[0x50, 0x51, 0x52],
+        &[0x41, 0x50, 0x41, 0x51, 0x41, 0x52, 0x41, 0x53],
+        &[0x55],
+        &[0x48, 0x89, 0xe5],
+        &[0x48, 0x83, 0xe4, 0xf0],
+        &[0x48, 0x83, 0xec, 0x60],
+        &[0xf3, 0x0f, 0x7f, 0x44, 0x24, 0x00],
+        &[0xf3, 0x0f, 0x7f, 0x4c, 0x24, 0x10],
+        &[0xf3, 0x0f, 0x7f, 0x54, 0x24, 0x20],
+        &[0xf3, 0x0f, 0x7f, 0x5c, 0x24, 0x30],
+        &[0xf3, 0x0f, 0x7f, 0x64, 0x24, 0x40],
+        &[0xf3, 0x0f, 0x7f, 0x6c, 0x24, 0x50],
+        &[0x48, 0x83, 0xec, 0x20],
+        &[0x48, 0xb9, 0xcc, 0xbb, 0xaa, 0x99, 0x00, 0x00, 0x00, 0x00],
+        &[0x48, 0xb8, 0x88, 0x77, 0x66, 0x55, 0x00, 0x00, 0x00, 0x00],
+        &[0xff, 0xd0],
+        &[0x48, 0xb9, 0x00, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00],
+        &[0x48, 0x89, 0x01],
+        &[0x48, 0xb8, 0x04, 0x03, 0x02, 0x01, 0x00, 0x00, 0x00, 0x00],
+        &[0xff, 0xd0],
+        &[0x48, 0xb9, 0x08, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00],
+        &[0x89, 0x01],
+        &[0x48, 0xb9, 0x0c, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x00],
+        &[0xc7, 0x01, 0x01, 0x00, 0x00, 0x00],
+        &[0x48, 0x83, 0xc4, 0x20],
+        &[0xf3, 0x0f, 0x6f, 0x44, 0x24, 0x00],
+        &[0xf3, 0x0f, 0x6f, 0x4c, 0x24, 0x10],
+        &[0xf3, 0x0f, 0x6f, 0x54, 0x24, 0x20],
+        &[0xf3, 0x0f, 0x6f, 0x5c, 0x24, 0x30],
+        &[0xf3, 0x0f, 0x6f, 0x64, 0x24, 0x40],
+        &[0xf3, 0x0f, 0x6f, 0x6c, 0x24, 0x50],
+        &[0x48, 0x89, 0xec],
+        &[0x5d],
+        &[0x41, 0x5b, 0x41, 0x5a, 0x41, 0x59, 0x41, 0x58],
+        &[0x5a, 0x59, 0x58],
+        &[0x9d, 0xc3],
+      ]
+      .concat()
+    );
+  }
+
+  // hijack_thread sizes the allocation with a stub built for address 0, before it knows the real ones
+  #[test]
+  fn stub_length_does_not_depend_on_addresses() {
+    let max = u32::MAX as usize;
+
+    for arch in [Arch::X86, Arch::X64] {
+      assert_eq!(
+        call_stub(arch, 0, 0, 0, 0).len(),
+        call_stub(arch, max, max, max, max - RESULT_SIZE).len()
+      );
+      assert_eq!(
+        call3_stub(arch, 0, [0; 3], 0).len(),
+        call3_stub(arch, max, [max; 3], max - RESULT_SIZE).len()
+      );
+      assert_eq!(
+        hijack_stub(arch, 0, 0, 0, 0, 0).len(),
+        hijack_stub(arch, max, max, max, max, max - RESULT_SIZE).len()
+      );
+      assert_eq!(
+        hijack_stub(arch, 0, 0, 0, 0, 0).len(),
+        hijack_stub(arch, RETURN_IP, FUNC, ARG, GET_LAST_ERROR, RESULT).len()
+      );
+    }
+  }
+}
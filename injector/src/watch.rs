@@ -0,0 +1,215 @@
+use std::{
+  collections::{HashMap, HashSet},
+  sync::atomic::{AtomicBool, Ordering},
+  thread,
+  time::{Duration, Instant},
+};
+
+use win::{
+  error::{WinApiError, WinApiResult},
+  process::{access::ProcessAccess, info::ProcessInfo, util::processes, WinApiProcess},
+  wintype::Pid,
+};
+
+use crate::{init_module, launch::is_ready, load_module, InjectError, InjectResult, RemoteModule};
+
+// polls in a row whose snapshot fails before run gives up
+const MAX_SNAPSHOT_FAILURES: u32 = 5;
+
+#[derive(Debug, Clone)]
+pub struct WatchOptions {
+  // exe names of every supported game
+  pub exe_names: Vec<String>,
+  pub module_path: String,
+  // name the module shows up as in the target, to tell if it is already loaded
+  pub module_name: String,
+  pub poll_interval: Duration,
+  // extra time a newly started game gets to initialise once it is ready for LoadLibraryW
+  pub settle: Duration,
+  // games that are not ready by then are given up on
+  pub startup_timeout: Duration,
+  // config blob for the module's init export, see init_module
+  pub init: Option<Vec<u8>>,
+}
+
+#[derive(Debug)]
+pub enum WatchEvent {
+  Found { pid: Pid, exe_name: String },
+  Injected { pid: Pid, module: RemoteModule },
+  AlreadyLoaded { pid: Pid, base: usize },
+  Failed { pid: Pid, err: InjectError },
+  Exited { pid: Pid },
+  // a process entry that could not be read, the rest of that poll's walk is lost with it
+  ProcessSkipped { err: WinApiError },
+  // the process snapshot could not be taken, nothing was polled
+  SnapshotFailed { err: WinApiError },
+}
+
+// polls for supported games and injects each one once
+#[derive(Debug)]
+pub struct Watcher {
+  options: WatchOptions,
+  // injected, already loaded or failed, never touched again while the process is alive
+  handled: HashSet<Pid>,
+  // seen but not injected yet, with when they were first seen
+  pending: HashMap<Pid, Instant>,
+  first_poll: bool,
+}
+
+impl Watcher {
+  pub fn new(options: WatchOptions) -> Self {
+    Self {
+      options,
+      handled: HashSet::new(),
+      pending: HashMap::new(),
+      first_poll: true,
+    }
+  }
+
+  // polls until stop is set, reporting everything that happens to on_event
+  // only fails once the snapshot has failed MAX_SNAPSHOT_FAILURES polls in a row
+  pub fn run(&mut self, stop: &AtomicBool, mut on_event: impl FnMut(WatchEvent)) -> WinApiResult<()> {
+    let mut snapshot_failures = 0;
+
+    while !stop.load(Ordering::Relaxed) {
+      match self.poll() {
+        Ok(events) => {
+          snapshot_failures = 0;
+
+          for event in events {
+            on_event(event);
+          }
+        }
+        Err(err) => {
+          snapshot_failures += 1;
+
+          if snapshot_failures >= MAX_SNAPSHOT_FAILURES {
+            return Err(err);
+          }
+
+          on_event(WatchEvent::SnapshotFailed { err });
+        }
+      }
+
+      thread::sleep(self.options.poll_interval);
+    }
+
+    Ok(())
+  }
+
+  // a single pass over the running processes, only failing if the snapshot cannot be taken
+  pub fn poll(&mut self) -> WinApiResult<Vec<WatchEvent>> {
+    let now = Instant::now();
+    let mut events = Vec::new();
+    let mut games = Vec::new();
+    let mut complete = true;
+
+    for info in processes()? {
+      match info {
+        Ok(info) if self.is_supported(&info) => games.push(info),
+        Ok(_) => (),
+        Err(err) => {
+          complete = false;
+
+          events.push(WatchEvent::ProcessSkipped { err });
+        }
+      }
+    }
+
+    let alive = games.iter().map(|info| info.pid).collect::<HashSet<_>>();
+
+    // forget exited games, so a restarted game (or a reused pid) is treated as new
+    // a walk cut short says nothing about the games it did not reach
+    if complete {
+      self.handled.retain(|pid| {
+        let alive = alive.contains(pid);
+
+        if !alive {
+          events.push(WatchEvent::Exited { pid: *pid });
+        }
+
+        alive
+      });
+
+      self.pending.retain(|pid, _| alive.contains(pid));
+    }
+
+    // games that were already running when we started are long past initialising
+    let first_seen_at = if self.first_poll {
+      now.checked_sub(self.options.settle).unwrap_or(now)
+    } else {
+      now
+    };
+
+    self.first_poll = false;
+
+    for info in games {
+      if self.handled.contains(&info.pid) {
+        continue;
+      }
+
+      let first_seen = *self.pending.entry(info.pid).or_insert_with(|| {
+        events.push(WatchEvent::Found {
+          pid: info.pid,
+          exe_name: info.exe_name.clone(),
+        });
+
+        first_seen_at
+      });
+
+      let waited = now.duration_since(first_seen);
+
+      if waited < self.options.settle || !is_ready(info.pid) {
+        if waited >= self.options.startup_timeout {
+          self.pending.remove(&info.pid);
+          self.handled.insert(info.pid);
+
+          events.push(WatchEvent::Failed {
+            pid: info.pid,
+            err: InjectError::StartupTimeoutError { pid: info.pid },
+          });
+        }
+
+        continue;
+      }
+
+      // failed injections are not retried either, they would most likely just fail again
+      self.pending.remove(&info.pid);
+      self.handled.insert(info.pid);
+
+      events.push(self.inject(info.pid));
+    }
+
+    Ok(events)
+  }
+
+  fn is_supported(&self, info: &ProcessInfo) -> bool {
+    self.options.exe_names.iter().any(|name| info.is_named(name))
+  }
+
+  fn inject(&self, pid: Pid) -> WatchEvent {
+    // an earlier run of the watcher, or something else entirely, may have loaded it already
+    let loaded = WinApiProcess::new(pid, ProcessAccess::QUERY_LIMITED_INFORMATION)
+      .ok()
+      .and_then(|process| process.find_module(&self.options.module_name).ok().flatten());
+
+    if let Some(module) = loaded {
+      return WatchEvent::AlreadyLoaded { pid, base: module.base };
+    }
+
+    match self.load(pid) {
+      Ok(module) => WatchEvent::Injected { pid, module },
+      Err(err) => WatchEvent::Failed { pid, err },
+    }
+  }
+
+  fn load(&self, pid: Pid) -> InjectResult<RemoteModule> {
+    let module = load_module(&self.options.module_path, pid)?;
+
+    if let Some(init) = &self.options.init {
+      init_module(pid, &module, init)?;
+    }
+
+    Ok(module)
+  }
+}
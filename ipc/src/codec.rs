@@ -0,0 +1,289 @@
+use std::{
+  error, fmt,
+  io::{self, Read, Write},
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+// frame layout: length (u32 le), version (u16 le), then the json message
+// the length covers the version and the json, not itself
+pub const PROTOCOL_VERSION: u16 = 1;
+pub const LEN_SIZE: usize = 4;
+pub const VERSION_SIZE: usize = 2;
+
+// anything bigger is a corrupt stream or a peer speaking something else entirely
+pub const MAX_FRAME_SIZE: usize = 1024 * 1024;
+
+pub type CodecResult<T> = Result<T, CodecError>;
+
+#[derive(Debug)]
+pub enum CodecError {
+  IoError(io::Error),
+  FrameSizeError(usize),
+  VersionError(u16),
+  JsonError(serde_json::Error),
+}
+
+impl CodecError {
+  // the other end went away, as opposed to sending something we cannot read
+  pub fn is_closed(&self) -> bool {
+    match self {
+      CodecError::IoError(err) => matches!(
+        err.kind(),
+        io::ErrorKind::UnexpectedEof | io::ErrorKind::BrokenPipe | io::ErrorKind::ConnectionReset
+      ),
+      _ => false,
+    }
+  }
+}
+
+impl fmt::Display for CodecError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      CodecError::IoError(err) => write!(f, "ipc io failed - {}", err),
+      CodecError::FrameSizeError(size) => {
+        write!(
+          f,
+          "invalid ipc frame size {}, expected {} to {}",
+          size, VERSION_SIZE, MAX_FRAME_SIZE
+        )
+      }
+      CodecError::VersionError(version) => {
+        write!(
+          f,
+          "ipc protocol version {} is not supported, expected {}",
+          version, PROTOCOL_VERSION
+        )
+      }
+      CodecError::JsonError(err) => write!(f, "invalid ipc message - {}", err),
+    }
+  }
+}
+
+impl error::Error for CodecError {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      CodecError::IoError(err) => Some(err),
+      CodecError::JsonError(err) => Some(err),
+      CodecError::FrameSizeError(_) | CodecError::VersionError(_) => None,
+    }
+  }
+}
+
+impl From<io::Error> for CodecError {
+  fn from(err: io::Error) -> Self {
+    CodecError::IoError(err)
+  }
+}
+
+impl From<serde_json::Error> for CodecError {
+  fn from(err: serde_json::Error) -> Self {
+    CodecError::JsonError(err)
+  }
+}
+
+// a whole frame, length prefix included
+pub fn encode<T: Serialize>(message: &T) -> CodecResult<Vec<u8>> {
+  let json = serde_json::to_vec(message)?;
+  let len = VERSION_SIZE + json.len();
+
+  if len > MAX_FRAME_SIZE {
+    return Err(CodecError::FrameSizeError(len));
+  }
+
+  let frame = (len as u32)
+    .to_le_bytes()
+    .into_iter()
+    .chain(PROTOCOL_VERSION.to_le_bytes())
+    .chain(json)
+    .collect();
+
+  Ok(frame)
+}
+
+// frame without its length prefix, ie. version and json
+pub fn decode<T: DeserializeOwned>(frame: &[u8]) -> CodecResult<T> {
+  if frame.len() < VERSION_SIZE || frame.len() > MAX_FRAME_SIZE {
+    return Err(CodecError::FrameSizeError(frame.len()));
+  }
+
+  let version = u16::from_le_bytes([frame[0], frame[1]]);
+
+  if version != PROTOCOL_VERSION {
+    return Err(CodecError::VersionError(version));
+  }
+
+  let message = serde_json::from_slice(&frame[VERSION_SIZE..])?;
+
+  Ok(message)
+}
+
+pub fn write_message<T: Serialize>(mut writer: impl Write, message: &T) -> CodecResult<()> {
+  let frame = encode(message)?;
+
+  writer.write_all(&frame)?;
+  writer.flush()?;
+
+  Ok(())
+}
+
+// blocks until a whole frame has arrived
+pub fn read_message<T: DeserializeOwned>(mut reader: impl Read) -> CodecResult<T> {
+  let mut len = [0; LEN_SIZE];
+
+  reader.read_exact(&mut len)?;
+
+  let len = u32::from_le_bytes(len) as usize;
+
+  // checked before allocating, the length could be anything
+  if !(VERSION_SIZE..=MAX_FRAME_SIZE).contains(&len) {
+    return Err(CodecError::FrameSizeError(len));
+  }
+
+  let mut frame = vec![0; len];
+
+  reader.read_exact(&mut frame)?;
+
+  decode(&frame)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    config::HookConfig,
+    message::{AppMessage, FrameTick, GameEvent, HookMessage, HookState, HookStatus, LogLevel, LogRecord},
+  };
+
+  fn round_trip<T: Serialize + DeserializeOwned>(message: &T) -> T {
+    let frame = encode(message).unwrap();
+
+    read_message(&frame[..]).unwrap()
+  }
+
+  // a frame with whatever length and version it is given, json included
+  fn frame(len: u32, version: u16, json: &[u8]) -> Vec<u8> {
+    [&len.to_le_bytes()[..], &version.to_le_bytes(), json].concat()
+  }
+
+  #[test]
+  fn round_trips_hook_messages() {
+    let messages = [
+      HookMessage::Hello {
+        pid: 1234,
+        version: "0.1.0".to_string(),
+      },
+      HookMessage::Event(GameEvent::LifeChanged { delta: -1 }),
+      HookMessage::Event(GameEvent::Graze),
+      HookMessage::Event(GameEvent::ItemCollected),
+      HookMessage::Frame(FrameTick {
+        frame: u64::MAX,
+        paused: true,
+      }),
+      HookMessage::Log(LogRecord {
+        level: LogLevel::Warn,
+        target: "hook::frame".to_string(),
+        message: "d3d device not found".to_string(),
+        timestamp_ms: 1_700_000_000_000,
+      }),
+      HookMessage::Status(HookStatus {
+        state: HookState::ShuttingDown,
+        hooks_enabled: false,
+        frame_clock_hooked: true,
+        callback_failures: 3,
+      }),
+    ];
+
+    for message in messages {
+      assert_eq!(round_trip(&message), message);
+    }
+  }
+
+  #[test]
+  fn round_trips_app_messages() {
+    let messages = [
+      AppMessage::ReloadConfig(HookConfig {
+        server_addr: Some("thvibe".to_string()),
+        ..HookConfig::default()
+      }),
+      AppMessage::StopAll,
+      AppMessage::Unhook,
+      AppMessage::QueryStatus,
+    ];
+
+    for message in messages {
+      assert_eq!(round_trip(&message), message);
+    }
+  }
+
+  #[test]
+  fn reads_frames_back_to_back() {
+    let mut stream = Vec::new();
+
+    write_message(&mut stream, &AppMessage::StopAll).unwrap();
+    write_message(&mut stream, &AppMessage::QueryStatus).unwrap();
+
+    let mut reader = &stream[..];
+
+    assert_eq!(read_message::<AppMessage>(&mut reader).unwrap(), AppMessage::StopAll);
+    assert_eq!(
+      read_message::<AppMessage>(&mut reader).unwrap(),
+      AppMessage::QueryStatus
+    );
+    assert!(read_message::<AppMessage>(&mut reader).unwrap_err().is_closed());
+  }
+
+  #[test]
+  fn rejects_other_versions() {
+    let json = br#"{"type":"stop_all"}"#;
+    let stream = frame((VERSION_SIZE + json.len()) as u32, PROTOCOL_VERSION + 1, json);
+
+    assert!(matches!(
+      read_message::<AppMessage>(&stream[..]),
+      Err(CodecError::VersionError(version)) if version == PROTOCOL_VERSION + 1
+    ));
+  }
+
+  #[test]
+  fn rejects_frame_sizes() {
+    for len in [0, VERSION_SIZE - 1, MAX_FRAME_SIZE + 1, u32::MAX as usize] {
+      // the length is checked before anything else is read
+      let stream = frame(len as u32, PROTOCOL_VERSION, b"");
+      let err = read_message::<AppMessage>(&stream[..]).unwrap_err();
+
+      assert!(matches!(err, CodecError::FrameSizeError(size) if size == len));
+      assert!(!err.is_closed());
+    }
+
+    let too_big = HookMessage::Log(LogRecord {
+      level: LogLevel::Info,
+      target: String::new(),
+      message: "x".repeat(MAX_FRAME_SIZE),
+      timestamp_ms: 0,
+    });
+
+    assert!(matches!(encode(&too_big), Err(CodecError::FrameSizeError(_))));
+  }
+
+  #[test]
+  fn truncated_streams_are_closed() {
+    let frame = encode(&AppMessage::QueryStatus).unwrap();
+
+    // nothing at all, part of the length, and part of the frame
+    for len in [0, LEN_SIZE - 1, frame.len() - 1] {
+      let err = read_message::<AppMessage>(&frame[..len]).unwrap_err();
+
+      assert!(err.is_closed(), "{} bytes - {}", len, err);
+    }
+  }
+
+  #[test]
+  fn invalid_json_is_not_closed() {
+    let json = br#"{"type":"launch_missiles"}"#;
+    let stream = frame((VERSION_SIZE + json.len()) as u32, PROTOCOL_VERSION, json);
+    let err = read_message::<AppMessage>(&stream[..]).unwrap_err();
+
+    assert!(matches!(err, CodecError::JsonError(_)));
+    assert!(!err.is_closed());
+  }
+}
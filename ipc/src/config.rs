@@ -0,0 +1,283 @@
+use std::{error, fmt, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::message::LogLevel;
+
+// blob layout: magic, version (u32 le), json length (u32 le), then the json itself
+// the header lets the hook tell how much to read from a bare pointer, and reject blobs from a mismatched app
+pub const BLOB_MAGIC: [u8; 4] = *b"THVB";
+pub const BLOB_VERSION: u32 = 1;
+pub const BLOB_HEADER_SIZE: usize = 12;
+
+pub type ConfigResult<T> = Result<T, ConfigError>;
+
+#[derive(Debug)]
+pub enum ConfigError {
+  MagicError([u8; 4]),
+  VersionError(u32),
+  TruncatedError { expected: usize, found: usize },
+  JsonError(serde_json::Error),
+}
+
+impl fmt::Display for ConfigError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ConfigError::MagicError(magic) => write!(f, "not a config blob, bad magic {:x?}", magic),
+      ConfigError::VersionError(version) => {
+        write!(
+          f,
+          "config blob version {} is not supported, expected {}",
+          version, BLOB_VERSION
+        )
+      }
+      ConfigError::TruncatedError { expected, found } => {
+        write!(
+          f,
+          "truncated config blob - expected {} bytes, found {}",
+          expected, found
+        )
+      }
+      ConfigError::JsonError(err) => write!(f, "invalid config json - {}", err),
+    }
+  }
+}
+
+impl error::Error for ConfigError {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    match self {
+      ConfigError::JsonError(err) => Some(err),
+      _ => None,
+    }
+  }
+}
+
+impl From<serde_json::Error> for ConfigError {
+  fn from(err: serde_json::Error) -> Self {
+    ConfigError::JsonError(err)
+  }
+}
+
+// everything the app tells hook.dll before it installs any hooks
+// missing fields take their defaults, so older apps keep working with newer hooks
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HookConfig {
+  // where logs go, next to the dll if none
+  pub log_dir: Option<PathBuf>,
+  // records below this level are dropped everywhere
+  pub log_level: LogLevel,
+  // opens a console in the game for the logs, which closes with it
+  pub log_console: bool,
+  // also forwards logs over ipc, for the app to show
+  pub log_to_app: bool,
+  // haptic profile to play events with
+  pub profile: String,
+  // where the app listens for the hook to connect, the hook runs standalone if none
+  pub server_addr: Option<String>,
+  pub haptics: HapticsMode,
+}
+
+// where the haptics runtime (tokio, buttplug and the bluetooth stack) runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HapticsMode {
+  // the hook forwards game events and the app drives the devices
+  // a crash or hang in the device stack cannot take the game down, and devices stay connected across game restarts
+  App,
+  // the hook drives the devices itself, only hooks built with the local-haptics feature can
+  InProcess,
+}
+
+impl Default for HookConfig {
+  fn default() -> Self {
+    Self {
+      log_dir: None,
+      log_level: LogLevel::Info,
+      log_console: false,
+      log_to_app: true,
+      profile: "default".to_string(),
+      server_addr: None,
+      haptics: HapticsMode::App,
+    }
+  }
+}
+
+impl HookConfig {
+  pub fn to_blob(&self) -> ConfigResult<Vec<u8>> {
+    let json = serde_json::to_vec(self)?;
+
+    let blob = BLOB_MAGIC
+      .iter()
+      .copied()
+      .chain(BLOB_VERSION.to_le_bytes())
+      .chain((json.len() as u32).to_le_bytes())
+      .chain(json)
+      .collect();
+
+    Ok(blob)
+  }
+
+  // blob must hold at least the whole header, trailing bytes past the json are ignored
+  pub fn from_blob(blob: &[u8]) -> ConfigResult<Self> {
+    let len = blob_len(blob)?;
+
+    if blob.len() < len {
+      return Err(ConfigError::TruncatedError {
+        expected: len,
+        found: blob.len(),
+      });
+    }
+
+    let config = serde_json::from_slice(&blob[BLOB_HEADER_SIZE..len])?;
+
+    Ok(config)
+  }
+}
+
+// total length of the blob, header included, from just its header
+pub fn blob_len(header: &[u8]) -> ConfigResult<usize> {
+  if header.len() < BLOB_HEADER_SIZE {
+    return Err(ConfigError::TruncatedError {
+      expected: BLOB_HEADER_SIZE,
+      found: header.len(),
+    });
+  }
+
+  let magic = [header[0], header[1], header[2], header[3]];
+
+  if magic != BLOB_MAGIC {
+    return Err(ConfigError::MagicError(magic));
+  }
+
+  let version = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+
+  if version != BLOB_VERSION {
+    return Err(ConfigError::VersionError(version));
+  }
+
+  let json_len = u32::from_le_bytes([header[8], header[9], header[10], header[11]]);
+
+  Ok(BLOB_HEADER_SIZE + json_len as usize)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  // a header with whatever magic, version and json length it is given
+  fn header(magic: [u8; 4], version: u32, json_len: u32) -> Vec<u8> {
+    [&magic[..], &version.to_le_bytes(), &json_len.to_le_bytes()].concat()
+  }
+
+  #[test]
+  fn round_trips_configs() {
+    let configs = [
+      HookConfig::default(),
+      HookConfig {
+        log_dir: Some(PathBuf::from(r"C:\games\th08\logs")),
+        log_level: LogLevel::Trace,
+        log_console: true,
+        log_to_app: false,
+        profile: "gentle".to_string(),
+        server_addr: Some("thvibe".to_string()),
+        haptics: HapticsMode::InProcess,
+      },
+    ];
+
+    for config in configs {
+      let blob = config.to_blob().unwrap();
+
+      assert_eq!(&blob[..4], &BLOB_MAGIC);
+      assert_eq!(blob_len(&blob).unwrap(), blob.len());
+      assert_eq!(HookConfig::from_blob(&blob).unwrap(), config);
+    }
+  }
+
+  #[test]
+  fn ignores_trailing_bytes() {
+    let config = HookConfig::default();
+    let mut blob = config.to_blob().unwrap();
+    let len = blob.len();
+
+    blob.extend_from_slice(b"garbage");
+
+    assert_eq!(blob_len(&blob).unwrap(), len);
+    assert_eq!(HookConfig::from_blob(&blob).unwrap(), config);
+  }
+
+  #[test]
+  fn missing_fields_take_defaults() {
+    let json = br#"{"profile":"gentle"}"#;
+    let blob = [&header(BLOB_MAGIC, BLOB_VERSION, json.len() as u32)[..], json].concat();
+
+    let config = HookConfig::from_blob(&blob).unwrap();
+
+    assert_eq!(
+      config,
+      HookConfig {
+        profile: "gentle".to_string(),
+        ..HookConfig::default()
+      }
+    );
+  }
+
+  #[test]
+  fn rejects_bad_magic() {
+    let blob = header(*b"MZ\x90\x00", BLOB_VERSION, 0);
+
+    assert!(matches!(blob_len(&blob), Err(ConfigError::MagicError(magic)) if &magic == b"MZ\x90\x00"));
+    assert!(matches!(HookConfig::from_blob(&blob), Err(ConfigError::MagicError(_))));
+  }
+
+  #[test]
+  fn rejects_other_versions() {
+    let blob = header(BLOB_MAGIC, BLOB_VERSION + 1, 0);
+
+    assert!(matches!(
+      blob_len(&blob),
+      Err(ConfigError::VersionError(version)) if version == BLOB_VERSION + 1
+    ));
+    assert!(matches!(
+      HookConfig::from_blob(&blob),
+      Err(ConfigError::VersionError(_))
+    ));
+  }
+
+  #[test]
+  fn rejects_truncated_headers() {
+    let blob = HookConfig::default().to_blob().unwrap();
+
+    for len in [0, 4, BLOB_HEADER_SIZE - 1] {
+      assert!(matches!(
+        blob_len(&blob[..len]),
+        Err(ConfigError::TruncatedError { expected: BLOB_HEADER_SIZE, found }) if found == len
+      ));
+      assert!(matches!(
+        HookConfig::from_blob(&blob[..len]),
+        Err(ConfigError::TruncatedError { .. })
+      ));
+    }
+  }
+
+  #[test]
+  fn rejects_truncated_json() {
+    let blob = HookConfig::default().to_blob().unwrap();
+
+    // the header alone, and all but the last byte
+    for len in [BLOB_HEADER_SIZE, blob.len() - 1] {
+      assert!(matches!(
+        HookConfig::from_blob(&blob[..len]),
+        Err(ConfigError::TruncatedError { expected, found }) if expected == blob.len() && found == len
+      ));
+    }
+  }
+
+  #[test]
+  fn rejects_invalid_json() {
+    let json = br#"{"haptics":"telepathy"}"#;
+    let blob = [&header(BLOB_MAGIC, BLOB_VERSION, json.len() as u32)[..], json].concat();
+
+    assert!(matches!(HookConfig::from_blob(&blob), Err(ConfigError::JsonError(_))));
+  }
+}
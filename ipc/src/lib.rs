@@ -0,0 +1,4 @@
+pub mod codec;
+pub mod config;
+pub mod message;
+pub mod transport;
@@ -0,0 +1,90 @@
+use serde::{Deserialize, Serialize};
+
+use crate::config::HookConfig;
+
+// hook to app
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum HookMessage {
+  // always the first message on a connection
+  Hello { pid: u32, version: String },
+  Event(GameEvent),
+  // sent every game frame, the clock haptic patterns run on
+  Frame(FrameTick),
+  Log(LogRecord),
+  Status(HookStatus),
+}
+
+// app to hook
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data", rename_all = "snake_case")]
+pub enum AppMessage {
+  // replaces the config the hook was injected with
+  ReloadConfig(HookConfig),
+  // stops every device, hooks stay in place
+  StopAll,
+  // removes every hook, the hook stays loaded until ejected
+  Unhook,
+  // asks for a Status message
+  QueryStatus,
+}
+
+// something that happened in the game
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum GameEvent {
+  // delta is what the game passed to its life update, negative on a hit
+  LifeChanged { delta: i32 },
+  Graze,
+  ItemCollected,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FrameTick {
+  // frames since the hook was loaded, counted by the game's render loop or, failing that, a wall clock
+  pub frame: u64,
+  // paused or in a menu, patterns hold where they are until it is false again
+  // always false until the hook knows where th08 keeps its pause flag
+  pub paused: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LogLevel {
+  Error,
+  Warn,
+  Info,
+  Debug,
+  Trace,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogRecord {
+  pub level: LogLevel,
+  // the module the record came from
+  pub target: String,
+  pub message: String,
+  // milliseconds since the unix epoch
+  pub timestamp_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookState {
+  Starting,
+  Ready,
+  ShuttingDown,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HookStatus {
+  pub state: HookState,
+  pub hooks_enabled: bool,
+  // false when frames come from a wall clock, because the game's render loop could not be hooked
+  // which for now is always, see D3D_DEVICE_OFFSET in the hook
+  #[serde(default)]
+  pub frame_clock_hooked: bool,
+  // panics and errors caught in hook callbacks since the hook was loaded
+  #[serde(default)]
+  pub callback_failures: u32,
+}
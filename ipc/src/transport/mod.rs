@@ -0,0 +1,194 @@
+use std::{
+  io::{self, Read, Write},
+  marker::PhantomData,
+};
+
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+  codec::{read_message, write_message, CodecResult},
+  message::{AppMessage, HookMessage},
+};
+
+#[cfg(unix)]
+mod unix;
+#[cfg(windows)]
+mod windows;
+
+#[cfg(unix)]
+use self::unix as sys;
+#[cfg(windows)]
+use self::windows as sys;
+
+// where the app listens when the config does not say otherwise
+// a pipe name on windows, a socket in the temp dir elsewhere
+pub const DEFAULT_ENDPOINT: &str = "thvibe";
+
+// the app's end, accepting connections from any number of hooks
+#[derive(Debug)]
+pub struct Listener(sys::Listener);
+
+impl Listener {
+  pub fn bind(endpoint: &str) -> io::Result<Self> {
+    sys::Listener::bind(endpoint).map(Self)
+  }
+
+  // blocks until the next hook connects
+  pub fn accept(&mut self) -> io::Result<Connection> {
+    self.0.accept().map(Connection)
+  }
+}
+
+// a byte stream between the app and one hook
+#[derive(Debug)]
+pub struct Connection(sys::Connection);
+
+impl Connection {
+  pub fn connect(endpoint: &str) -> io::Result<Self> {
+    sys::Connection::connect(endpoint).map(Self)
+  }
+
+  pub fn try_clone(&self) -> io::Result<Self> {
+    self.0.try_clone().map(Self)
+  }
+
+  // unblocks a recv on any clone of this connection, which then fails
+  // a recv that starts later can still block, so keep calling this until the reader is gone
+  pub fn shutdown(&self) -> io::Result<()> {
+    self.0.shutdown()
+  }
+
+  // typed halves that can be used from different threads
+  pub fn split<S, R>(self) -> io::Result<(Sender<S>, Receiver<R>)> {
+    let reader = self.try_clone()?;
+
+    Ok((
+      Sender {
+        connection: self,
+        message: PhantomData,
+      },
+      Receiver {
+        connection: reader,
+        message: PhantomData,
+      },
+    ))
+  }
+}
+
+impl Read for Connection {
+  fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+    self.0.read(buffer)
+  }
+}
+
+impl Write for Connection {
+  fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+    self.0.write(buffer)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.0.flush()
+  }
+}
+
+#[derive(Debug)]
+pub struct Sender<T> {
+  connection: Connection,
+  message: PhantomData<fn(T)>,
+}
+
+impl<T: Serialize> Sender<T> {
+  pub fn send(&mut self, message: &T) -> CodecResult<()> {
+    write_message(&mut self.connection, message)
+  }
+}
+
+#[derive(Debug)]
+pub struct Receiver<T> {
+  connection: Connection,
+  message: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> Receiver<T> {
+  // blocks until the next message, see CodecError::is_closed for telling a disconnect apart
+  pub fn recv(&mut self) -> CodecResult<T> {
+    read_message(&mut self.connection)
+  }
+}
+
+// the hook's view of a connection
+pub type HookSender = Sender<HookMessage>;
+pub type HookReceiver = Receiver<AppMessage>;
+
+// the app's view of a connection
+pub type AppSender = Sender<AppMessage>;
+pub type AppReceiver = Receiver<HookMessage>;
+
+#[cfg(test)]
+mod tests {
+  use std::{process, thread};
+
+  use super::*;
+  use crate::message::{FrameTick, HookMessage};
+
+  #[test]
+  fn loopback() {
+    // unique per test run, so runs in parallel do not take over each other's endpoint
+    let endpoint = format!("thvibe-test-{}", process::id());
+    let mut listener = Listener::bind(&endpoint).unwrap();
+
+    let hook = thread::spawn({
+      let endpoint = endpoint.clone();
+
+      move || {
+        let (mut sender, mut receiver): (HookSender, HookReceiver) =
+          Connection::connect(&endpoint).unwrap().split().unwrap();
+
+        sender
+          .send(&HookMessage::Hello {
+            pid: process::id(),
+            version: "test".to_string(),
+          })
+          .unwrap();
+
+        assert_eq!(receiver.recv().unwrap(), AppMessage::QueryStatus);
+
+        sender
+          .send(&HookMessage::Frame(FrameTick {
+            frame: 1,
+            paused: false,
+          }))
+          .unwrap();
+
+        assert_eq!(receiver.recv().unwrap(), AppMessage::Unhook);
+      }
+    });
+
+    let (mut sender, mut receiver): (AppSender, AppReceiver) = listener.accept().unwrap().split().unwrap();
+
+    assert_eq!(
+      receiver.recv().unwrap(),
+      HookMessage::Hello {
+        pid: process::id(),
+        version: "test".to_string(),
+      }
+    );
+
+    sender.send(&AppMessage::QueryStatus).unwrap();
+
+    assert_eq!(
+      receiver.recv().unwrap(),
+      HookMessage::Frame(FrameTick {
+        frame: 1,
+        paused: false
+      })
+    );
+
+    sender.send(&AppMessage::Unhook).unwrap();
+
+    hook.join().unwrap();
+
+    // the hook's end is gone with its thread
+    assert!(receiver.recv().unwrap_err().is_closed());
+  }
+}
@@ -0,0 +1,79 @@
+use std::{
+  env, fs,
+  io::{self, Read, Write},
+  net::Shutdown,
+  os::unix::net::{UnixListener, UnixStream},
+  path::PathBuf,
+};
+
+fn socket_path(endpoint: &str) -> PathBuf {
+  env::temp_dir().join(format!("{}.sock", endpoint))
+}
+
+#[derive(Debug)]
+pub struct Listener {
+  listener: UnixListener,
+  path: PathBuf,
+}
+
+impl Listener {
+  pub fn bind(endpoint: &str) -> io::Result<Self> {
+    let path = socket_path(endpoint);
+
+    // left behind by an app that did not exit cleanly, binding fails while it exists
+    if path.exists() {
+      fs::remove_file(&path)?;
+    }
+
+    let listener = UnixListener::bind(&path)?;
+
+    Ok(Self { listener, path })
+  }
+
+  pub fn accept(&mut self) -> io::Result<Connection> {
+    let (stream, _) = self.listener.accept()?;
+
+    Ok(Connection(stream))
+  }
+}
+
+impl Drop for Listener {
+  fn drop(&mut self) {
+    if let Err(err) = fs::remove_file(&self.path) {
+      eprintln!("{:?}", err);
+    }
+  }
+}
+
+#[derive(Debug)]
+pub struct Connection(UnixStream);
+
+impl Connection {
+  pub fn connect(endpoint: &str) -> io::Result<Self> {
+    UnixStream::connect(socket_path(endpoint)).map(Self)
+  }
+
+  pub fn try_clone(&self) -> io::Result<Self> {
+    self.0.try_clone().map(Self)
+  }
+
+  pub fn shutdown(&self) -> io::Result<()> {
+    self.0.shutdown(Shutdown::Both)
+  }
+}
+
+impl Read for Connection {
+  fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+    self.0.read(buffer)
+  }
+}
+
+impl Write for Connection {
+  fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+    self.0.write(buffer)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.0.flush()
+  }
+}
@@ -0,0 +1,80 @@
+use std::{
+  io::{self, Read, Write},
+  mem, thread,
+  time::{Duration, Instant},
+};
+
+use win::{error::code::ErrorCode, pipe::NamedPipe};
+
+// every server instance can be taken for a moment while the app creates the next one
+const BUSY_RETRY_INTERVAL: Duration = Duration::from_millis(20);
+const BUSY_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Debug)]
+pub struct Listener {
+  endpoint: String,
+  // always one instance waiting, so clients never find the pipe missing between accepts
+  next: NamedPipe,
+}
+
+impl Listener {
+  pub fn bind(endpoint: &str) -> io::Result<Self> {
+    let next = NamedPipe::create(endpoint)?;
+
+    Ok(Self {
+      endpoint: endpoint.to_string(),
+      next,
+    })
+  }
+
+  pub fn accept(&mut self) -> io::Result<Connection> {
+    self.next.connect()?;
+
+    let connected = mem::replace(&mut self.next, NamedPipe::create(&self.endpoint)?);
+
+    Ok(Connection(connected))
+  }
+}
+
+#[derive(Debug)]
+pub struct Connection(NamedPipe);
+
+impl Connection {
+  pub fn connect(endpoint: &str) -> io::Result<Self> {
+    let deadline = Instant::now() + BUSY_TIMEOUT;
+
+    loop {
+      match NamedPipe::open(endpoint) {
+        Ok(pipe) => return Ok(Self(pipe)),
+        Err(err) if err.kind() == ErrorCode::PipeBusy && Instant::now() < deadline => {
+          thread::sleep(BUSY_RETRY_INTERVAL)
+        }
+        Err(err) => return Err(err.into()),
+      }
+    }
+  }
+
+  pub fn try_clone(&self) -> io::Result<Self> {
+    Ok(Self(self.0.try_clone()?))
+  }
+
+  pub fn shutdown(&self) -> io::Result<()> {
+    Ok(self.0.cancel_io()?)
+  }
+}
+
+impl Read for Connection {
+  fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+    self.0.read(buffer)
+  }
+}
+
+impl Write for Connection {
+  fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+    self.0.write(buffer)
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    self.0.flush()
+  }
+}
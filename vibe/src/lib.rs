@@ -5,46 +5,124 @@ use buttplug::{
   server::ButtplugServer,
 };
 use futures::StreamExt;
-use tokio::sync::oneshot;
+use tokio::{
+  sync::{mpsc, oneshot},
+  task::JoinHandle,
+};
+
+pub mod pattern;
 
 type BoxResult<T> = Result<T, Box<dyn Error + Send + Sync + 'static>>;
 
+// what the command task sends to the device, in the order they were queued
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Command {
+  Set(f64),
+  Stop,
+}
+
 pub struct XBone {
   rt: tokio::runtime::Runtime,
   client: ButtplugClient,
-  xbone: Arc<ButtplugClientDevice>,
+  // every command goes through one task, so a vibrate can never land after a stop queued later
+  commands: mpsc::UnboundedSender<Command>,
+  command_task: JoinHandle<()>,
 }
 
 impl XBone {
   pub fn new(rt: tokio::runtime::Runtime, client: ButtplugClient, xbone: Arc<ButtplugClientDevice>) -> BoxResult<Self> {
-    Ok(Self { rt, client, xbone })
+    let (commands, receiver) = mpsc::unbounded_channel();
+    let command_task = rt.spawn(run_commands(xbone, receiver));
+
+    Ok(Self {
+      rt,
+      client,
+      commands,
+      command_task,
+    })
   }
 
   pub fn vibe(&self, intensity: f64, duration: Duration) -> BoxResult<()> {
-    let xbone = self.xbone.clone();
+    self.send(Command::Set(intensity))?;
 
-    self.rt.spawn(async move {
-      xbone.vibrate(VibrateCommand::Speed(intensity)).await.unwrap();
+    let commands = self.commands.clone();
 
+    // queued behind whatever was sent in the meantime, so only a later set is cut short by it
+    self.rt.spawn(async move {
       tokio::time::sleep(duration).await;
 
-      xbone.stop().await.unwrap();
+      let _ = commands.send(Command::Stop);
     });
 
     Ok(())
   }
 
+  // holds until the next call, for patterns timed by something other than the runtime's clock
+  pub fn set(&self, intensity: f64) -> BoxResult<()> {
+    if intensity <= 0.0 {
+      return self.stop();
+    }
+
+    self.send(Command::Set(intensity))
+  }
+
   pub fn stop(&self) -> BoxResult<()> {
-    let xbone = self.xbone.clone();
+    self.send(Command::Stop)
+  }
 
-    self.rt.spawn(async move {
-      xbone.stop().await.unwrap();
+  // only fails once the command task is gone, ie. the runtime is shutting down
+  fn send(&self, command: Command) -> BoxResult<()> {
+    self
+      .commands
+      .send(command)
+      .map_err(|_| "device command task stopped".into())
+  }
+
+  // stops every device and disconnects, then tears the runtime down
+  // tasks still running after timeout are abandoned rather than waited on
+  pub fn shutdown(self, timeout: Duration) -> BoxResult<()> {
+    let XBone {
+      rt,
+      client,
+      commands,
+      command_task,
+    } = self;
+
+    // the task ends once it has sent what was already queued, which has to happen before the devices are stopped
+    drop(commands);
+
+    let stopped = rt.block_on(async {
+      tokio::time::timeout(timeout, async {
+        let _ = command_task.await;
+
+        client.stop_all_devices().await?;
+        client.disconnect().await
+      })
+      .await
     });
 
+    rt.shutdown_timeout(timeout);
+
+    stopped??;
+
     Ok(())
   }
 }
 
+// a failed command is reported and the next one still goes out, the device may only have missed a beat
+async fn run_commands(xbone: Arc<ButtplugClientDevice>, mut commands: mpsc::UnboundedReceiver<Command>) {
+  while let Some(command) = commands.recv().await {
+    let sent = match command {
+      Command::Set(intensity) => xbone.vibrate(VibrateCommand::Speed(intensity)).await,
+      Command::Stop => xbone.stop().await,
+    };
+
+    if let Err(error) = sent {
+      eprintln!("{:?} errored: {}", command, error);
+    }
+  }
+}
+
 pub fn init_xbone() -> BoxResult<XBone> {
   let rt = tokio::runtime::Runtime::new()?;
 
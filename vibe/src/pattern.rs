@@ -0,0 +1,212 @@
+// effects timed in game frames rather than wall clock time
+// the scheduler only moves on when it is ticked, so patterns freeze while the game is paused and slow down with it
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Step {
+  pub intensity: f64,
+  pub frames: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Pattern {
+  steps: Vec<Step>,
+}
+
+impl Pattern {
+  pub fn new(steps: Vec<Step>) -> Self {
+    Self { steps }
+  }
+
+  pub fn constant(intensity: f64, frames: u64) -> Self {
+    Self::new(vec![Step { intensity, frames }])
+  }
+
+  pub fn frames(&self) -> u64 {
+    self.steps.iter().map(|step| step.frames).sum()
+  }
+
+  // none once the pattern is over
+  pub fn intensity_at(&self, frame: u64) -> Option<f64> {
+    let mut start = 0;
+
+    for step in &self.steps {
+      if frame < start + step.frames {
+        return Some(step.intensity);
+      }
+
+      start += step.frames;
+    }
+
+    None
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Playing {
+  pattern: Pattern,
+  // frames into the pattern, none until the first tick after it was started
+  position: Option<u64>,
+}
+
+// plays one pattern at a time against a frame clock, a new one replaces whatever is playing
+// tick and stop return the intensity to set the device to, only when it changes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scheduler {
+  playing: Option<Playing>,
+  last_frame: Option<u64>,
+  intensity: f64,
+}
+
+impl Scheduler {
+  pub fn play(&mut self, pattern: Pattern) {
+    self.playing = Some(Playing {
+      pattern,
+      position: None,
+    });
+  }
+
+  pub fn stop(&mut self) -> Option<f64> {
+    self.playing = None;
+
+    self.set(0.0)
+  }
+
+  // frames skipped between ticks still count, a paused frame never does
+  // the device is off while paused, and picks up where the pattern left off after
+  pub fn tick(&mut self, frame: u64, paused: bool) -> Option<f64> {
+    let elapsed = match self.last_frame {
+      Some(last_frame) => frame.saturating_sub(last_frame),
+      None => 1,
+    };
+
+    self.last_frame = Some(frame);
+
+    if paused {
+      return self.set(0.0);
+    }
+
+    let intensity = match &mut self.playing {
+      Some(playing) => {
+        let position = match playing.position {
+          Some(position) => position + elapsed,
+          None => 0,
+        };
+
+        playing.position = Some(position);
+        playing.pattern.intensity_at(position)
+      }
+      None => None,
+    };
+
+    match intensity {
+      Some(intensity) => self.set(intensity),
+      None => self.stop(),
+    }
+  }
+
+  fn set(&mut self, intensity: f64) -> Option<f64> {
+    if intensity == self.intensity {
+      return None;
+    }
+
+    self.intensity = intensity;
+
+    Some(intensity)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn steps() -> Pattern {
+    Pattern::new(vec![
+      Step {
+        intensity: 0.5,
+        frames: 2,
+      },
+      Step {
+        intensity: 1.0,
+        frames: 1,
+      },
+      Step {
+        intensity: 0.25,
+        frames: 3,
+      },
+    ])
+  }
+
+  #[test]
+  fn intensity_at() {
+    let pattern = steps();
+
+    assert_eq!(pattern.frames(), 6);
+    assert_eq!(
+      (0..7).map(|frame| pattern.intensity_at(frame)).collect::<Vec<_>>(),
+      [
+        Some(0.5),
+        Some(0.5),
+        Some(1.0),
+        Some(0.25),
+        Some(0.25),
+        Some(0.25),
+        None
+      ]
+    );
+
+    assert_eq!(Pattern::default().frames(), 0);
+    assert_eq!(Pattern::default().intensity_at(0), None);
+  }
+
+  #[test]
+  fn ticks_through_patterns() {
+    let mut scheduler = Scheduler::default();
+
+    // nothing playing and already off
+    assert_eq!(scheduler.tick(1, false), None);
+
+    scheduler.play(Pattern::constant(1.0, 3));
+
+    assert_eq!(scheduler.tick(2, false), Some(1.0));
+    // off while paused, without moving through the pattern
+    assert_eq!(scheduler.tick(3, true), Some(0.0));
+    assert_eq!(scheduler.tick(4, true), None);
+    assert_eq!(scheduler.tick(5, false), Some(1.0));
+    assert_eq!(scheduler.tick(6, false), None);
+    // three frames played, so the pattern is over
+    assert_eq!(scheduler.tick(7, false), Some(0.0));
+    assert_eq!(scheduler.tick(9, false), None);
+  }
+
+  #[test]
+  fn skipped_frames_count() {
+    let mut scheduler = Scheduler::default();
+
+    scheduler.tick(10, false);
+    scheduler.play(steps());
+
+    assert_eq!(scheduler.tick(11, false), Some(0.5));
+    // frames 12 and 13 were never ticked, which puts us 3 frames in
+    assert_eq!(scheduler.tick(14, false), Some(0.25));
+    assert_eq!(scheduler.tick(16, false), None);
+    assert_eq!(scheduler.tick(17, false), Some(0.0));
+  }
+
+  #[test]
+  fn play_replaces_and_stop_turns_off() {
+    let mut scheduler = Scheduler::default();
+
+    scheduler.play(Pattern::constant(0.5, 10));
+
+    assert_eq!(scheduler.tick(1, false), Some(0.5));
+
+    // starts from its first frame on the next tick
+    scheduler.play(steps());
+
+    assert_eq!(scheduler.tick(2, false), None);
+    assert_eq!(scheduler.tick(4, false), Some(1.0));
+    assert_eq!(scheduler.stop(), Some(0.0));
+    assert_eq!(scheduler.stop(), None);
+    assert_eq!(scheduler.tick(5, false), None);
+  }
+}
@@ -0,0 +1,34 @@
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Arch {
+  X86,
+  X64,
+}
+
+impl Arch {
+  // the arch this crate was compiled for
+  pub fn current() -> Self {
+    if cfg!(target_pointer_width = "64") {
+      Arch::X64
+    } else {
+      Arch::X86
+    }
+  }
+
+  pub fn pointer_size(&self) -> usize {
+    match self {
+      Arch::X86 => 4,
+      Arch::X64 => 8,
+    }
+  }
+}
+
+impl fmt::Display for Arch {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      Arch::X86 => write!(f, "32 bit"),
+      Arch::X64 => write!(f, "64 bit"),
+    }
+  }
+}
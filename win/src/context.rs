@@ -0,0 +1,214 @@
+use std::mem;
+
+use winapi::um::{
+  processthreadsapi::{GetThreadContext, SetThreadContext},
+  winbase::{Wow64GetThreadContext, Wow64SetThreadContext},
+  winnt::{CONTEXT, CONTEXT_FULL, HANDLE, WOW64_CONTEXT, WOW64_CONTEXT_FULL},
+};
+
+use crate::{
+  arch::Arch,
+  error::{code::ErrorCode, util::get_last_error, WinApiCodeResult, WinApiErrorCode},
+};
+
+// general purpose registers shared by x86 and x64, named without their size prefix
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Register {
+  Ax,
+  Bx,
+  Cx,
+  Dx,
+  Si,
+  Di,
+  Bp,
+  Sp,
+  Ip,
+  Flags,
+}
+
+// winapi does not align CONTEXT, but Get/SetThreadContext require 16 byte alignment on x64
+#[repr(C, align(16))]
+struct AlignedContext(CONTEXT);
+
+enum RawContext {
+  Native(Box<AlignedContext>),
+  Wow64(Box<WOW64_CONTEXT>),
+}
+
+// registers of a suspended thread, in the thread's own arch
+pub struct ThreadContext {
+  arch: Arch,
+  raw: RawContext,
+}
+
+impl ThreadContext {
+  // a thread of a process with the given arch, wow64 threads are read through their 32 bit context
+  // safety
+  // - handle must be a thread handle with THREAD_GET_CONTEXT, suspended unless it is the current thread
+  pub(crate) unsafe fn get(handle: HANDLE, arch: Arch) -> WinApiCodeResult<Self> {
+    if arch == Arch::current() {
+      let mut context = Box::new(AlignedContext(mem::zeroed()));
+      context.0.ContextFlags = CONTEXT_FULL;
+
+      if GetThreadContext(handle, &mut context.0) == 0 {
+        return Err(get_last_error("GetThreadContext"));
+      }
+
+      return Ok(Self {
+        arch,
+        raw: RawContext::Native(context),
+      });
+    }
+
+    // a 32 bit process cannot see the 64 bit context of another thread
+    if arch == Arch::X64 {
+      return Err(WinApiErrorCode::new(ErrorCode::NotSupported.code(), "GetThreadContext"));
+    }
+
+    let mut context: Box<WOW64_CONTEXT> = Box::new(mem::zeroed());
+    context.ContextFlags = WOW64_CONTEXT_FULL;
+
+    if Wow64GetThreadContext(handle, &mut *context) == 0 {
+      return Err(get_last_error("Wow64GetThreadContext"));
+    }
+
+    Ok(Self {
+      arch,
+      raw: RawContext::Wow64(context),
+    })
+  }
+
+  // safety
+  // - handle must be the thread this context was read from, with THREAD_SET_CONTEXT, and suspended
+  pub(crate) unsafe fn set(&self, handle: HANDLE) -> WinApiCodeResult<()> {
+    match &self.raw {
+      RawContext::Native(context) => {
+        if SetThreadContext(handle, &context.0) == 0 {
+          return Err(get_last_error("SetThreadContext"));
+        }
+      }
+      RawContext::Wow64(context) => {
+        if Wow64SetThreadContext(handle, &**context) == 0 {
+          return Err(get_last_error("Wow64SetThreadContext"));
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  pub fn arch(&self) -> Arch {
+    self.arch
+  }
+
+  pub fn reg(&self, reg: Register) -> usize {
+    match &self.raw {
+      RawContext::Native(context) => native_reg(&context.0, reg),
+      RawContext::Wow64(context) => {
+        (match reg {
+          Register::Ax => context.Eax,
+          Register::Bx => context.Ebx,
+          Register::Cx => context.Ecx,
+          Register::Dx => context.Edx,
+          Register::Si => context.Esi,
+          Register::Di => context.Edi,
+          Register::Bp => context.Ebp,
+          Register::Sp => context.Esp,
+          Register::Ip => context.Eip,
+          Register::Flags => context.EFlags,
+        }) as usize
+      }
+    }
+  }
+
+  // values are truncated to the thread's register size
+  pub fn set_reg(&mut self, reg: Register, value: usize) {
+    match &mut self.raw {
+      RawContext::Native(context) => set_native_reg(&mut context.0, reg, value),
+      RawContext::Wow64(context) => {
+        let field = match reg {
+          Register::Ax => &mut context.Eax,
+          Register::Bx => &mut context.Ebx,
+          Register::Cx => &mut context.Ecx,
+          Register::Dx => &mut context.Edx,
+          Register::Si => &mut context.Esi,
+          Register::Di => &mut context.Edi,
+          Register::Bp => &mut context.Ebp,
+          Register::Sp => &mut context.Esp,
+          Register::Ip => &mut context.Eip,
+          Register::Flags => &mut context.EFlags,
+        };
+
+        *field = value as u32;
+      }
+    }
+  }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn native_reg(context: &CONTEXT, reg: Register) -> usize {
+  (match reg {
+    Register::Ax => context.Rax,
+    Register::Bx => context.Rbx,
+    Register::Cx => context.Rcx,
+    Register::Dx => context.Rdx,
+    Register::Si => context.Rsi,
+    Register::Di => context.Rdi,
+    Register::Bp => context.Rbp,
+    Register::Sp => context.Rsp,
+    Register::Ip => context.Rip,
+    Register::Flags => context.EFlags as u64,
+  }) as usize
+}
+
+#[cfg(target_arch = "x86_64")]
+fn set_native_reg(context: &mut CONTEXT, reg: Register, value: usize) {
+  let value = value as u64;
+
+  match reg {
+    Register::Ax => context.Rax = value,
+    Register::Bx => context.Rbx = value,
+    Register::Cx => context.Rcx = value,
+    Register::Dx => context.Rdx = value,
+    Register::Si => context.Rsi = value,
+    Register::Di => context.Rdi = value,
+    Register::Bp => context.Rbp = value,
+    Register::Sp => context.Rsp = value,
+    Register::Ip => context.Rip = value,
+    Register::Flags => context.EFlags = value as u32,
+  }
+}
+
+#[cfg(target_arch = "x86")]
+fn native_reg(context: &CONTEXT, reg: Register) -> usize {
+  (match reg {
+    Register::Ax => context.Eax,
+    Register::Bx => context.Ebx,
+    Register::Cx => context.Ecx,
+    Register::Dx => context.Edx,
+    Register::Si => context.Esi,
+    Register::Di => context.Edi,
+    Register::Bp => context.Ebp,
+    Register::Sp => context.Esp,
+    Register::Ip => context.Eip,
+    Register::Flags => context.EFlags,
+  }) as usize
+}
+
+#[cfg(target_arch = "x86")]
+fn set_native_reg(context: &mut CONTEXT, reg: Register, value: usize) {
+  let value = value as u32;
+
+  match reg {
+    Register::Ax => context.Eax = value,
+    Register::Bx => context.Ebx = value,
+    Register::Cx => context.Ecx = value,
+    Register::Dx => context.Edx = value,
+    Register::Si => context.Esi = value,
+    Register::Di => context.Edi = value,
+    Register::Bp => context.Ebp = value,
+    Register::Sp => context.Esp = value,
+    Register::Ip => context.Eip = value,
+    Register::Flags => context.EFlags = value,
+  }
+}
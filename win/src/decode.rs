@@ -0,0 +1,738 @@
+// just enough of a 32 bit x86 decoder to move the instructions a hook patches over somewhere else
+// lengths cover the general purpose, x87, mmx and sse encodings, vex and evex are refused
+// x86 has no ip relative addressing outside of branches, so branches are the only thing relocation rewrites
+
+use std::{error, fmt};
+
+// longer is always a #UD
+pub const MAX_INSTRUCTION_SIZE: usize = 15;
+
+const JMP_REL_8: u8 = 0xeb;
+const JMP_REL_32: u8 = 0xe9;
+const CALL_REL_32: u8 = 0xe8;
+// after the 0x0f escape
+const JCC_REL_32: u8 = 0x80;
+
+pub type DecodeResult<T> = Result<T, DecodeError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+  TruncatedError { addr: usize },
+  OpcodeError { addr: usize, opcode: u8 },
+  // loop, jecxz and 16 bit branches have no rel32 form to widen to
+  BranchError { addr: usize },
+  // a branch from one stolen instruction into another, which would land on the jmp once patched
+  InternalBranchError { addr: usize, target: usize },
+}
+
+impl fmt::Display for DecodeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      DecodeError::TruncatedError { addr } => write!(f, "instruction at {:#x} runs past the end of the code", addr),
+      DecodeError::OpcodeError { addr, opcode } => {
+        write!(f, "unsupported opcode {:#04x} in instruction at {:#x}", opcode, addr)
+      }
+      DecodeError::BranchError { addr } => write!(f, "branch at {:#x} cannot be relocated", addr),
+      DecodeError::InternalBranchError { addr, target } => write!(
+        f,
+        "branch at {:#x} targets {:#x}, inside the instructions being relocated",
+        addr, target
+      ),
+    }
+  }
+}
+
+impl error::Error for DecodeError {
+  fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+    None
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BranchKind {
+  Jmp,
+  Call,
+  // the low nibble of the opcode, shared by the rel8 and rel32 forms
+  Jcc(u8),
+  // loop, loope, loopne and jecxz
+  Loop,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Branch {
+  pub kind: BranchKind,
+  // absolute, worked out from where the instruction was decoded
+  pub target: usize,
+  // a 16 bit displacement, from an operand size prefix
+  pub short_operand: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+  pub len: usize,
+  // only for relative branches
+  pub branch: Option<Branch>,
+}
+
+// what comes after the opcode, besides a modrm
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Imm {
+  None,
+  Imm8,
+  Imm16,
+  // 4 bytes, or 2 with an operand size prefix
+  ImmZ,
+  // enter
+  Imm16Imm8,
+  // a far pointer, ImmZ plus a selector
+  Far,
+  // an address the size of the address size
+  Moffs,
+  // group 3 test only takes an immediate for /0 and /1
+  Group3(Imm8OrZ),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Imm8OrZ {
+  Imm8,
+  ImmZ,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Operands {
+  modrm: bool,
+  imm: Imm,
+}
+
+const fn ops(modrm: bool, imm: Imm) -> Option<Operands> {
+  Some(Operands { modrm, imm })
+}
+
+// decodes the instruction at the start of code, addr is where code lives and only matters for branches
+pub fn decode(code: &[u8], addr: usize) -> DecodeResult<Instruction> {
+  let mut cursor = Cursor { code, addr, pos: 0 };
+
+  let mut operand_size_16 = false;
+  let mut address_size_16 = false;
+
+  let opcode = loop {
+    match cursor.byte()? {
+      0x66 => operand_size_16 = true,
+      0x67 => address_size_16 = true,
+      // lock, rep and segment overrides
+      0xf0 | 0xf2 | 0xf3 | 0x26 | 0x2e | 0x36 | 0x3e | 0x64 | 0x65 => {}
+      opcode => break opcode,
+    }
+  };
+
+  let mut branch_kind = None;
+
+  let operands = if opcode == 0x0f {
+    let opcode = cursor.byte()?;
+
+    match opcode {
+      0x38 => {
+        cursor.byte()?;
+
+        ops(true, Imm::None)
+      }
+      0x3a => {
+        cursor.byte()?;
+
+        ops(true, Imm::Imm8)
+      }
+      0x80..=0x8f => {
+        branch_kind = Some(BranchKind::Jcc(opcode & 0x0f));
+
+        two_byte_operands(opcode)
+      }
+      opcode => two_byte_operands(opcode),
+    }
+    .ok_or(DecodeError::OpcodeError { addr, opcode })?
+  } else {
+    branch_kind = match opcode {
+      0x70..=0x7f => Some(BranchKind::Jcc(opcode & 0x0f)),
+      0xe0..=0xe3 => Some(BranchKind::Loop),
+      CALL_REL_32 => Some(BranchKind::Call),
+      JMP_REL_32 | JMP_REL_8 => Some(BranchKind::Jmp),
+      _ => None,
+    };
+
+    // c4, c5 and 62 are les, lds and bound unless the modrm is a register, which makes them vex and evex
+    if matches!(opcode, 0xc4 | 0xc5 | 0x62) && cursor.peek()? >> 6 == 0b11 {
+      return Err(DecodeError::OpcodeError { addr, opcode });
+    }
+
+    one_byte_operands(opcode).ok_or(DecodeError::OpcodeError { addr, opcode })?
+  };
+
+  let mut reg = 0;
+
+  if operands.modrm {
+    reg = cursor.modrm(address_size_16)?;
+  }
+
+  let imm_z = if operand_size_16 { 2 } else { 4 };
+
+  let imm_size = match operands.imm {
+    Imm::None => 0,
+    Imm::Imm8 => 1,
+    Imm::Imm16 => 2,
+    Imm::ImmZ => imm_z,
+    Imm::Imm16Imm8 => 3,
+    Imm::Far => imm_z + 2,
+    Imm::Moffs => {
+      if address_size_16 {
+        2
+      } else {
+        4
+      }
+    }
+    Imm::Group3(imm) => match (reg, imm) {
+      (0 | 1, Imm8OrZ::Imm8) => 1,
+      (0 | 1, Imm8OrZ::ImmZ) => imm_z,
+      _ => 0,
+    },
+  };
+
+  let imm_start = cursor.pos;
+
+  cursor.skip(imm_size)?;
+
+  let len = cursor.pos;
+
+  if len > MAX_INSTRUCTION_SIZE {
+    return Err(DecodeError::OpcodeError { addr, opcode });
+  }
+
+  let branch = branch_kind.map(|kind| {
+    let displacement = match imm_size {
+      1 => code[imm_start] as i8 as isize,
+      2 => i16::from_le_bytes([code[imm_start], code[imm_start + 1]]) as isize,
+      _ => i32::from_le_bytes([
+        code[imm_start],
+        code[imm_start + 1],
+        code[imm_start + 2],
+        code[imm_start + 3],
+      ]) as isize,
+    };
+
+    Branch {
+      kind,
+      target: (addr + len).wrapping_add_signed(displacement),
+      short_operand: imm_size == 2,
+    }
+  });
+
+  Ok(Instruction { len, branch })
+}
+
+// copies whole instructions from code until at least min_len bytes are covered, for them to run at to instead of from
+// relative branches are widened to rel32 and pointed back at their original targets
+// returns the relocated code and how many bytes of the original it covers
+pub fn relocate(code: &[u8], from: usize, to: usize, min_len: usize) -> DecodeResult<(Vec<u8>, usize)> {
+  let mut instructions = Vec::new();
+  let mut len = 0;
+
+  while len < min_len {
+    let instruction = decode(&code[len..], from + len)?;
+
+    instructions.push((len, instruction));
+    len += instruction.len;
+  }
+
+  let stolen = from..from + len;
+  let mut relocated = Vec::with_capacity(len);
+
+  for (offset, instruction) in instructions {
+    let addr = from + offset;
+    let bytes = &code[offset..offset + instruction.len];
+
+    let branch = match instruction.branch {
+      Some(branch) => branch,
+      None => {
+        relocated.extend_from_slice(bytes);
+
+        continue;
+      }
+    };
+
+    // a branch to the first stolen instruction still works, it lands on the patch
+    if stolen.contains(&branch.target) && branch.target != from {
+      return Err(DecodeError::InternalBranchError {
+        addr,
+        target: branch.target,
+      });
+    }
+
+    let op = match branch.kind {
+      _ if branch.short_operand => return Err(DecodeError::BranchError { addr }),
+      BranchKind::Jmp => vec![JMP_REL_32],
+      BranchKind::Call => vec![CALL_REL_32],
+      BranchKind::Jcc(condition) => vec![0x0f, JCC_REL_32 | condition],
+      BranchKind::Loop => return Err(DecodeError::BranchError { addr }),
+    };
+
+    // prefixes on a branch are at most branch hints, which are dropped
+    let end = to + relocated.len() + op.len() + 4;
+    let displacement = branch.target.wrapping_sub(end) as u32;
+
+    relocated.extend_from_slice(&op);
+    relocated.extend_from_slice(&displacement.to_le_bytes());
+  }
+
+  Ok((relocated, len))
+}
+
+struct Cursor<'a> {
+  code: &'a [u8],
+  addr: usize,
+  pos: usize,
+}
+
+impl Cursor<'_> {
+  fn peek(&self) -> DecodeResult<u8> {
+    self
+      .code
+      .get(self.pos)
+      .copied()
+      .ok_or(DecodeError::TruncatedError { addr: self.addr })
+  }
+
+  fn byte(&mut self) -> DecodeResult<u8> {
+    let byte = self.peek()?;
+
+    self.pos += 1;
+
+    Ok(byte)
+  }
+
+  fn skip(&mut self, len: usize) -> DecodeResult<()> {
+    if self.pos + len > self.code.len() {
+      return Err(DecodeError::TruncatedError { addr: self.addr });
+    }
+
+    self.pos += len;
+
+    Ok(())
+  }
+
+  // skips the modrm, sib and displacement, returning the reg field
+  fn modrm(&mut self, address_size_16: bool) -> DecodeResult<u8> {
+    let modrm = self.byte()?;
+
+    let mode = modrm >> 6;
+    let reg = (modrm >> 3) & 0b111;
+    let rm = modrm & 0b111;
+
+    if mode == 0b11 {
+      return Ok(reg);
+    }
+
+    let displacement = if address_size_16 {
+      match (mode, rm) {
+        (0b00, 0b110) => 2,
+        (0b00, _) => 0,
+        (0b01, _) => 1,
+        _ => 2,
+      }
+    } else {
+      // a sib with no base register is followed by a disp32
+      let sib_base = if rm == 0b100 { self.byte()? & 0b111 } else { 0 };
+
+      match (mode, rm) {
+        (0b00, 0b101) => 4,
+        (0b00, 0b100) if sib_base == 0b101 => 4,
+        (0b00, _) => 0,
+        (0b01, _) => 1,
+        _ => 4,
+      }
+    };
+
+    self.skip(displacement)?;
+
+    Ok(reg)
+  }
+}
+
+fn one_byte_operands(opcode: u8) -> Option<Operands> {
+  match opcode {
+    // add, or, adc, sbb, and, sub, xor, cmp
+    0x00..=0x3f => match opcode & 0x07 {
+      0x00..=0x03 => ops(true, Imm::None),
+      0x04 => ops(false, Imm::Imm8),
+      0x05 => ops(false, Imm::ImmZ),
+      // push and pop segment, daa, das, aaa, aas
+      // the escape and prefixes never get here
+      _ => ops(false, Imm::None),
+    },
+    // inc, dec, push, pop, pushad, popad
+    0x40..=0x61 => ops(false, Imm::None),
+    // bound, arpl
+    0x62 | 0x63 => ops(true, Imm::None),
+    0x68 => ops(false, Imm::ImmZ),
+    0x69 => ops(true, Imm::ImmZ),
+    0x6a => ops(false, Imm::Imm8),
+    0x6b => ops(true, Imm::Imm8),
+    // ins, outs
+    0x6c..=0x6f => ops(false, Imm::None),
+    // jcc rel8
+    0x70..=0x7f => ops(false, Imm::Imm8),
+    0x80 | 0x82 | 0x83 => ops(true, Imm::Imm8),
+    0x81 => ops(true, Imm::ImmZ),
+    // test, xchg, mov, lea, pop
+    0x84..=0x8f => ops(true, Imm::None),
+    // xchg, cwde, cdq
+    0x90..=0x99 => ops(false, Imm::None),
+    // call far
+    0x9a => ops(false, Imm::Far),
+    // fwait, pushfd, popfd, sahf, lahf
+    0x9b..=0x9f => ops(false, Imm::None),
+    0xa0..=0xa3 => ops(false, Imm::Moffs),
+    // movs, cmps
+    0xa4..=0xa7 => ops(false, Imm::None),
+    0xa8 => ops(false, Imm::Imm8),
+    0xa9 => ops(false, Imm::ImmZ),
+    // stos, lods, scas
+    0xaa..=0xaf => ops(false, Imm::None),
+    0xb0..=0xb7 => ops(false, Imm::Imm8),
+    0xb8..=0xbf => ops(false, Imm::ImmZ),
+    // shifts by imm8
+    0xc0 | 0xc1 => ops(true, Imm::Imm8),
+    // ret imm16
+    0xc2 => ops(false, Imm::Imm16),
+    0xc3 => ops(false, Imm::None),
+    // les, lds
+    0xc4 | 0xc5 => ops(true, Imm::None),
+    0xc6 => ops(true, Imm::Imm8),
+    0xc7 => ops(true, Imm::ImmZ),
+    0xc8 => ops(false, Imm::Imm16Imm8),
+    // leave
+    0xc9 => ops(false, Imm::None),
+    0xca => ops(false, Imm::Imm16),
+    // retf, int3
+    0xcb | 0xcc => ops(false, Imm::None),
+    0xcd => ops(false, Imm::Imm8),
+    // into, iretd
+    0xce | 0xcf => ops(false, Imm::None),
+    // shifts by 1 and cl
+    0xd0..=0xd3 => ops(true, Imm::None),
+    // aam, aad
+    0xd4 | 0xd5 => ops(false, Imm::Imm8),
+    // salc, xlat
+    0xd6 | 0xd7 => ops(false, Imm::None),
+    // x87
+    0xd8..=0xdf => ops(true, Imm::None),
+    // loop, jecxz, in, out
+    0xe0..=0xe7 => ops(false, Imm::Imm8),
+    // call rel32, jmp rel32
+    0xe8 | 0xe9 => ops(false, Imm::ImmZ),
+    0xea => ops(false, Imm::Far),
+    0xeb => ops(false, Imm::Imm8),
+    // in, out with dx
+    0xec..=0xef => ops(false, Imm::None),
+    // int1, hlt, cmc
+    0xf1 | 0xf4 | 0xf5 => ops(false, Imm::None),
+    0xf6 => ops(true, Imm::Group3(Imm8OrZ::Imm8)),
+    0xf7 => ops(true, Imm::Group3(Imm8OrZ::ImmZ)),
+    // clc, stc, cli, sti, cld, std
+    0xf8..=0xfd => ops(false, Imm::None),
+    // inc, dec, call, jmp, push
+    0xfe | 0xff => ops(true, Imm::None),
+    _ => None,
+  }
+}
+
+// after the 0x0f escape, 0x38 and 0x3a are handled by decode
+fn two_byte_operands(opcode: u8) -> Option<Operands> {
+  match opcode {
+    // sldt, lgdt, lar, lsl
+    0x00..=0x03 => ops(true, Imm::None),
+    // syscall, clts, sysret, invd, wbinvd, ud2
+    0x05..=0x09 | 0x0b => ops(false, Imm::None),
+    // prefetch
+    0x0d => ops(true, Imm::None),
+    // femms
+    0x0e => ops(false, Imm::None),
+    // 3dnow, the opcode comes last
+    0x0f => ops(true, Imm::Imm8),
+    // sse moves, prefetch and hint nops, mov to and from control and debug registers
+    0x10..=0x23 | 0x28..=0x2f => ops(true, Imm::None),
+    // wrmsr, rdtsc, rdmsr, rdpmc, sysenter, sysexit, getsec
+    0x30..=0x35 | 0x37 => ops(false, Imm::None),
+    // cmov, sse, mmx
+    0x40..=0x6f => ops(true, Imm::None),
+    // pshuf and shifts by imm8
+    0x70..=0x73 => ops(true, Imm::Imm8),
+    0x74..=0x76 => ops(true, Imm::None),
+    // emms
+    0x77 => ops(false, Imm::None),
+    // vmread, vmwrite
+    0x78 | 0x79 | 0x7c..=0x7f => ops(true, Imm::None),
+    // jcc rel32
+    0x80..=0x8f => ops(false, Imm::ImmZ),
+    // setcc
+    0x90..=0x9f => ops(true, Imm::None),
+    // push fs, pop fs, cpuid, push gs, pop gs, rsm
+    0xa0..=0xa2 | 0xa8..=0xaa => ops(false, Imm::None),
+    0xa3 | 0xa5 | 0xab | 0xad..=0xaf => ops(true, Imm::None),
+    // shld, shrd
+    0xa4 | 0xac => ops(true, Imm::Imm8),
+    // cmpxchg, btr, movzx, popcnt, bsf, bsr, movsx
+    0xb0..=0xb9 | 0xbb..=0xbf => ops(true, Imm::None),
+    // bt group
+    0xba => ops(true, Imm::Imm8),
+    0xc0 | 0xc1 | 0xc3 | 0xc7 => ops(true, Imm::None),
+    // cmpps, pinsrw, pextrw, shufps
+    0xc2 | 0xc4..=0xc6 => ops(true, Imm::Imm8),
+    // bswap
+    0xc8..=0xcf => ops(false, Imm::None),
+    // mmx and sse, ud0
+    0xd0..=0xff => ops(true, Imm::None),
+    _ => None,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const ADDR: usize = 0x1000;
+
+  fn len(code: &[u8]) -> usize {
+    decode(code, ADDR).unwrap().len
+  }
+
+  fn branch(code: &[u8]) -> Branch {
+    decode(code, ADDR).unwrap().branch.unwrap()
+  }
+
+  #[test]
+  fn decodes_prologues() {
+    // push ebp
+    assert_eq!(len(&[0x55]), 1);
+    // mov ebp, esp
+    assert_eq!(len(&[0x8b, 0xec]), 2);
+    // sub esp, 0x10
+    assert_eq!(len(&[0x83, 0xec, 0x10]), 3);
+    // sub esp, 0x100
+    assert_eq!(len(&[0x81, 0xec, 0x00, 0x01, 0x00, 0x00]), 6);
+    // mov edi, edi
+    assert_eq!(len(&[0x8b, 0xff]), 2);
+
+    assert_eq!(
+      decode(&[0x55, 0x8b, 0xec], ADDR),
+      Ok(Instruction { len: 1, branch: None })
+    );
+  }
+
+  #[test]
+  fn decodes_prefixes() {
+    // mov ax, 0x1234
+    assert_eq!(len(&[0x66, 0xb8, 0x34, 0x12]), 4);
+    // mov eax, fs:[0]
+    assert_eq!(len(&[0x64, 0xa1, 0x00, 0x00, 0x00, 0x00]), 6);
+    // mov eax, [0x1234] with a 16 bit address
+    assert_eq!(len(&[0x67, 0xa1, 0x34, 0x12]), 4);
+    // rep movsd
+    assert_eq!(len(&[0xf3, 0xa5]), 2);
+    // lock cmpxchg [esi], ecx
+    assert_eq!(len(&[0xf0, 0x0f, 0xb1, 0x0e]), 4);
+    // test ax, 0x1234
+    assert_eq!(len(&[0x66, 0xf7, 0xc0, 0x34, 0x12]), 5);
+  }
+
+  #[test]
+  fn decodes_operand_forms() {
+    // mov eax, ecx
+    assert_eq!(len(&[0x8b, 0xc1]), 2);
+    // mov eax, [ecx]
+    assert_eq!(len(&[0x8b, 0x01]), 2);
+    // mov eax, [ebp + 8]
+    assert_eq!(len(&[0x8b, 0x45, 0x08]), 3);
+    // mov eax, [ebp + 0x100]
+    assert_eq!(len(&[0x8b, 0x85, 0x00, 0x01, 0x00, 0x00]), 6);
+    // mov eax, [0x12345678]
+    assert_eq!(len(&[0x8b, 0x05, 0x78, 0x56, 0x34, 0x12]), 6);
+    // mov eax, [esp]
+    assert_eq!(len(&[0x8b, 0x04, 0x24]), 3);
+    // mov eax, [esp + 4]
+    assert_eq!(len(&[0x8b, 0x44, 0x24, 0x04]), 4);
+    // mov eax, [eax * 4 + 0x12345678], a sib with no base
+    assert_eq!(len(&[0x8b, 0x04, 0x85, 0x78, 0x56, 0x34, 0x12]), 7);
+    // mov eax, [bp + 2]
+    assert_eq!(len(&[0x67, 0x8b, 0x46, 0x02]), 4);
+    // mov eax, [0x1234] through a 16 bit modrm
+    assert_eq!(len(&[0x67, 0x8b, 0x06, 0x34, 0x12]), 5);
+    // mov dword [ebp - 4], 0
+    assert_eq!(len(&[0xc7, 0x45, 0xfc, 0x00, 0x00, 0x00, 0x00]), 7);
+  }
+
+  #[test]
+  fn decodes_group3_immediates() {
+    // test al, 1
+    assert_eq!(len(&[0xf6, 0xc0, 0x01]), 3);
+    // not al
+    assert_eq!(len(&[0xf6, 0xd0]), 2);
+    // test eax, 1
+    assert_eq!(len(&[0xf7, 0xc0, 0x01, 0x00, 0x00, 0x00]), 6);
+    // neg eax
+    assert_eq!(len(&[0xf7, 0xd8]), 2);
+  }
+
+  #[test]
+  fn decodes_two_byte_opcodes() {
+    // nop dword [eax + eax]
+    assert_eq!(len(&[0x0f, 0x1f, 0x44, 0x00, 0x00]), 5);
+    // pshufb mm0, mm1
+    assert_eq!(len(&[0x0f, 0x38, 0x00, 0xc1]), 4);
+    // palignr xmm0, xmm1, 8
+    assert_eq!(len(&[0x66, 0x0f, 0x3a, 0x0f, 0xc1, 0x08]), 6);
+    // movzx eax, byte [ecx]
+    assert_eq!(len(&[0x0f, 0xb6, 0x01]), 3);
+  }
+
+  #[test]
+  fn refuses_vex() {
+    // vzeroupper
+    assert_eq!(
+      decode(&[0xc5, 0xf8, 0x77], ADDR),
+      Err(DecodeError::OpcodeError {
+        addr: ADDR,
+        opcode: 0xc5
+      })
+    );
+    // les eax, [esi] is still fine
+    assert_eq!(len(&[0xc4, 0x06]), 2);
+  }
+
+  #[test]
+  fn rejects_bad_instructions() {
+    assert_eq!(decode(&[], ADDR), Err(DecodeError::TruncatedError { addr: ADDR }));
+    assert_eq!(decode(&[0x8b], ADDR), Err(DecodeError::TruncatedError { addr: ADDR }));
+    assert_eq!(
+      decode(&[0xe8, 0x00, 0x00], ADDR),
+      Err(DecodeError::TruncatedError { addr: ADDR })
+    );
+    assert_eq!(
+      decode(&[0x0f, 0x04], ADDR),
+      Err(DecodeError::OpcodeError {
+        addr: ADDR,
+        opcode: 0x04
+      })
+    );
+
+    let mut too_long = vec![0x66; 14];
+
+    too_long.extend_from_slice(&[0xb8, 0x34, 0x12]);
+
+    assert_eq!(
+      decode(&too_long, ADDR),
+      Err(DecodeError::OpcodeError {
+        addr: ADDR,
+        opcode: 0xb8
+      })
+    );
+  }
+
+  #[test]
+  fn decodes_branches() {
+    assert_eq!(
+      branch(&[0xeb, 0xfe]),
+      Branch {
+        kind: BranchKind::Jmp,
+        target: ADDR,
+        short_operand: false,
+      }
+    );
+    assert_eq!(
+      branch(&[0xe8, 0x10, 0x00, 0x00, 0x00]),
+      Branch {
+        kind: BranchKind::Call,
+        target: ADDR + 0x15,
+        short_operand: false,
+      }
+    );
+    assert_eq!(
+      branch(&[0x0f, 0x85, 0xfb, 0xff, 0xff, 0xff]),
+      Branch {
+        kind: BranchKind::Jcc(5),
+        target: ADDR + 1,
+        short_operand: false,
+      }
+    );
+    assert_eq!(
+      branch(&[0x66, 0xe9, 0x10, 0x00]),
+      Branch {
+        kind: BranchKind::Jmp,
+        target: ADDR + 0x14,
+        short_operand: true,
+      }
+    );
+    assert_eq!(branch(&[0xe2, 0x10]).kind, BranchKind::Loop);
+
+    // an indirect call is not relative
+    assert_eq!(decode(&[0xff, 0xd0], ADDR), Ok(Instruction { len: 2, branch: None }));
+  }
+
+  #[test]
+  fn relocates_whole_instructions() {
+    let code = [0x55, 0x8b, 0xec, 0x83, 0xec, 0x10, 0x90];
+
+    assert_eq!(relocate(&code, ADDR, 0x5000, 5), Ok((code[..6].to_vec(), 6)));
+  }
+
+  #[test]
+  fn widens_short_branches() {
+    // je 0x1013 moved to 0x5001
+    let code = [0x55, 0x74, 0x10, 0x8b, 0xec, 0x90, 0x90];
+
+    assert_eq!(
+      relocate(&code, ADDR, 0x5000, 5),
+      Ok((vec![0x55, 0x0f, 0x84, 0x0c, 0xc0, 0xff, 0xff, 0x8b, 0xec], 5))
+    );
+
+    // jmp 0x1012
+    assert_eq!(
+      relocate(&[0xeb, 0x10], ADDR, 0x5000, 2),
+      Ok((vec![0xe9, 0x0d, 0xc0, 0xff, 0xff], 2))
+    );
+
+    // call 0x1005
+    assert_eq!(
+      relocate(&[0xe8, 0x00, 0x00, 0x00, 0x00], ADDR, 0x5000, 5),
+      Ok((vec![0xe8, 0x00, 0xc0, 0xff, 0xff], 5))
+    );
+  }
+
+  #[test]
+  fn allows_branches_to_the_start() {
+    // jmp back to the push, which is where the patch will be
+    let code = [0x55, 0xeb, 0xfd];
+
+    assert_eq!(
+      relocate(&code, ADDR, 0x5000, 3),
+      Ok((vec![0x55, 0xe9, 0xfa, 0xbf, 0xff, 0xff], 3))
+    );
+  }
+
+  #[test]
+  fn rejects_unrelocatable_branches() {
+    assert_eq!(
+      relocate(&[0xe2, 0x10, 0x90], ADDR, 0x5000, 2),
+      Err(DecodeError::BranchError { addr: ADDR })
+    );
+    assert_eq!(
+      relocate(&[0x66, 0xe9, 0x10, 0x00], ADDR, 0x5000, 4),
+      Err(DecodeError::BranchError { addr: ADDR })
+    );
+    // je over the push, into the middle of what is being stolen
+    assert_eq!(
+      relocate(&[0x74, 0x00, 0x55], ADDR, 0x5000, 3),
+      Err(DecodeError::InternalBranchError {
+        addr: ADDR,
+        target: ADDR + 2,
+      })
+    );
+  }
+}
@@ -0,0 +1,114 @@
+// the windows error codes we run into, with their system messages for when FormatMessageW is not available
+macro_rules! error_codes {
+  ($($name:ident = $code:literal => $message:literal,)*) => {
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub enum ErrorCode {
+      $($name,)*
+      Other(u32),
+    }
+
+    impl ErrorCode {
+      pub fn code(&self) -> u32 {
+        match self {
+          $(ErrorCode::$name => $code,)*
+          ErrorCode::Other(code) => *code,
+        }
+      }
+
+      pub fn description(&self) -> Option<&'static str> {
+        match self {
+          $(ErrorCode::$name => Some($message),)*
+          ErrorCode::Other(_) => None,
+        }
+      }
+    }
+
+    #[cfg(test)]
+    const KNOWN_CODES: &[ErrorCode] = &[$(ErrorCode::$name,)*];
+
+    impl From<u32> for ErrorCode {
+      fn from(code: u32) -> Self {
+        match code {
+          $($code => ErrorCode::$name,)*
+          _ => ErrorCode::Other(code),
+        }
+      }
+    }
+  };
+}
+
+error_codes! {
+  Success = 0 => "The operation completed successfully.",
+  InvalidFunction = 1 => "Incorrect function.",
+  FileNotFound = 2 => "The system cannot find the file specified.",
+  PathNotFound = 3 => "The system cannot find the path specified.",
+  AccessDenied = 5 => "Access is denied.",
+  InvalidHandle = 6 => "The handle is invalid.",
+  NotEnoughMemory = 8 => "Not enough memory resources are available to process this command.",
+  NoMoreFiles = 18 => "There are no more files.",
+  BadLength = 24 => "The program issued a command but the command length is incorrect.",
+  GenFailure = 31 => "A device attached to the system is not functioning.",
+  NotSupported = 50 => "The request is not supported.",
+  InvalidParameter = 87 => "The parameter is incorrect.",
+  BrokenPipe = 109 => "The pipe has been ended.",
+  SemTimeout = 121 => "The semaphore timeout period has expired.",
+  InsufficientBuffer = 122 => "The data area passed to a system call is too small.",
+  ModNotFound = 126 => "The specified module could not be found.",
+  ProcNotFound = 127 => "The specified procedure could not be found.",
+  AlreadyExists = 183 => "Cannot create a file when that file already exists.",
+  BadExeFormat = 193 => "%1 is not a valid Win32 application.",
+  ExeMachineTypeMismatch = 216 => "This version of %1 is not compatible with the version of Windows you're running.",
+  PipeBusy = 231 => "All pipe instances are busy.",
+  NoData = 232 => "The pipe is being closed.",
+  PipeNotConnected = 233 => "No process is on the other end of the pipe.",
+  MoreData = 234 => "More data is available.",
+  WaitTimeout = 258 => "The wait operation timed out.",
+  PartialCopy = 299 => "Only part of a ReadProcessMemory or WriteProcessMemory request was completed.",
+  InvalidAddress = 487 => "Attempt to access invalid address.",
+  PipeConnected = 535 => "There is a process on other end of the pipe.",
+  ElevationRequired = 740 => "The requested operation requires elevation.",
+  OperationAborted = 995 => "The I/O operation has been aborted because of either a thread exit or an application request.",
+  IoPending = 997 => "Overlapped I/O operation is in progress.",
+  NoAccess = 998 => "Invalid access to memory location.",
+  DllInitFailed = 1114 => "A dynamic link library (DLL) initialization routine failed.",
+  NotFound = 1168 => "Element not found.",
+  Timeout = 1460 => "This operation returned because the timeout period expired.",
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn known_codes_round_trip() {
+    for &kind in KNOWN_CODES {
+      assert_eq!(ErrorCode::from(kind.code()), kind);
+      assert!(kind.description().is_some());
+    }
+
+    assert_eq!(ErrorCode::from(5), ErrorCode::AccessDenied);
+    assert_eq!(ErrorCode::AccessDenied.code(), 5);
+    assert_eq!(ErrorCode::from(1460), ErrorCode::Timeout);
+  }
+
+  #[test]
+  fn other_codes_round_trip() {
+    for code in [4, 1234, 0xdead_beef, u32::MAX] {
+      let kind = ErrorCode::from(code);
+
+      assert_eq!(kind, ErrorCode::Other(code));
+      assert_eq!(kind.code(), code);
+      assert_eq!(kind.description(), None);
+    }
+  }
+
+  #[test]
+  fn codes_are_unique() {
+    let mut codes = KNOWN_CODES.iter().map(ErrorCode::code).collect::<Vec<_>>();
+
+    codes.sort_unstable();
+    codes.dedup();
+
+    assert_eq!(codes.len(), KNOWN_CODES.len());
+  }
+}
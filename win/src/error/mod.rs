@@ -1,16 +1,34 @@
-use std::{error, ffi, fmt};
+use std::{error, ffi, fmt, io};
+
+use crate::wintype::Wchar;
 
 pub type BoxResult<T> = Result<T, Box<dyn error::Error + Send + Sync + 'static>>;
 pub type WinApiResult<T> = Result<T, WinApiError>;
 pub type WinApiCodeResult<T> = Result<T, WinApiErrorCode>;
 
+pub mod code;
+#[cfg(windows)]
 pub mod util;
 
+use self::code::ErrorCode;
+
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
 pub enum WinApiError {
   WinApiErrorCode(WinApiErrorCode),
   StringParseError(ffi::OsString),
+  WideStringParseError(Vec<Wchar>),
+  UnterminatedStringError(usize),
   BufferSizeError(usize),
+  PartialReadError {
+    addr: usize,
+    expected: usize,
+    read: usize,
+  },
+  PartialWriteError {
+    addr: usize,
+    expected: usize,
+    written: usize,
+  },
 }
 
 impl fmt::Display for WinApiError {
@@ -18,26 +36,144 @@ impl fmt::Display for WinApiError {
     match self {
       WinApiError::WinApiErrorCode(winapi_code) => winapi_code.fmt(f),
       WinApiError::StringParseError(os_string) => write!(f, "error parsing winapi string - {:?}", os_string),
+      WinApiError::WideStringParseError(wide) => write!(f, "error parsing wide string - {:?}", wide),
+      WinApiError::UnterminatedStringError(max_len) => write!(f, "no string terminator within {} chars", max_len),
       WinApiError::BufferSizeError(size) => write!(f, "invalid buffer size - {}", size),
+      WinApiError::PartialReadError { addr, expected, read } => {
+        write!(f, "partial read at {:#x} - read {} of {} bytes", addr, read, expected)
+      }
+      WinApiError::PartialWriteError {
+        addr,
+        expected,
+        written,
+      } => {
+        write!(
+          f,
+          "partial write at {:#x} - wrote {} of {} bytes",
+          addr, written, expected
+        )
+      }
     }
   }
 }
 
 impl error::Error for WinApiError {}
 
+impl From<WinApiErrorCode> for WinApiError {
+  fn from(code: WinApiErrorCode) -> Self {
+    WinApiError::WinApiErrorCode(code)
+  }
+}
+
+impl From<ffi::OsString> for WinApiError {
+  fn from(os_string: ffi::OsString) -> Self {
+    WinApiError::StringParseError(os_string)
+  }
+}
+
+// a failed winapi call and the error code it left behind
 #[derive(Debug, Clone, PartialEq, PartialOrd)]
-pub struct WinApiErrorCode(u32);
+pub struct WinApiErrorCode {
+  code: ErrorCode,
+  call: &'static str,
+}
 
 impl WinApiErrorCode {
-  fn new(code: u32) -> Self {
-    Self(code)
+  pub fn new(code: u32, call: &'static str) -> Self {
+    Self {
+      code: ErrorCode::from(code),
+      call,
+    }
+  }
+
+  pub fn code(&self) -> u32 {
+    self.code.code()
+  }
+
+  pub fn kind(&self) -> ErrorCode {
+    self.code
+  }
+
+  pub fn call(&self) -> &'static str {
+    self.call
+  }
+
+  // the system message on windows, our own table of known codes elsewhere or if that fails
+  pub fn message(&self) -> String {
+    #[cfg(windows)]
+    if let Some(message) = util::format_message(self.code()) {
+      return message;
+    }
+
+    match self.code.description() {
+      Some(description) => description.to_string(),
+      None => String::from("unknown error"),
+    }
   }
 }
 
 impl fmt::Display for WinApiErrorCode {
   fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-    write!(f, "winapi error code - {}", self.0)
+    write!(
+      f,
+      "{} failed - {} (winapi error code {})",
+      self.call,
+      self.message(),
+      self.code()
+    )
   }
 }
 
 impl error::Error for WinApiErrorCode {}
+
+// keeps the code as the raw os error, so io::ErrorKind is mapped as usual
+impl From<WinApiErrorCode> for io::Error {
+  fn from(err: WinApiErrorCode) -> Self {
+    io::Error::from_raw_os_error(err.code() as i32)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn displays_the_call_and_code() {
+    let err = WinApiErrorCode::new(5, "OpenProcess");
+
+    assert_eq!(err.kind(), ErrorCode::AccessDenied);
+    assert_eq!(err.call(), "OpenProcess");
+    assert_eq!(
+      err.to_string(),
+      format!("OpenProcess failed - {} (winapi error code 5)", err.message())
+    );
+  }
+
+  // windows has the system messages instead, in whatever language the user has
+  #[cfg(not(windows))]
+  #[test]
+  fn falls_back_to_known_messages() {
+    assert_eq!(
+      WinApiErrorCode::new(5, "OpenProcess").to_string(),
+      "OpenProcess failed - Access is denied. (winapi error code 5)"
+    );
+    assert_eq!(
+      WinApiErrorCode::new(0xdead_beef, "ReadProcessMemory").to_string(),
+      "ReadProcessMemory failed - unknown error (winapi error code 3735928559)"
+    );
+  }
+
+  #[test]
+  fn wraps_error_codes() {
+    let err = WinApiError::from(WinApiErrorCode::new(258, "WaitForSingleObject"));
+
+    assert_eq!(
+      err,
+      WinApiError::WinApiErrorCode(WinApiErrorCode::new(258, "WaitForSingleObject"))
+    );
+    assert_eq!(
+      io::Error::from(WinApiErrorCode::new(2, "CreateFileW")).raw_os_error(),
+      Some(2)
+    );
+  }
+}
@@ -1,9 +1,43 @@
-use winapi::um::errhandlingapi::GetLastError;
+use std::ptr;
+
+use winapi::um::{
+  errhandlingapi::GetLastError,
+  winbase::{FormatMessageW, FORMAT_MESSAGE_FROM_SYSTEM, FORMAT_MESSAGE_IGNORE_INSERTS},
+};
 
 use super::WinApiErrorCode;
+use crate::wintype::Wchar;
+
+const MESSAGE_BUFFER_SIZE: usize = 512;
 
-pub fn get_last_error() -> WinApiErrorCode {
+// call is the name of the winapi function that just failed
+pub fn get_last_error(call: &'static str) -> WinApiErrorCode {
   let code = unsafe { GetLastError() };
 
-  WinApiErrorCode::new(code)
+  WinApiErrorCode::new(code, call)
+}
+
+// the system message for code in the user's language
+pub fn format_message(code: u32) -> Option<String> {
+  let mut buffer = [0 as Wchar; MESSAGE_BUFFER_SIZE];
+
+  let ret_chars = unsafe {
+    FormatMessageW(
+      FORMAT_MESSAGE_FROM_SYSTEM | FORMAT_MESSAGE_IGNORE_INSERTS,
+      ptr::null(),
+      code,
+      0,
+      buffer.as_mut_ptr(),
+      buffer.len() as u32,
+      ptr::null_mut(),
+    )
+  };
+
+  if ret_chars == 0 {
+    return None;
+  }
+
+  let message = String::from_utf16_lossy(&buffer[..ret_chars as usize]);
+
+  Some(message.trim_end().to_string())
 }
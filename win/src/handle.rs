@@ -0,0 +1,48 @@
+use std::mem;
+
+use winapi::um::{handleapi::CloseHandle, winnt::HANDLE};
+
+use crate::error::util::get_last_error;
+
+// a handle we own and close on drop
+#[derive(Debug)]
+pub struct OwnedHandle(HANDLE);
+
+impl OwnedHandle {
+  // safety
+  // - handle must be a valid, open handle (not null or INVALID_HANDLE_VALUE)
+  // - nothing else may close it
+  pub unsafe fn new(handle: HANDLE) -> Self {
+    Self(handle)
+  }
+
+  pub fn as_raw(&self) -> HANDLE {
+    self.0
+  }
+
+  // gives up ownership without closing the handle
+  pub fn into_raw(self) -> HANDLE {
+    let handle = self.0;
+
+    mem::forget(self);
+
+    handle
+  }
+}
+
+impl Drop for OwnedHandle {
+  // if bad stuff happens, print error and (probably) leak the handle
+  fn drop(&mut self) {
+    let res = unsafe { CloseHandle(self.0) };
+
+    if res == 0 {
+      let err = get_last_error("CloseHandle");
+
+      eprintln!("{:?}", err);
+    }
+  }
+}
+
+// handles are process wide, any thread may use or close them
+unsafe impl Send for OwnedHandle {}
+unsafe impl Sync for OwnedHandle {}
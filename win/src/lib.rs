@@ -1,3 +1,22 @@
+// safety requirements are written as plain // safety comments above unsafe items, not rustdoc sections
+#![allow(clippy::missing_safety_doc)]
+
+pub mod arch;
+#[cfg(windows)]
+pub mod context;
+pub mod decode;
 pub mod error;
+#[cfg(windows)]
+pub mod handle;
+pub mod memory;
+pub mod module;
+pub mod pe;
+#[cfg(windows)]
+pub mod pipe;
+#[cfg(windows)]
 pub mod process;
+#[cfg(windows)]
+mod snapshot;
+#[cfg(windows)]
+pub mod thread;
 pub mod wintype;
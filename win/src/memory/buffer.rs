@@ -0,0 +1,69 @@
+use std::cell::RefCell;
+
+use super::ProcessMemory;
+use crate::error::WinApiResult;
+
+// a single block of memory pretending to be mapped at base
+// anything outside of it reads and writes as unmapped
+#[derive(Debug, Clone, Default)]
+pub struct BufferMemory {
+  base: usize,
+  data: RefCell<Vec<u8>>,
+}
+
+impl BufferMemory {
+  pub fn new(base: usize, data: Vec<u8>) -> Self {
+    Self {
+      base,
+      data: RefCell::new(data),
+    }
+  }
+
+  pub fn base(&self) -> usize {
+    self.base
+  }
+
+  pub fn len(&self) -> usize {
+    self.data.borrow().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.data.borrow().is_empty()
+  }
+
+  pub fn into_inner(self) -> Vec<u8> {
+    self.data.into_inner()
+  }
+
+  // offset into data and how many bytes are available from there
+  fn span(&self, addr: usize, len: usize) -> Option<(usize, usize)> {
+    let offset = addr.checked_sub(self.base)?;
+    let available = self.len().checked_sub(offset)?;
+
+    Some((offset, available.min(len)))
+  }
+}
+
+impl ProcessMemory for BufferMemory {
+  fn read_partial(&self, addr: usize, buffer: &mut [u8]) -> WinApiResult<usize> {
+    let (offset, len) = match self.span(addr, buffer.len()) {
+      Some(span) => span,
+      None => return Ok(0),
+    };
+
+    buffer[..len].copy_from_slice(&self.data.borrow()[offset..offset + len]);
+
+    Ok(len)
+  }
+
+  fn write_partial(&self, addr: usize, buffer: &[u8]) -> WinApiResult<usize> {
+    let (offset, len) = match self.span(addr, buffer.len()) {
+      Some(span) => span,
+      None => return Ok(0),
+    };
+
+    self.data.borrow_mut()[offset..offset + len].copy_from_slice(&buffer[..len]);
+
+    Ok(len)
+  }
+}
@@ -0,0 +1,410 @@
+use std::{ffi, mem, slice};
+
+use crate::{
+  error::{WinApiError, WinApiResult},
+  wintype::Wchar,
+};
+
+pub mod buffer;
+pub mod region;
+
+// strings are read in chunks so we dont fault on a page boundary past the terminator
+const STRING_CHUNK_SIZE: usize = 64;
+
+// plain old data, any bit pattern is a valid value
+// safety
+// - implementors must be valid for every bit pattern
+// - implementors must not contain padding bytes, references or pointers that get dereferenced
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+  ($($ty:ty),*) => {
+    $(unsafe impl Pod for $ty {})*
+  };
+}
+
+impl_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScatterRead {
+  pub addr: usize,
+  pub len: usize,
+  pub data: Vec<u8>,
+}
+
+impl ScatterRead {
+  pub fn is_complete(&self) -> bool {
+    self.data.len() == self.len
+  }
+}
+
+// memory of some (usually remote) process
+// implemented for live processes and in-memory fakes, so anything built on top can be run without windows
+pub trait ProcessMemory {
+  // reads as many bytes as are readable starting at addr, returning how many were read
+  // hitting unreadable memory is not an error, the read just stops there
+  fn read_partial(&self, addr: usize, buffer: &mut [u8]) -> WinApiResult<usize>;
+
+  // writes as many bytes as are writable starting at addr, returning how many were written
+  fn write_partial(&self, addr: usize, buffer: &[u8]) -> WinApiResult<usize>;
+
+  fn read_exact(&self, addr: usize, buffer: &mut [u8]) -> WinApiResult<()> {
+    let read = self.read_partial(addr, buffer)?;
+
+    if read != buffer.len() {
+      return Err(WinApiError::PartialReadError {
+        addr,
+        expected: buffer.len(),
+        read,
+      });
+    }
+
+    Ok(())
+  }
+
+  fn write_all(&self, addr: usize, buffer: &[u8]) -> WinApiResult<()> {
+    let written = self.write_partial(addr, buffer)?;
+
+    if written != buffer.len() {
+      return Err(WinApiError::PartialWriteError {
+        addr,
+        expected: buffer.len(),
+        written,
+      });
+    }
+
+    Ok(())
+  }
+
+  fn read_bytes(&self, addr: usize, len: usize) -> WinApiResult<Vec<u8>> {
+    let mut buffer = vec![0; len];
+
+    self.read_exact(addr, &mut buffer)?;
+
+    Ok(buffer)
+  }
+
+  fn read<T: Pod>(&self, addr: usize) -> WinApiResult<T> {
+    let mut value = mem::MaybeUninit::<T>::zeroed();
+
+    let buffer = unsafe {
+      // safety
+      // - value is zeroed so every byte is initialised
+      slice::from_raw_parts_mut(value.as_mut_ptr() as *mut u8, mem::size_of::<T>())
+    };
+
+    self.read_exact(addr, buffer)?;
+
+    unsafe {
+      // safety
+      // - T is pod, any bytes we read make a valid value
+      Ok(value.assume_init())
+    }
+  }
+
+  fn write<T: Pod>(&self, addr: usize, value: &T) -> WinApiResult<()> {
+    let buffer = unsafe {
+      // safety
+      // - T is pod, it has no padding so every byte is initialised
+      slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>())
+    };
+
+    self.write_all(addr, buffer)
+  }
+
+  // reads a nul terminated byte string of at most max_len bytes (excluding the terminator)
+  fn read_cstring(&self, addr: usize, max_len: usize) -> WinApiResult<ffi::CString> {
+    let mut bytes = Vec::new();
+    let mut chunk = [0; STRING_CHUNK_SIZE];
+
+    while bytes.len() <= max_len {
+      let chunk_addr = addr + bytes.len();
+      let read = self.read_partial(chunk_addr, &mut chunk)?;
+
+      if read == 0 {
+        return Err(WinApiError::PartialReadError {
+          addr: chunk_addr,
+          expected: chunk.len(),
+          read,
+        });
+      }
+
+      match chunk[..read].iter().position(|&byte| byte == 0) {
+        Some(end) => {
+          bytes.extend_from_slice(&chunk[..end]);
+
+          if bytes.len() > max_len {
+            break;
+          }
+
+          // cannot fail, we stopped at the first nul
+          return Ok(ffi::CString::new(bytes).unwrap());
+        }
+        None => bytes.extend_from_slice(&chunk[..read]),
+      }
+    }
+
+    Err(WinApiError::UnterminatedStringError(max_len))
+  }
+
+  // reads a nul terminated utf-16 string of at most max_len chars (excluding the terminator)
+  fn read_wstring(&self, addr: usize, max_len: usize) -> WinApiResult<String> {
+    let mut chars = Vec::<Wchar>::new();
+    let mut chunk = [0; STRING_CHUNK_SIZE];
+
+    while chars.len() <= max_len {
+      let chunk_addr = addr + chars.len() * mem::size_of::<Wchar>();
+      let read = self.read_partial(chunk_addr, &mut chunk)?;
+      let read_chars = read / mem::size_of::<Wchar>();
+
+      if read_chars == 0 {
+        return Err(WinApiError::PartialReadError {
+          addr: chunk_addr,
+          expected: chunk.len(),
+          read,
+        });
+      }
+
+      let decoded = chunk[..read_chars * mem::size_of::<Wchar>()]
+        .chunks_exact(mem::size_of::<Wchar>())
+        .map(|pair| Wchar::from_le_bytes([pair[0], pair[1]]));
+
+      let mut terminated = false;
+
+      for char in decoded {
+        if char == 0 {
+          terminated = true;
+
+          break;
+        }
+
+        chars.push(char);
+      }
+
+      if terminated && chars.len() <= max_len {
+        return String::from_utf16(&chars).map_err(|_| WinApiError::WideStringParseError(chars));
+      }
+
+      if terminated {
+        break;
+      }
+    }
+
+    Err(WinApiError::UnterminatedStringError(max_len))
+  }
+
+  // reads a batch of (addr, len) ranges
+  // ranges that are only partly readable are returned short instead of failing the whole batch
+  fn read_scatter(&self, ranges: &[(usize, usize)]) -> WinApiResult<Vec<ScatterRead>> {
+    ranges
+      .iter()
+      .map(|&(addr, len)| {
+        let mut data = vec![0; len];
+        let read = self.read_partial(addr, &mut data)?;
+
+        data.truncate(read);
+
+        Ok(ScatterRead { addr, len, data })
+      })
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{buffer::BufferMemory, *};
+
+  const BASE: usize = 0x40_0000;
+
+  fn wide(text: &str) -> Vec<u8> {
+    text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+  }
+
+  #[test]
+  fn reads_and_writes() {
+    let memory = BufferMemory::new(BASE, vec![0; 8]);
+
+    memory.write(BASE + 2, &0x1122_3344_u32).unwrap();
+
+    assert_eq!(memory.read::<u32>(BASE + 2).unwrap(), 0x1122_3344);
+    assert_eq!(memory.read_bytes(BASE, 4).unwrap(), [0, 0, 0x44, 0x33]);
+    assert_eq!(memory.into_inner(), [0, 0, 0x44, 0x33, 0x22, 0x11, 0, 0]);
+  }
+
+  #[test]
+  fn partial_reads_and_writes() {
+    let memory = BufferMemory::new(BASE, vec![0xaa; 8]);
+    let mut buffer = [0; 4];
+
+    assert_eq!(memory.read_partial(BASE + 6, &mut buffer).unwrap(), 2);
+    assert_eq!(buffer, [0xaa, 0xaa, 0, 0]);
+    // below the base is as unmapped as past the end
+    assert_eq!(memory.read_partial(BASE - 1, &mut buffer).unwrap(), 0);
+
+    assert_eq!(
+      memory.read::<u32>(BASE + 6).unwrap_err(),
+      WinApiError::PartialReadError {
+        addr: BASE + 6,
+        expected: 4,
+        read: 2,
+      }
+    );
+    assert_eq!(
+      memory.write_all(BASE + 7, &[1, 2]).unwrap_err(),
+      WinApiError::PartialWriteError {
+        addr: BASE + 7,
+        expected: 2,
+        written: 1,
+      }
+    );
+    // the part that was writable is still written
+    assert_eq!(memory.read::<u8>(BASE + 7).unwrap(), 1);
+  }
+
+  #[test]
+  fn reads_cstrings() {
+    let memory = BufferMemory::new(BASE, b"hello\0world\0".to_vec());
+
+    assert_eq!(memory.read_cstring(BASE, 5).unwrap().as_bytes(), b"hello");
+    assert_eq!(memory.read_cstring(BASE + 6, 16).unwrap().as_bytes(), b"world");
+    assert_eq!(memory.read_cstring(BASE + 5, 0).unwrap().as_bytes(), b"");
+    // the nul is one past max_len
+    assert_eq!(
+      memory.read_cstring(BASE, 4).unwrap_err(),
+      WinApiError::UnterminatedStringError(4)
+    );
+  }
+
+  #[test]
+  fn reads_cstrings_across_chunks() {
+    let mut data = vec![b'a'; STRING_CHUNK_SIZE * 2];
+
+    data.push(0);
+
+    let memory = BufferMemory::new(BASE, data);
+
+    assert_eq!(
+      memory.read_cstring(BASE, STRING_CHUNK_SIZE * 2).unwrap().as_bytes(),
+      [b'a'; STRING_CHUNK_SIZE * 2]
+    );
+    assert_eq!(
+      memory.read_cstring(BASE, STRING_CHUNK_SIZE * 2 - 1).unwrap_err(),
+      WinApiError::UnterminatedStringError(STRING_CHUNK_SIZE * 2 - 1)
+    );
+  }
+
+  #[test]
+  fn unterminated_cstrings() {
+    let memory = BufferMemory::new(BASE, b"hello".to_vec());
+
+    // runs into unmapped memory before finding a nul
+    assert_eq!(
+      memory.read_cstring(BASE, 16).unwrap_err(),
+      WinApiError::PartialReadError {
+        addr: BASE + 5,
+        expected: STRING_CHUNK_SIZE,
+        read: 0,
+      }
+    );
+    // gives up at max_len without reading that far
+    assert_eq!(
+      memory.read_cstring(BASE, 3).unwrap_err(),
+      WinApiError::UnterminatedStringError(3)
+    );
+  }
+
+  #[test]
+  fn reads_wstrings() {
+    let mut data = wide("kernel32.dll\0ü\0");
+
+    data.extend_from_slice(&wide("x"));
+
+    let memory = BufferMemory::new(BASE, data);
+
+    assert_eq!(memory.read_wstring(BASE, 12).unwrap(), "kernel32.dll");
+    assert_eq!(memory.read_wstring(BASE + 26, 1).unwrap(), "ü");
+    assert_eq!(
+      memory.read_wstring(BASE, 11).unwrap_err(),
+      WinApiError::UnterminatedStringError(11)
+    );
+    assert_eq!(
+      memory.read_wstring(BASE + 30, 4).unwrap_err(),
+      WinApiError::PartialReadError {
+        addr: BASE + 32,
+        expected: STRING_CHUNK_SIZE,
+        read: 0,
+      }
+    );
+  }
+
+  #[test]
+  fn wstrings_with_an_odd_byte_count() {
+    let mut data = wide("ab");
+
+    data.push(b'c');
+
+    let memory = BufferMemory::new(BASE, data);
+
+    // the trailing byte is half a char, which is as good as unreadable
+    assert_eq!(
+      memory.read_wstring(BASE, 8).unwrap_err(),
+      WinApiError::PartialReadError {
+        addr: BASE + 4,
+        expected: STRING_CHUNK_SIZE,
+        read: 1,
+      }
+    );
+  }
+
+  #[test]
+  fn invalid_wstrings() {
+    let memory = BufferMemory::new(BASE, vec![0x00, 0xd8, 0x41, 0x00, 0x00, 0x00]);
+
+    assert_eq!(
+      memory.read_wstring(BASE, 8).unwrap_err(),
+      WinApiError::WideStringParseError(vec![0xd800, 0x41])
+    );
+  }
+
+  #[test]
+  fn scatter_reads() {
+    let memory = BufferMemory::new(BASE, (0..16).collect());
+
+    let reads = memory
+      .read_scatter(&[(BASE, 4), (BASE + 0x1000, 4), (BASE + 14, 4), (BASE + 8, 0)])
+      .unwrap();
+
+    assert_eq!(
+      reads,
+      [
+        ScatterRead {
+          addr: BASE,
+          len: 4,
+          data: vec![0, 1, 2, 3],
+        },
+        // unreadable, the rest of the batch is still read
+        ScatterRead {
+          addr: BASE + 0x1000,
+          len: 4,
+          data: Vec::new(),
+        },
+        ScatterRead {
+          addr: BASE + 14,
+          len: 4,
+          data: vec![14, 15],
+        },
+        ScatterRead {
+          addr: BASE + 8,
+          len: 0,
+          data: Vec::new(),
+        },
+      ]
+    );
+    assert_eq!(
+      reads.iter().map(ScatterRead::is_complete).collect::<Vec<_>>(),
+      [true, false, false, true]
+    );
+  }
+}
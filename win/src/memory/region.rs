@@ -0,0 +1,411 @@
+use std::iter;
+#[cfg(windows)]
+use std::{marker::PhantomData, mem};
+
+#[cfg(windows)]
+use winapi::um::{
+  memoryapi::{VirtualQuery, VirtualQueryEx},
+  winnt::MEMORY_BASIC_INFORMATION,
+};
+
+#[cfg(windows)]
+use crate::{
+  error::{code::ErrorCode, util::get_last_error, WinApiError, WinApiResult},
+  wintype::ProcessHandle,
+};
+
+// values mirror winnt.h
+const MEM_COMMIT: u32 = 0x1000;
+const MEM_RESERVE: u32 = 0x2000;
+const MEM_FREE: u32 = 0x10000;
+const MEM_PRIVATE: u32 = 0x20000;
+const MEM_MAPPED: u32 = 0x40000;
+const MEM_IMAGE: u32 = 0x1000000;
+
+const PAGE_NOACCESS: u32 = 0x01;
+const PAGE_READONLY: u32 = 0x02;
+const PAGE_READWRITE: u32 = 0x04;
+const PAGE_WRITECOPY: u32 = 0x08;
+const PAGE_EXECUTE: u32 = 0x10;
+const PAGE_EXECUTE_READ: u32 = 0x20;
+const PAGE_EXECUTE_READWRITE: u32 = 0x40;
+const PAGE_EXECUTE_WRITECOPY: u32 = 0x80;
+const PAGE_GUARD: u32 = 0x100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionState {
+  Commit,
+  Reserve,
+  Free,
+  Unknown(u32),
+}
+
+impl From<u32> for RegionState {
+  fn from(state: u32) -> Self {
+    match state {
+      MEM_COMMIT => RegionState::Commit,
+      MEM_RESERVE => RegionState::Reserve,
+      MEM_FREE => RegionState::Free,
+      _ => RegionState::Unknown(state),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionType {
+  Image,
+  Mapped,
+  Private,
+  // free regions have no type
+  None,
+  Unknown(u32),
+}
+
+impl From<u32> for RegionType {
+  fn from(region_type: u32) -> Self {
+    match region_type {
+      MEM_IMAGE => RegionType::Image,
+      MEM_MAPPED => RegionType::Mapped,
+      MEM_PRIVATE => RegionType::Private,
+      0 => RegionType::None,
+      _ => RegionType::Unknown(region_type),
+    }
+  }
+}
+
+// raw PAGE_* protection value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Protection(u32);
+
+impl Protection {
+  pub const NO_ACCESS: Self = Self(PAGE_NOACCESS);
+  pub const READONLY: Self = Self(PAGE_READONLY);
+  pub const READWRITE: Self = Self(PAGE_READWRITE);
+  pub const EXECUTE: Self = Self(PAGE_EXECUTE);
+  pub const EXECUTE_READ: Self = Self(PAGE_EXECUTE_READ);
+  pub const EXECUTE_READWRITE: Self = Self(PAGE_EXECUTE_READWRITE);
+
+  pub fn new(protection: u32) -> Self {
+    Self(protection)
+  }
+
+  pub fn bits(&self) -> u32 {
+    self.0
+  }
+
+  // protection without modifiers such as PAGE_GUARD or PAGE_NOCACHE
+  fn base(&self) -> u32 {
+    self.0 & 0xff
+  }
+
+  pub fn is_guarded(&self) -> bool {
+    self.0 & PAGE_GUARD != 0
+  }
+
+  pub fn is_readable(&self) -> bool {
+    matches!(
+      self.base(),
+      PAGE_READONLY
+        | PAGE_READWRITE
+        | PAGE_WRITECOPY
+        | PAGE_EXECUTE_READ
+        | PAGE_EXECUTE_READWRITE
+        | PAGE_EXECUTE_WRITECOPY
+    )
+  }
+
+  pub fn is_writable(&self) -> bool {
+    matches!(
+      self.base(),
+      PAGE_READWRITE | PAGE_WRITECOPY | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY
+    )
+  }
+
+  pub fn is_executable(&self) -> bool {
+    matches!(
+      self.base(),
+      PAGE_EXECUTE | PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY
+    )
+  }
+
+  pub fn is_no_access(&self) -> bool {
+    self.base() == PAGE_NOACCESS
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryRegion {
+  pub base: usize,
+  pub size: usize,
+  pub allocation_base: usize,
+  pub state: RegionState,
+  pub protection: Protection,
+  pub region_type: RegionType,
+}
+
+impl MemoryRegion {
+  pub fn end(&self) -> usize {
+    self.base.saturating_add(self.size)
+  }
+
+  pub fn contains(&self, addr: usize) -> bool {
+    addr >= self.base && addr < self.end()
+  }
+
+  pub fn is_committed(&self) -> bool {
+    self.state == RegionState::Commit
+  }
+
+  pub fn is_free(&self) -> bool {
+    self.state == RegionState::Free
+  }
+
+  // committed, readable and not a guard page, ie. safe to read from
+  pub fn is_readable(&self) -> bool {
+    self.is_committed() && self.protection.is_readable() && !self.protection.is_guarded()
+  }
+
+  pub fn is_writable(&self) -> bool {
+    self.is_committed() && self.protection.is_writable() && !self.protection.is_guarded()
+  }
+
+  pub fn is_executable(&self) -> bool {
+    self.is_committed() && self.protection.is_executable() && !self.protection.is_guarded()
+  }
+
+  pub fn is_image(&self) -> bool {
+    self.region_type == RegionType::Image
+  }
+}
+
+#[cfg(windows)]
+impl From<MEMORY_BASIC_INFORMATION> for MemoryRegion {
+  fn from(info: MEMORY_BASIC_INFORMATION) -> Self {
+    Self {
+      base: info.BaseAddress as usize,
+      size: info.RegionSize,
+      allocation_base: info.AllocationBase as usize,
+      state: RegionState::from(info.State),
+      protection: Protection::new(info.Protect),
+      region_type: RegionType::from(info.Type),
+    }
+  }
+}
+
+// filtering helpers for anything yielding regions
+type RegionPredicate = fn(&MemoryRegion) -> bool;
+
+pub trait RegionFilter: Iterator<Item = MemoryRegion> + Sized {
+  fn committed(self) -> iter::Filter<Self, RegionPredicate> {
+    self.filter(MemoryRegion::is_committed as RegionPredicate)
+  }
+
+  fn readable(self) -> iter::Filter<Self, RegionPredicate> {
+    self.filter(MemoryRegion::is_readable as RegionPredicate)
+  }
+
+  fn writable(self) -> iter::Filter<Self, RegionPredicate> {
+    self.filter(MemoryRegion::is_writable as RegionPredicate)
+  }
+
+  fn executable(self) -> iter::Filter<Self, RegionPredicate> {
+    self.filter(MemoryRegion::is_executable as RegionPredicate)
+  }
+}
+
+impl<I: Iterator<Item = MemoryRegion>> RegionFilter for I {}
+
+type RegionResultPredicate<E> = fn(&Result<MemoryRegion, E>) -> bool;
+
+// the same filters for anything yielding results, like Regions, errors are passed through untouched
+pub trait RegionResultFilter<E>: Iterator<Item = Result<MemoryRegion, E>> + Sized {
+  fn committed(self) -> iter::Filter<Self, RegionResultPredicate<E>> {
+    self.filter((|region| region.as_ref().map_or(true, MemoryRegion::is_committed)) as RegionResultPredicate<E>)
+  }
+
+  fn readable(self) -> iter::Filter<Self, RegionResultPredicate<E>> {
+    self.filter((|region| region.as_ref().map_or(true, MemoryRegion::is_readable)) as RegionResultPredicate<E>)
+  }
+
+  fn writable(self) -> iter::Filter<Self, RegionResultPredicate<E>> {
+    self.filter((|region| region.as_ref().map_or(true, MemoryRegion::is_writable)) as RegionResultPredicate<E>)
+  }
+
+  fn executable(self) -> iter::Filter<Self, RegionResultPredicate<E>> {
+    self.filter((|region| region.as_ref().map_or(true, MemoryRegion::is_executable)) as RegionResultPredicate<E>)
+  }
+}
+
+impl<E, I: Iterator<Item = Result<MemoryRegion, E>>> RegionResultFilter<E> for I {}
+
+#[cfg(windows)]
+#[derive(Debug, Clone, Copy)]
+enum QueryTarget {
+  Local,
+  Remote(ProcessHandle),
+}
+
+#[cfg(windows)]
+fn query_region(target: QueryTarget, addr: usize) -> WinApiResult<Option<MemoryRegion>> {
+  let mut info: MEMORY_BASIC_INFORMATION = unsafe { mem::zeroed() };
+  let info_size = mem::size_of::<MEMORY_BASIC_INFORMATION>();
+
+  let ret_size = unsafe {
+    match target {
+      QueryTarget::Local => VirtualQuery(addr as *const _, &mut info, info_size),
+      QueryTarget::Remote(handle) => VirtualQueryEx(handle, addr as *const _, &mut info, info_size),
+    }
+  };
+
+  if ret_size == 0 {
+    let err = match target {
+      QueryTarget::Local => get_last_error("VirtualQuery"),
+      QueryTarget::Remote(_) => get_last_error("VirtualQueryEx"),
+    };
+
+    // addr is past the highest user mode address
+    if err.kind() == ErrorCode::InvalidParameter {
+      return Ok(None);
+    }
+
+    return Err(WinApiError::WinApiErrorCode(err));
+  }
+
+  Ok(Some(MemoryRegion::from(info)))
+}
+
+// address of the region containing addr in the current process
+#[cfg(windows)]
+pub fn local_region_at(addr: usize) -> WinApiResult<Option<MemoryRegion>> {
+  query_region(QueryTarget::Local, addr)
+}
+
+// walks the address space from bottom to top, one region at a time
+// stops after the first error
+#[cfg(windows)]
+#[derive(Debug)]
+pub struct Regions<'a> {
+  target: QueryTarget,
+  addr: usize,
+  done: bool,
+  // remote handles are borrowed from their owning process
+  _handle: PhantomData<&'a ()>,
+}
+
+#[cfg(windows)]
+impl<'a> Regions<'a> {
+  // safety
+  // - handle must be a valid process handle with PROCESS_QUERY_INFORMATION for 'a
+  pub(crate) unsafe fn remote(handle: ProcessHandle) -> Self {
+    Self {
+      target: QueryTarget::Remote(handle),
+      addr: 0,
+      done: false,
+      _handle: PhantomData,
+    }
+  }
+
+  // start walking from the region containing addr instead of the bottom of the address space
+  pub fn starting_at(mut self, addr: usize) -> Self {
+    self.addr = addr;
+
+    self
+  }
+}
+
+#[cfg(windows)]
+impl Regions<'static> {
+  // regions of the current process, usable from inside an injected dll
+  pub fn local() -> Self {
+    Self {
+      target: QueryTarget::Local,
+      addr: 0,
+      done: false,
+      _handle: PhantomData,
+    }
+  }
+}
+
+#[cfg(windows)]
+impl<'a> Iterator for Regions<'a> {
+  type Item = WinApiResult<MemoryRegion>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done {
+      return None;
+    }
+
+    match query_region(self.target, self.addr) {
+      Ok(Some(region)) => {
+        match region.base.checked_add(region.size) {
+          Some(next) if region.size != 0 => self.addr = next,
+          _ => self.done = true,
+        }
+
+        Some(Ok(region))
+      }
+      Ok(None) => {
+        self.done = true;
+
+        None
+      }
+      Err(err) => {
+        self.done = true;
+
+        Some(Err(err))
+      }
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn region(base: usize, state: u32, protection: u32) -> MemoryRegion {
+    MemoryRegion {
+      base,
+      size: 0x1000,
+      allocation_base: base,
+      state: RegionState::from(state),
+      protection: Protection::new(protection),
+      region_type: RegionType::Private,
+    }
+  }
+
+  #[test]
+  fn filters_regions() {
+    let regions = [
+      region(0x1000, MEM_COMMIT, PAGE_READWRITE),
+      region(0x2000, MEM_RESERVE, PAGE_NOACCESS),
+      region(0x3000, MEM_COMMIT, PAGE_EXECUTE_READ),
+      region(0x4000, MEM_COMMIT, PAGE_READWRITE | PAGE_GUARD),
+    ];
+
+    let bases = |regions: Vec<MemoryRegion>| regions.iter().map(|region| region.base).collect::<Vec<_>>();
+
+    assert_eq!(
+      bases(regions.into_iter().committed().collect()),
+      [0x1000, 0x3000, 0x4000]
+    );
+    assert_eq!(bases(regions.into_iter().readable().collect()), [0x1000, 0x3000]);
+    assert_eq!(bases(regions.into_iter().writable().collect()), [0x1000]);
+    assert_eq!(bases(regions.into_iter().executable().collect()), [0x3000]);
+  }
+
+  #[test]
+  fn filters_results_and_passes_errors_through() {
+    let results = vec![
+      Ok(region(0x1000, MEM_COMMIT, PAGE_READWRITE)),
+      Ok(region(0x2000, MEM_FREE, PAGE_NOACCESS)),
+      Err("query failed"),
+    ];
+
+    let filtered = results.into_iter().readable().collect::<Vec<_>>();
+
+    assert_eq!(
+      filtered,
+      [Ok(region(0x1000, MEM_COMMIT, PAGE_READWRITE)), Err("query failed")]
+    );
+  }
+}
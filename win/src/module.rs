@@ -0,0 +1,29 @@
+use std::path::PathBuf;
+
+// a module (exe or dll) mapped into some process
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Module {
+  pub name: String,
+  pub path: PathBuf,
+  pub base: usize,
+  pub size: usize,
+}
+
+impl Module {
+  pub fn end(&self) -> usize {
+    self.base.saturating_add(self.size)
+  }
+
+  pub fn contains(&self, addr: usize) -> bool {
+    addr >= self.base && addr < self.end()
+  }
+
+  // module names are case insensitive on windows
+  pub fn is_named(&self, name: &str) -> bool {
+    self.name.eq_ignore_ascii_case(name)
+  }
+}
+
+pub fn find_module<'a>(modules: &'a [Module], name: &str) -> Option<&'a Module> {
+  modules.iter().find(|module| module.is_named(name))
+}
@@ -0,0 +1,350 @@
+// a data only dll whose one job is to have the loader set up thread_local data for a manually mapped image
+// loading it makes the loader hand out a tls index and give every thread, existing and future, its own copy of the
+// template, which the mapped image then uses as its own by taking over the index
+
+use super::{
+  reloc::{REL_BASED_DIR64, REL_BASED_HIGHLOW},
+  DataDirectoryKind, DATA_DIRECTORY_SIZE, DOS_LFANEW_OFFSET, DOS_SIGNATURE, FILE_HEADER_SIZE, MACHINE_AMD64,
+  MACHINE_I386, NT_SIGNATURE, OPTIONAL_MAGIC_PE32, OPTIONAL_MAGIC_PE32_PLUS, SCN_MEM_READ, SCN_MEM_WRITE,
+  SECTION_HEADER_SIZE,
+};
+use crate::arch::Arch;
+
+const NT_OFFSET: usize = 0x40;
+const HEADERS_SIZE: u32 = 0x200;
+const SECTION_RVA: u32 = 0x1000;
+const SECTION_ALIGNMENT: u32 = 0x1000;
+const FILE_ALIGNMENT: u32 = 0x200;
+const DIRECTORY_COUNT: usize = 16;
+
+// IMAGE_FILE_EXECUTABLE_IMAGE | IMAGE_FILE_DLL, plus IMAGE_FILE_32BIT_MACHINE or IMAGE_FILE_LARGE_ADDRESS_AWARE
+const FILE_CHARACTERISTICS: u16 = 0x0002 | 0x2000;
+const FILE_32BIT_MACHINE: u16 = 0x0100;
+const FILE_LARGE_ADDRESS_AWARE: u16 = 0x0020;
+
+// IMAGE_SUBSYSTEM_WINDOWS_GUI, and vista, the first version to set up tls for dlls loaded after startup
+const SUBSYSTEM: u16 = 2;
+const OS_VERSION: u16 = 6;
+
+// IMAGE_DLLCHARACTERISTICS_DYNAMIC_BASE | IMAGE_DLLCHARACTERISTICS_NX_COMPAT
+const DLL_CHARACTERISTICS: u16 = 0x0040 | 0x0100;
+
+// IMAGE_SCN_CNT_INITIALIZED_DATA
+const SCN_CNT_INITIALIZED_DATA: u32 = 0x40;
+
+const STACK_RESERVE: u64 = 0x10_0000;
+const STACK_COMMIT: u64 = 0x1000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TlsCarrier {
+  // the dll as it goes on disk
+  pub image: Vec<u8>,
+  // where the loader writes the tls index, to be copied into the mapped image
+  pub index_rva: u32,
+  // callback_slots null pointers and a terminator, for the mapped image's callbacks to be written to once it is ready
+  // the loader calls whatever is there for every thread that starts or exits, with the carrier as the module
+  pub callbacks_rva: u32,
+}
+
+// template is the mapped image's tls data, already relocated for where the image ends up, it is copied as is
+// characteristics are those of the mapped image's tls directory
+pub fn tls_carrier(
+  arch: Arch,
+  template: &[u8],
+  zero_fill: u32,
+  characteristics: u32,
+  callback_slots: usize,
+) -> TlsCarrier {
+  let pointer_size = arch.pointer_size();
+
+  let (image_base, machine, file_characteristics, magic, optional_size, directories_offset, reloc_kind) = match arch {
+    Arch::X86 => (
+      0x1000_0000_u64,
+      MACHINE_I386,
+      FILE_CHARACTERISTICS | FILE_32BIT_MACHINE,
+      OPTIONAL_MAGIC_PE32,
+      96,
+      96,
+      REL_BASED_HIGHLOW,
+    ),
+    Arch::X64 => (
+      0x1_8000_0000,
+      MACHINE_AMD64,
+      FILE_CHARACTERISTICS | FILE_LARGE_ADDRESS_AWARE,
+      OPTIONAL_MAGIC_PE32_PLUS,
+      112,
+      112,
+      REL_BASED_DIR64,
+    ),
+  };
+
+  let optional_size = optional_size + DIRECTORY_COUNT * DATA_DIRECTORY_SIZE;
+
+  // the tls directory, the index, the callbacks, the template and the relocations for the directory, in that order
+  let directory_size = pointer_size * 4 + 8;
+  let index = align(directory_size, 8);
+  let callbacks = index + 8;
+  let template_start = align(callbacks + (callback_slots + 1) * pointer_size, 16);
+  let relocations = align(template_start + template.len(), 4);
+  let relocations_size = 8 + 4 * 2;
+  let virtual_size = relocations + relocations_size;
+
+  let va = |offset: usize| image_base + SECTION_RVA as u64 + offset as u64;
+  let rva = |offset: usize| SECTION_RVA + offset as u32;
+
+  let mut section = vec![0; virtual_size];
+
+  for (field, value) in [
+    va(template_start),
+    va(template_start + template.len()),
+    va(index),
+    va(callbacks),
+  ]
+  .into_iter()
+  .enumerate()
+  {
+    put_pointer(arch, &mut section, field * pointer_size, value);
+  }
+
+  put(&mut section, pointer_size * 4, &zero_fill.to_le_bytes());
+  put(&mut section, pointer_size * 4 + 4, &characteristics.to_le_bytes());
+  put(&mut section, template_start, template);
+
+  // a single block for the directory's four addresses, all in the first page
+  put(&mut section, relocations, &SECTION_RVA.to_le_bytes());
+  put(&mut section, relocations + 4, &(relocations_size as u32).to_le_bytes());
+
+  for field in 0..4 {
+    let entry = reloc_kind << 12 | (field * pointer_size) as u16;
+
+    put(&mut section, relocations + 8 + field * 2, &entry.to_le_bytes());
+  }
+
+  let raw_size = align(virtual_size, FILE_ALIGNMENT as usize);
+  let size_of_image = SECTION_RVA + align(virtual_size, SECTION_ALIGNMENT as usize) as u32;
+
+  let mut image = vec![0; HEADERS_SIZE as usize + raw_size];
+
+  put(&mut image, 0, &DOS_SIGNATURE.to_le_bytes());
+  put(&mut image, DOS_LFANEW_OFFSET, &(NT_OFFSET as u32).to_le_bytes());
+  put(&mut image, NT_OFFSET, &NT_SIGNATURE.to_le_bytes());
+
+  let file_header = NT_OFFSET + 4;
+
+  put(&mut image, file_header, &machine.to_le_bytes());
+  put(&mut image, file_header + 2, &1_u16.to_le_bytes());
+  put(&mut image, file_header + 16, &(optional_size as u16).to_le_bytes());
+  put(&mut image, file_header + 18, &file_characteristics.to_le_bytes());
+
+  let optional = file_header + FILE_HEADER_SIZE;
+
+  put(&mut image, optional, &magic.to_le_bytes());
+  put(&mut image, optional + 8, &(raw_size as u32).to_le_bytes());
+
+  // no entry point, there is no code
+  match arch {
+    Arch::X86 => put(&mut image, optional + 28, &(image_base as u32).to_le_bytes()),
+    Arch::X64 => put(&mut image, optional + 24, &image_base.to_le_bytes()),
+  }
+
+  put(&mut image, optional + 32, &SECTION_ALIGNMENT.to_le_bytes());
+  put(&mut image, optional + 36, &FILE_ALIGNMENT.to_le_bytes());
+  put(&mut image, optional + 40, &OS_VERSION.to_le_bytes());
+  put(&mut image, optional + 48, &OS_VERSION.to_le_bytes());
+  put(&mut image, optional + 56, &size_of_image.to_le_bytes());
+  put(&mut image, optional + 60, &HEADERS_SIZE.to_le_bytes());
+  put(&mut image, optional + 68, &SUBSYSTEM.to_le_bytes());
+  put(&mut image, optional + 70, &DLL_CHARACTERISTICS.to_le_bytes());
+
+  // stack and heap sizes, which only matter for exes but are never left 0 by linkers
+  for (field, value) in [STACK_RESERVE, STACK_COMMIT, STACK_RESERVE, STACK_COMMIT]
+    .into_iter()
+    .enumerate()
+  {
+    put_pointer(arch, &mut image, optional + 72 + field * pointer_size, value);
+  }
+
+  put(
+    &mut image,
+    optional + directories_offset - 4,
+    &(DIRECTORY_COUNT as u32).to_le_bytes(),
+  );
+
+  let directories = [
+    (DataDirectoryKind::Tls, rva(0), directory_size),
+    (DataDirectoryKind::BaseReloc, rva(relocations), relocations_size),
+  ];
+
+  for (kind, rva, size) in directories {
+    let offset = optional + directories_offset + kind as usize * DATA_DIRECTORY_SIZE;
+
+    put(&mut image, offset, &rva.to_le_bytes());
+    put(&mut image, offset + 4, &(size as u32).to_le_bytes());
+  }
+
+  let section_header = optional + optional_size;
+
+  debug_assert!(section_header + SECTION_HEADER_SIZE <= HEADERS_SIZE as usize);
+
+  put(&mut image, section_header, b".tls");
+  put(&mut image, section_header + 8, &(virtual_size as u32).to_le_bytes());
+  put(&mut image, section_header + 12, &SECTION_RVA.to_le_bytes());
+  put(&mut image, section_header + 16, &(raw_size as u32).to_le_bytes());
+  put(&mut image, section_header + 20, &HEADERS_SIZE.to_le_bytes());
+  put(
+    &mut image,
+    section_header + 36,
+    &(SCN_CNT_INITIALIZED_DATA | SCN_MEM_READ | SCN_MEM_WRITE).to_le_bytes(),
+  );
+
+  put(&mut image, HEADERS_SIZE as usize, &section);
+
+  TlsCarrier {
+    image,
+    index_rva: rva(index),
+    callbacks_rva: rva(callbacks),
+  }
+}
+
+fn align(value: usize, alignment: usize) -> usize {
+  value.div_ceil(alignment) * alignment
+}
+
+// offsets are all worked out above, out of bounds is a bug
+fn put(data: &mut [u8], offset: usize, bytes: &[u8]) {
+  data[offset..offset + bytes.len()].copy_from_slice(bytes);
+}
+
+fn put_pointer(arch: Arch, data: &mut [u8], offset: usize, value: u64) {
+  match arch {
+    Arch::X86 => put(data, offset, &(value as u32).to_le_bytes()),
+    Arch::X64 => put(data, offset, &value.to_le_bytes()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::pe::{
+    fixture::Fixture,
+    reloc::{apply_relocations, Relocation, RelocationKind},
+    Layout, PeImage,
+  };
+
+  #[test]
+  fn builds_a_carrier() {
+    for arch in [Arch::X86, Arch::X64] {
+      let carrier = tls_carrier(arch, &[1, 2, 3, 4, 5, 6, 7, 8], 0x20, 0x0050_0000, 2);
+      let image = PeImage::parse(&carrier.image, Layout::File).unwrap();
+
+      assert_eq!(image.arch(), arch);
+      assert_eq!(image.entry_point(), 0);
+      assert_eq!(image.size_of_image(), 0x2000);
+      assert_eq!(image.sections().len(), 1);
+      assert_eq!(image.imports().unwrap(), []);
+      assert_eq!(image.exports().unwrap(), []);
+
+      let directory = image.tls_directory().unwrap().unwrap();
+
+      assert_eq!(directory.template_size, 8);
+      assert_eq!(directory.zero_fill, 0x20);
+      assert_eq!(directory.index_rva, carrier.index_rva);
+      assert_eq!(directory.callbacks_rva, carrier.callbacks_rva);
+      assert_eq!(directory.characteristics, 0x0050_0000);
+      assert_eq!(image.implicit_tls_size().unwrap(), 0x28);
+      // the slots start out empty
+      assert_eq!(image.tls_callbacks().unwrap(), []);
+
+      let mapped = image.map().unwrap();
+      let template = directory.template_rva as usize;
+
+      assert_eq!(mapped[template..template + 8], [1, 2, 3, 4, 5, 6, 7, 8]);
+      assert_eq!(
+        mapped[carrier.index_rva as usize..carrier.index_rva as usize + 4],
+        [0; 4]
+      );
+      // nothing else in the section overlaps the callback slots and their terminator
+      let callbacks = carrier.callbacks_rva as usize;
+      assert!(callbacks + 3 * arch.pointer_size() <= template);
+    }
+  }
+
+  #[test]
+  fn relocates_the_directory() {
+    for (arch, kind) in [(Arch::X86, RelocationKind::HighLow), (Arch::X64, RelocationKind::Dir64)] {
+      let carrier = tls_carrier(arch, &[0xaa; 3], 0, 0, 0);
+      let image = PeImage::parse(&carrier.image, Layout::File).unwrap();
+      let directory = image.directory(DataDirectoryKind::Tls).unwrap();
+      let pointer_size = arch.pointer_size() as u32;
+
+      let relocations = image.relocations().unwrap();
+
+      assert_eq!(
+        relocations,
+        (0..4)
+          .map(|field| Relocation {
+            rva: directory.rva + field * pointer_size,
+            kind,
+          })
+          .collect::<Vec<_>>()
+      );
+
+      let mut mapped = image.map().unwrap();
+
+      apply_relocations(&mut mapped, &relocations, 0x10_0000).unwrap();
+
+      let relocated = PeImage::parse(&mapped, Layout::Mapped).unwrap();
+
+      assert_eq!(
+        relocated.pointer_at(directory.rva + 2 * pointer_size).unwrap(),
+        image.image_base() + 0x10_0000 + carrier.index_rva as u64
+      );
+      assert_eq!(
+        relocated.pointer_at(directory.rva + 3 * pointer_size).unwrap(),
+        image.image_base() + 0x10_0000 + carrier.callbacks_rva as u64
+      );
+    }
+  }
+
+  #[test]
+  fn carries_a_mapped_template() {
+    for arch in [Arch::X86, Arch::X64] {
+      let mut fixture = Fixture::new(arch);
+      let callback = fixture.add(&[0xc3]);
+      fixture.tls(&[0x11, 0x22, 0x33, 0x44, 0x55], 0x100, &[callback]);
+
+      let data = fixture.build();
+      let image = PeImage::parse(&data, Layout::File).unwrap();
+      let mapped = image.map().unwrap();
+      let directory = image.tls_directory().unwrap().unwrap();
+
+      let template_start = directory.template_rva as usize;
+      let template = &mapped[template_start..template_start + directory.template_size as usize];
+
+      let carrier = tls_carrier(
+        arch,
+        template,
+        directory.zero_fill,
+        directory.characteristics,
+        image.tls_callbacks().unwrap().len(),
+      );
+      let carrier_image = PeImage::parse(&carrier.image, Layout::File).unwrap();
+      let carried = carrier_image.tls_directory().unwrap().unwrap();
+
+      // every thread gets the same data at the same offsets the mapped image's code expects
+      assert_eq!(carried.size(), directory.size());
+      assert_eq!(
+        carrier_image.slice_at(carried.template_rva, 5).unwrap(),
+        [0x11, 0x22, 0x33, 0x44, 0x55]
+      );
+      // room for the one callback and the terminator
+      assert_eq!(carrier_image.pointer_at(carrier.callbacks_rva).unwrap(), 0);
+      assert_eq!(
+        carrier_image
+          .pointer_at(carrier.callbacks_rva + arch.pointer_size() as u32)
+          .unwrap(),
+        0
+      );
+    }
+  }
+}
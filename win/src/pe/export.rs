@@ -0,0 +1,286 @@
+use super::{DataDirectoryKind, PeImage, PeResult};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExportTarget {
+  Rva(u32),
+  // "MODULE.Name" or "MODULE.#ordinal", to be resolved in another module
+  Forwarded(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Export {
+  // exports by ordinal only have no name
+  pub name: Option<String>,
+  pub ordinal: u16,
+  pub target: ExportTarget,
+}
+
+// a forwarder split into the module it points at and what to look up there
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Forwarder<'a> {
+  pub module: &'a str,
+  pub export: ForwardedExport<'a>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForwardedExport<'a> {
+  Name(&'a str),
+  Ordinal(u16),
+}
+
+impl<'a> Forwarder<'a> {
+  pub fn parse(forwarder: &'a str) -> Option<Self> {
+    let (module, export) = forwarder.rsplit_once('.')?;
+
+    let export = match export.strip_prefix('#') {
+      Some(ordinal) => ForwardedExport::Ordinal(ordinal.parse().ok()?),
+      None => ForwardedExport::Name(export),
+    };
+
+    Some(Self { module, export })
+  }
+
+  // forwarders leave out the extension
+  pub fn module_file_name(&self) -> String {
+    format!("{}.dll", self.module)
+  }
+}
+
+// export directory field offsets
+const EXPORT_ORDINAL_BASE: u32 = 16;
+const EXPORT_FUNCTION_COUNT: u32 = 20;
+const EXPORT_NAME_COUNT: u32 = 24;
+const EXPORT_FUNCTIONS: u32 = 28;
+const EXPORT_NAMES: u32 = 32;
+const EXPORT_NAME_ORDINALS: u32 = 36;
+
+impl<'a> PeImage<'a> {
+  pub fn exports(&self) -> PeResult<Vec<Export>> {
+    let directory = match self.directory(DataDirectoryKind::Export) {
+      Some(directory) => directory,
+      None => return Ok(Vec::new()),
+    };
+
+    let ordinal_base = self.u32_at(directory.rva + EXPORT_ORDINAL_BASE)?;
+    let function_count = self.u32_at(directory.rva + EXPORT_FUNCTION_COUNT)?;
+    let name_count = self.u32_at(directory.rva + EXPORT_NAME_COUNT)?;
+    let functions = self.u32_at(directory.rva + EXPORT_FUNCTIONS)?;
+    let names = self.u32_at(directory.rva + EXPORT_NAMES)?;
+    let name_ordinals = self.u32_at(directory.rva + EXPORT_NAME_ORDINALS)?;
+
+    let mut exports = (0..function_count)
+      .map(|index| {
+        let rva = self.u32_at(functions + index * 4)?;
+
+        Ok(Export {
+          name: None,
+          ordinal: (ordinal_base + index) as u16,
+          target: self.export_target(rva)?,
+        })
+      })
+      .collect::<PeResult<Vec<_>>>()?;
+
+    for index in 0..name_count {
+      let name = self.str_at(self.u32_at(names + index * 4)?)?;
+      let function_index = self.u16_at(name_ordinals + index * 2)? as usize;
+
+      if let Some(export) = exports.get_mut(function_index) {
+        export.name = Some(name.to_string());
+      }
+    }
+
+    // unused slots in the function table are zero
+    exports.retain(|export| export.target != ExportTarget::Rva(0));
+
+    Ok(exports)
+  }
+
+  // looks a single export up by name without building the whole table
+  pub fn find_export(&self, name: &str) -> PeResult<Option<ExportTarget>> {
+    let directory = match self.directory(DataDirectoryKind::Export) {
+      Some(directory) => directory,
+      None => return Ok(None),
+    };
+
+    let function_count = self.u32_at(directory.rva + EXPORT_FUNCTION_COUNT)?;
+    let name_count = self.u32_at(directory.rva + EXPORT_NAME_COUNT)?;
+    let functions = self.u32_at(directory.rva + EXPORT_FUNCTIONS)?;
+    let names = self.u32_at(directory.rva + EXPORT_NAMES)?;
+    let name_ordinals = self.u32_at(directory.rva + EXPORT_NAME_ORDINALS)?;
+
+    for index in 0..name_count {
+      if self.str_at(self.u32_at(names + index * 4)?)? != name {
+        continue;
+      }
+
+      let function_index = self.u16_at(name_ordinals + index * 2)? as u32;
+
+      if function_index >= function_count {
+        return Ok(None);
+      }
+
+      let rva = self.u32_at(functions + function_index * 4)?;
+
+      return self.export_target(rva).map(Some);
+    }
+
+    Ok(None)
+  }
+
+  pub fn find_export_by_ordinal(&self, ordinal: u16) -> PeResult<Option<ExportTarget>> {
+    let directory = match self.directory(DataDirectoryKind::Export) {
+      Some(directory) => directory,
+      None => return Ok(None),
+    };
+
+    let ordinal_base = self.u32_at(directory.rva + EXPORT_ORDINAL_BASE)?;
+    let function_count = self.u32_at(directory.rva + EXPORT_FUNCTION_COUNT)?;
+    let functions = self.u32_at(directory.rva + EXPORT_FUNCTIONS)?;
+
+    let index = match (ordinal as u32).checked_sub(ordinal_base) {
+      Some(index) if index < function_count => index,
+      _ => return Ok(None),
+    };
+
+    match self.u32_at(functions + index * 4)? {
+      0 => Ok(None),
+      rva => self.export_target(rva).map(Some),
+    }
+  }
+
+  // functions that point back into the export directory are forwarder strings
+  fn export_target(&self, rva: u32) -> PeResult<ExportTarget> {
+    match self.directory(DataDirectoryKind::Export) {
+      Some(directory) if directory.contains(rva) => Ok(ExportTarget::Forwarded(self.str_at(rva)?.to_string())),
+      _ => Ok(ExportTarget::Rva(rva)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    arch::Arch,
+    pe::{fixture::Fixture, Layout},
+  };
+
+  // ordinal 5 is Exported, 6 an unused slot, 7 by ordinal only and 8 Forwarded
+  fn fixture(arch: Arch) -> Vec<u8> {
+    let mut fixture = Fixture::new(arch);
+    let exported = fixture.add(&[0xc3]);
+    let by_ordinal = fixture.add(&[0xc3]);
+
+    fixture.exports(
+      "test.dll",
+      5,
+      &[
+        ExportTarget::Rva(exported),
+        ExportTarget::Rva(0),
+        ExportTarget::Rva(by_ordinal),
+        ExportTarget::Forwarded("NTDLL.RtlAllocateHeap".to_string()),
+      ],
+      &[("Exported", 0), ("Forwarded", 3)],
+    );
+
+    fixture.build()
+  }
+
+  #[test]
+  fn lists_exports() {
+    for arch in [Arch::X86, Arch::X64] {
+      let data = fixture(arch);
+      let image = PeImage::parse(&data, Layout::File).unwrap();
+
+      assert_eq!(
+        image.exports().unwrap(),
+        [
+          Export {
+            name: Some("Exported".to_string()),
+            ordinal: 5,
+            target: ExportTarget::Rva(0x1000),
+          },
+          Export {
+            name: None,
+            ordinal: 7,
+            target: ExportTarget::Rva(0x1008),
+          },
+          Export {
+            name: Some("Forwarded".to_string()),
+            ordinal: 8,
+            target: ExportTarget::Forwarded("NTDLL.RtlAllocateHeap".to_string()),
+          },
+        ]
+      );
+    }
+  }
+
+  #[test]
+  fn finds_exports_by_name() {
+    let data = fixture(Arch::X86);
+    let image = PeImage::parse(&data, Layout::File).unwrap();
+
+    assert_eq!(image.find_export("Exported").unwrap(), Some(ExportTarget::Rva(0x1000)));
+    assert_eq!(
+      image.find_export("Forwarded").unwrap(),
+      Some(ExportTarget::Forwarded("NTDLL.RtlAllocateHeap".to_string()))
+    );
+    // names are case sensitive, unlike module names
+    assert_eq!(image.find_export("exported").unwrap(), None);
+    assert_eq!(image.find_export("ByOrdinal").unwrap(), None);
+  }
+
+  #[test]
+  fn finds_exports_by_ordinal() {
+    let data = fixture(Arch::X64);
+    let mapped = PeImage::parse(&data, Layout::File).unwrap().map().unwrap();
+    let image = PeImage::parse(&mapped, Layout::Mapped).unwrap();
+
+    assert_eq!(
+      image.find_export_by_ordinal(5).unwrap(),
+      Some(ExportTarget::Rva(0x1000))
+    );
+    assert_eq!(
+      image.find_export_by_ordinal(7).unwrap(),
+      Some(ExportTarget::Rva(0x1008))
+    );
+    assert_eq!(
+      image.find_export_by_ordinal(8).unwrap(),
+      Some(ExportTarget::Forwarded("NTDLL.RtlAllocateHeap".to_string()))
+    );
+    // below the base, the unused slot, and past the end
+    assert_eq!(image.find_export_by_ordinal(4).unwrap(), None);
+    assert_eq!(image.find_export_by_ordinal(6).unwrap(), None);
+    assert_eq!(image.find_export_by_ordinal(9).unwrap(), None);
+  }
+
+  #[test]
+  fn no_export_directory() {
+    let data = Fixture::new(Arch::X86).build();
+    let image = PeImage::parse(&data, Layout::File).unwrap();
+
+    assert_eq!(image.exports().unwrap(), []);
+    assert_eq!(image.find_export("Exported").unwrap(), None);
+    assert_eq!(image.find_export_by_ordinal(1).unwrap(), None);
+  }
+
+  #[test]
+  fn parses_forwarders() {
+    let by_name = Forwarder::parse("NTDLL.RtlAllocateHeap").unwrap();
+
+    assert_eq!(by_name.module, "NTDLL");
+    assert_eq!(by_name.export, ForwardedExport::Name("RtlAllocateHeap"));
+    assert_eq!(by_name.module_file_name(), "NTDLL.dll");
+
+    assert_eq!(
+      Forwarder::parse("api-ms-win-core-heap-l1-1-0.#12"),
+      Some(Forwarder {
+        module: "api-ms-win-core-heap-l1-1-0",
+        export: ForwardedExport::Ordinal(12),
+      })
+    );
+
+    assert_eq!(Forwarder::parse("NTDLL"), None);
+    assert_eq!(Forwarder::parse("NTDLL.#name"), None);
+  }
+}
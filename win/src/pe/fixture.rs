@@ -0,0 +1,331 @@
+// builds small pe images for the tests, laid out on disk the way a linker would
+// the headers come first, then a single section whose raw data starts before its rva so file and mapped layouts differ
+
+use super::{export::ExportTarget, import::ImportName, DataDirectoryKind, DOS_SIGNATURE, NT_SIGNATURE};
+use crate::arch::Arch;
+
+pub const HEADERS_SIZE: u32 = 0x400;
+pub const SECTION_RVA: u32 = 0x1000;
+pub const SECTION_RAW: u32 = HEADERS_SIZE;
+
+const NT_OFFSET: usize = 0x80;
+const DIRECTORY_COUNT: usize = 16;
+const SECTION_ALIGNMENT: u32 = 0x1000;
+const FILE_ALIGNMENT: u32 = 0x200;
+
+// IMAGE_SCN_CNT_CODE | IMAGE_SCN_CNT_INITIALIZED_DATA | IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_READ | IMAGE_SCN_MEM_WRITE
+const SECTION_CHARACTERISTICS: u32 = 0x20 | 0x40 | 0x2000_0000 | 0x4000_0000 | 0x8000_0000;
+
+pub struct Fixture {
+  arch: Arch,
+  image_base: u64,
+  entry_point: u32,
+  section: Vec<u8>,
+  directories: [(u32, u32); DIRECTORY_COUNT],
+}
+
+impl Fixture {
+  pub fn new(arch: Arch) -> Self {
+    let image_base = match arch {
+      Arch::X86 => 0x1000_0000,
+      Arch::X64 => 0x1_8000_0000,
+    };
+
+    Self {
+      arch,
+      image_base,
+      entry_point: SECTION_RVA,
+      section: Vec::new(),
+      directories: [(0, 0); DIRECTORY_COUNT],
+    }
+  }
+
+  pub fn image_base(&self) -> u64 {
+    self.image_base
+  }
+
+  pub fn pointer_size(&self) -> usize {
+    self.arch.pointer_size()
+  }
+
+  // appends bytes to the section, pointer aligned, returning their rva
+  pub fn add(&mut self, bytes: &[u8]) -> u32 {
+    let aligned = align(self.section.len(), 8);
+
+    self.section.resize(aligned, 0);
+    self.section.extend_from_slice(bytes);
+
+    SECTION_RVA + aligned as u32
+  }
+
+  pub fn add_str(&mut self, string: &str) -> u32 {
+    let mut bytes = string.as_bytes().to_vec();
+    bytes.push(0);
+
+    self.add(&bytes)
+  }
+
+  pub fn write(&mut self, rva: u32, bytes: &[u8]) {
+    let offset = (rva - SECTION_RVA) as usize;
+
+    if self.section.len() < offset + bytes.len() {
+      self.section.resize(offset + bytes.len(), 0);
+    }
+
+    self.section[offset..offset + bytes.len()].copy_from_slice(bytes);
+  }
+
+  pub fn write_pointer(&mut self, rva: u32, value: u64) {
+    let bytes = pointer_bytes(self.arch, value);
+
+    self.write(rva, &bytes);
+  }
+
+  pub fn set_directory(&mut self, kind: DataDirectoryKind, rva: u32, size: u32) {
+    self.directories[kind as usize] = (rva, size);
+  }
+
+  // an export directory for module_name, functions are indexed by ordinal - ordinal_base
+  // forwarders are stored inside the directory, like the linker does
+  pub fn exports(&mut self, module_name: &str, ordinal_base: u32, functions: &[ExportTarget], names: &[(&str, u16)]) {
+    let rva = self.add(&[]);
+
+    let functions_offset = 40;
+    let names_offset = functions_offset + functions.len() * 4;
+    let ordinals_offset = names_offset + names.len() * 4;
+    let strings_offset = ordinals_offset + names.len() * 2;
+
+    let mut directory = vec![0; strings_offset];
+    let mut strings = Vec::new();
+
+    let mut add_string = |string: &str| {
+      let string_rva = rva + (strings_offset + strings.len()) as u32;
+
+      strings.extend_from_slice(string.as_bytes());
+      strings.push(0);
+
+      string_rva
+    };
+
+    let name_rva = add_string(module_name);
+
+    let function_rvas = functions
+      .iter()
+      .map(|function| match function {
+        ExportTarget::Rva(function_rva) => *function_rva,
+        ExportTarget::Forwarded(forwarder) => add_string(forwarder),
+      })
+      .collect::<Vec<_>>();
+
+    let name_rvas = names.iter().map(|(name, _)| add_string(name)).collect::<Vec<_>>();
+
+    put_u32(&mut directory, 12, name_rva);
+    put_u32(&mut directory, 16, ordinal_base);
+    put_u32(&mut directory, 20, functions.len() as u32);
+    put_u32(&mut directory, 24, names.len() as u32);
+    put_u32(&mut directory, 28, rva + functions_offset as u32);
+    put_u32(&mut directory, 32, rva + names_offset as u32);
+    put_u32(&mut directory, 36, rva + ordinals_offset as u32);
+
+    for (index, function_rva) in function_rvas.into_iter().enumerate() {
+      put_u32(&mut directory, functions_offset + index * 4, function_rva);
+    }
+
+    for (index, (name_rva, (_, function_index))) in name_rvas.into_iter().zip(names).enumerate() {
+      put_u32(&mut directory, names_offset + index * 4, name_rva);
+      directory[ordinals_offset + index * 2..ordinals_offset + index * 2 + 2]
+        .copy_from_slice(&function_index.to_le_bytes());
+    }
+
+    directory.extend_from_slice(&strings);
+
+    self.write(rva, &directory);
+    self.set_directory(DataDirectoryKind::Export, rva, directory.len() as u32);
+  }
+
+  // an import directory, returning the address table slot rvas of each module's functions
+  // without a lookup table the address table is the only copy of the names, as with old linkers
+  pub fn imports(&mut self, modules: &[(&str, &[ImportName])], lookup_table: bool) -> Vec<Vec<u32>> {
+    let pointer_size = self.pointer_size();
+    let ordinal_flag = match self.arch {
+      Arch::X86 => 0x8000_0000,
+      Arch::X64 => 0x8000_0000_0000_0000,
+    };
+
+    // the descriptor table goes first so it is one contiguous block, ending with an all zero entry
+    let descriptors = self.add(&vec![0; (modules.len() + 1) * 20]);
+    let mut slots = Vec::new();
+
+    for (index, (module_name, functions)) in modules.iter().enumerate() {
+      let thunks = functions
+        .iter()
+        .map(|function| match function {
+          ImportName::Name(name) => {
+            // hint, then the name
+            let mut hint_name = vec![0, 0];
+            hint_name.extend_from_slice(name.as_bytes());
+            hint_name.push(0);
+
+            self.add(&hint_name) as u64
+          }
+          ImportName::Ordinal(ordinal) => ordinal_flag | *ordinal as u64,
+        })
+        .collect::<Vec<_>>();
+
+      let mut table = thunks
+        .iter()
+        .flat_map(|&thunk| pointer_bytes(self.arch, thunk))
+        .collect::<Vec<_>>();
+      table.extend(vec![0; pointer_size]);
+
+      let lookup_rva = if lookup_table { self.add(&table) } else { 0 };
+      let address_rva = self.add(&table);
+      let name_rva = self.add_str(module_name);
+
+      let descriptor = descriptors + index as u32 * 20;
+
+      self.write(descriptor, &lookup_rva.to_le_bytes());
+      self.write(descriptor + 12, &name_rva.to_le_bytes());
+      self.write(descriptor + 16, &address_rva.to_le_bytes());
+
+      slots.push(
+        (0..functions.len())
+          .map(|index| address_rva + (index * pointer_size) as u32)
+          .collect(),
+      );
+    }
+
+    self.set_directory(DataDirectoryKind::Import, descriptors, (modules.len() as u32 + 1) * 20);
+
+    slots
+  }
+
+  // a base relocation block per page, entries are (type << 12) | page offset
+  pub fn relocations(&mut self, blocks: &[(u32, &[u16])]) {
+    let mut directory = Vec::new();
+
+    for (page_rva, entries) in blocks {
+      // blocks stay 4 byte aligned, padded with an absolute entry
+      let padded = align(entries.len(), 2);
+      let block_size = 8 + padded as u32 * 2;
+
+      directory.extend_from_slice(&page_rva.to_le_bytes());
+      directory.extend_from_slice(&block_size.to_le_bytes());
+
+      for entry in entries.iter().chain([0].iter().take(padded - entries.len())) {
+        directory.extend_from_slice(&entry.to_le_bytes());
+      }
+    }
+
+    let rva = self.add(&directory);
+
+    self.set_directory(DataDirectoryKind::BaseReloc, rva, directory.len() as u32);
+  }
+
+  // a tls directory with data as the template each thread's copy starts as, and callbacks at the given rvas
+  pub fn tls(&mut self, data: &[u8], zero_fill: u32, callbacks: &[u32]) {
+    let pointer_size = self.pointer_size();
+
+    let mut table = callbacks
+      .iter()
+      .flat_map(|&callback| pointer_bytes(self.arch, self.image_base + callback as u64))
+      .collect::<Vec<_>>();
+    table.extend(vec![0; pointer_size]);
+
+    let callbacks_rva = self.add(&table);
+    let data_rva = self.add(data);
+    let index_rva = self.add(&[0; 4]);
+
+    let va = |rva: u32| pointer_bytes(self.arch, self.image_base + rva as u64);
+
+    // start and end of the template, the index and the callbacks as absolute addresses, then the zero fill size
+    let mut directory = [
+      va(data_rva),
+      va(data_rva + data.len() as u32),
+      va(index_rva),
+      va(callbacks_rva),
+    ]
+    .concat();
+    directory.extend_from_slice(&zero_fill.to_le_bytes());
+    directory.extend_from_slice(&[0; 4]);
+
+    let rva = self.add(&directory);
+
+    self.set_directory(DataDirectoryKind::Tls, rva, directory.len() as u32);
+  }
+
+  // the image as it is on disk
+  pub fn build(&self) -> Vec<u8> {
+    let raw_size = align(self.section.len().max(1), FILE_ALIGNMENT as usize);
+    let virtual_size = self.section.len().max(1) as u32;
+    let size_of_image = SECTION_RVA + align(virtual_size as usize, SECTION_ALIGNMENT as usize) as u32;
+
+    let mut image = vec![0; HEADERS_SIZE as usize + raw_size];
+
+    image[..2].copy_from_slice(&DOS_SIGNATURE.to_le_bytes());
+    put_u32(&mut image, 0x3c, NT_OFFSET as u32);
+    put_u32(&mut image, NT_OFFSET, NT_SIGNATURE);
+
+    let (machine, magic, optional_size, directories_offset) = match self.arch {
+      Arch::X86 => (0x14c_u16, 0x10b_u16, 96 + DIRECTORY_COUNT * 8, 96),
+      Arch::X64 => (0x8664, 0x20b, 112 + DIRECTORY_COUNT * 8, 112),
+    };
+
+    let file_header = NT_OFFSET + 4;
+
+    image[file_header..file_header + 2].copy_from_slice(&machine.to_le_bytes());
+    image[file_header + 2..file_header + 4].copy_from_slice(&1_u16.to_le_bytes());
+    image[file_header + 16..file_header + 18].copy_from_slice(&(optional_size as u16).to_le_bytes());
+
+    let optional = file_header + 20;
+
+    image[optional..optional + 2].copy_from_slice(&magic.to_le_bytes());
+    put_u32(&mut image, optional + 16, self.entry_point);
+
+    match self.arch {
+      Arch::X86 => put_u32(&mut image, optional + 28, self.image_base as u32),
+      Arch::X64 => image[optional + 24..optional + 32].copy_from_slice(&self.image_base.to_le_bytes()),
+    }
+
+    put_u32(&mut image, optional + 32, SECTION_ALIGNMENT);
+    put_u32(&mut image, optional + 36, FILE_ALIGNMENT);
+    put_u32(&mut image, optional + 56, size_of_image);
+    put_u32(&mut image, optional + 60, HEADERS_SIZE);
+    put_u32(&mut image, optional + directories_offset - 4, DIRECTORY_COUNT as u32);
+
+    for (index, (rva, size)) in self.directories.iter().enumerate() {
+      put_u32(&mut image, optional + directories_offset + index * 8, *rva);
+      put_u32(&mut image, optional + directories_offset + index * 8 + 4, *size);
+    }
+
+    let section = optional + optional_size;
+
+    image[section..section + 5].copy_from_slice(b".text");
+    put_u32(&mut image, section + 8, virtual_size);
+    put_u32(&mut image, section + 12, SECTION_RVA);
+    put_u32(&mut image, section + 16, raw_size as u32);
+    put_u32(&mut image, section + 20, SECTION_RAW);
+    put_u32(&mut image, section + 36, SECTION_CHARACTERISTICS);
+
+    let raw = SECTION_RAW as usize;
+
+    image[raw..raw + self.section.len()].copy_from_slice(&self.section);
+
+    image
+  }
+}
+
+fn align(value: usize, alignment: usize) -> usize {
+  value.div_ceil(alignment) * alignment
+}
+
+fn put_u32(data: &mut [u8], offset: usize, value: u32) {
+  data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+}
+
+fn pointer_bytes(arch: Arch, value: u64) -> Vec<u8> {
+  match arch {
+    Arch::X86 => (value as u32).to_le_bytes().to_vec(),
+    Arch::X64 => value.to_le_bytes().to_vec(),
+  }
+}
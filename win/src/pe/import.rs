@@ -0,0 +1,275 @@
+use super::{write_slice, DataDirectoryKind, Layout, PeError, PeImage, PeResult};
+use crate::arch::Arch;
+
+const IMPORT_DESCRIPTOR_SIZE: u32 = 20;
+
+// import descriptor field offsets
+const IMPORT_LOOKUP_TABLE: u32 = 0;
+const IMPORT_NAME: u32 = 12;
+const IMPORT_ADDRESS_TABLE: u32 = 16;
+
+const ORDINAL_FLAG_32: u64 = 0x8000_0000;
+const ORDINAL_FLAG_64: u64 = 0x8000_0000_0000_0000;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImportName {
+  Name(String),
+  Ordinal(u16),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportFunction {
+  // rva of the import address table slot the loader writes the function's address to
+  pub slot_rva: u32,
+  pub import: ImportName,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportModule {
+  pub name: String,
+  pub functions: Vec<ImportFunction>,
+}
+
+impl<'a> PeImage<'a> {
+  pub fn imports(&self) -> PeResult<Vec<ImportModule>> {
+    let directory = match self.directory(DataDirectoryKind::Import) {
+      Some(directory) => directory,
+      None => return Ok(Vec::new()),
+    };
+
+    let pointer_size = self.arch.pointer_size() as u32;
+
+    let ordinal_flag = match self.arch {
+      Arch::X86 => ORDINAL_FLAG_32,
+      Arch::X64 => ORDINAL_FLAG_64,
+    };
+
+    let mut modules = Vec::new();
+
+    // the descriptor table ends with an all zero entry
+    for descriptor in (directory.rva..).step_by(IMPORT_DESCRIPTOR_SIZE as usize) {
+      let name_rva = self.u32_at(descriptor + IMPORT_NAME)?;
+      let address_table = self.u32_at(descriptor + IMPORT_ADDRESS_TABLE)?;
+
+      if name_rva == 0 && address_table == 0 {
+        break;
+      }
+
+      // the lookup table is optional, older linkers only emit the address table
+      // once the loader has bound that it holds addresses instead of names, so it is only read from the file
+      let lookup_table = match self.u32_at(descriptor + IMPORT_LOOKUP_TABLE)? {
+        0 if self.layout == Layout::Mapped => return Err(PeError::ImportLookupError(descriptor)),
+        0 => address_table,
+        lookup_table => lookup_table,
+      };
+
+      let mut functions = Vec::new();
+
+      for index in 0.. {
+        let thunk = self.pointer_at(lookup_table + index * pointer_size)?;
+
+        if thunk == 0 {
+          break;
+        }
+
+        let import = if thunk & ordinal_flag != 0 {
+          ImportName::Ordinal(thunk as u16)
+        } else {
+          // skip the hint, the name follows it
+          ImportName::Name(self.str_at(thunk as u32 + 2)?.to_string())
+        };
+
+        functions.push(ImportFunction {
+          slot_rva: address_table + index * pointer_size,
+          import,
+        });
+      }
+
+      modules.push(ImportModule {
+        name: self.str_at(name_rva)?.to_string(),
+        functions,
+      });
+    }
+
+    Ok(modules)
+  }
+
+  // rva of the import address table slot for module!import, none if the image does not import it
+  // module names are compared case insensitively, like the loader does
+  pub fn find_import(&self, module: &str, import: &ImportName) -> PeResult<Option<u32>> {
+    let slot_rva = self
+      .imports()?
+      .into_iter()
+      .filter(|imported| imported.name.eq_ignore_ascii_case(module))
+      .flat_map(|imported| imported.functions)
+      .find(|function| function.import == *import)
+      .map(|function| function.slot_rva);
+
+    Ok(slot_rva)
+  }
+}
+
+// writes a resolved function address into its import address table slot of a mapped image
+pub fn bind_import(mapped: &mut [u8], arch: Arch, slot_rva: u32, addr: u64) -> PeResult<()> {
+  let offset = slot_rva as usize;
+
+  match arch {
+    Arch::X86 => write_slice(mapped, offset, &(addr as u32).to_le_bytes()),
+    Arch::X64 => write_slice(mapped, offset, &addr.to_le_bytes()),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::pe::fixture::Fixture;
+
+  fn fixture(arch: Arch, lookup_table: bool) -> (Vec<u8>, Vec<Vec<u32>>) {
+    let mut fixture = Fixture::new(arch);
+
+    let slots = fixture.imports(
+      &[
+        (
+          "KERNEL32.dll",
+          &[
+            ImportName::Name("GetProcAddress".to_string()),
+            ImportName::Name("LoadLibraryW".to_string()),
+          ],
+        ),
+        ("WS2_32.dll", &[ImportName::Ordinal(23)]),
+      ],
+      lookup_table,
+    );
+
+    (fixture.build(), slots)
+  }
+
+  #[test]
+  fn lists_imports() {
+    for arch in [Arch::X86, Arch::X64] {
+      for lookup_table in [true, false] {
+        let (data, slots) = fixture(arch, lookup_table);
+        let image = PeImage::parse(&data, Layout::File).unwrap();
+
+        assert_eq!(
+          image.imports().unwrap(),
+          [
+            ImportModule {
+              name: "KERNEL32.dll".to_string(),
+              functions: vec![
+                ImportFunction {
+                  slot_rva: slots[0][0],
+                  import: ImportName::Name("GetProcAddress".to_string()),
+                },
+                ImportFunction {
+                  slot_rva: slots[0][1],
+                  import: ImportName::Name("LoadLibraryW".to_string()),
+                },
+              ],
+            },
+            ImportModule {
+              name: "WS2_32.dll".to_string(),
+              functions: vec![ImportFunction {
+                slot_rva: slots[1][0],
+                import: ImportName::Ordinal(23),
+              }],
+            },
+          ]
+        );
+      }
+    }
+  }
+
+  #[test]
+  fn binds_imports() {
+    let (data, slots) = fixture(Arch::X86, true);
+    let image = PeImage::parse(&data, Layout::File).unwrap();
+    let mut mapped = image.map().unwrap();
+    let slot = slots[0][1] as usize;
+
+    bind_import(&mut mapped, Arch::X86, slots[0][1], 0x7710_2030).unwrap();
+
+    assert_eq!(mapped[slot..slot + 4], 0x7710_2030_u32.to_le_bytes());
+    // the next slot, and the terminator after it, are left alone
+    assert_eq!(mapped[slot + 4..slot + 8], [0; 4]);
+    assert_eq!(
+      PeImage::parse(&mapped, Layout::Mapped)
+        .unwrap()
+        .pointer_at(slots[0][1])
+        .unwrap(),
+      0x7710_2030
+    );
+
+    let (data, slots) = fixture(Arch::X64, true);
+    let image = PeImage::parse(&data, Layout::File).unwrap();
+    let mut mapped = image.map().unwrap();
+    let slot = slots[1][0] as usize;
+
+    bind_import(&mut mapped, Arch::X64, slots[1][0], 0x7ffa_1234_5678).unwrap();
+
+    assert_eq!(mapped[slot..slot + 8], 0x7ffa_1234_5678_u64.to_le_bytes());
+  }
+
+  #[test]
+  fn finds_imports() {
+    for arch in [Arch::X86, Arch::X64] {
+      let (data, slots) = fixture(arch, true);
+      let image = PeImage::parse(&data, Layout::File).unwrap();
+
+      assert_eq!(
+        image
+          .find_import("KERNEL32.dll", &ImportName::Name("LoadLibraryW".to_string()))
+          .unwrap(),
+        Some(slots[0][1])
+      );
+      assert_eq!(
+        image.find_import("WS2_32.dll", &ImportName::Ordinal(23)).unwrap(),
+        Some(slots[1][0])
+      );
+      // module names are not case sensitive, function names are
+      assert_eq!(
+        image
+          .find_import("kernel32.DLL", &ImportName::Name("GetProcAddress".to_string()))
+          .unwrap(),
+        Some(slots[0][0])
+      );
+      assert_eq!(
+        image
+          .find_import("KERNEL32.dll", &ImportName::Name("getprocaddress".to_string()))
+          .unwrap(),
+        None
+      );
+      assert_eq!(image.find_import("WS2_32.dll", &ImportName::Ordinal(24)).unwrap(), None);
+      assert_eq!(
+        image
+          .find_import("USER32.dll", &ImportName::Name("GetProcAddress".to_string()))
+          .unwrap(),
+        None
+      );
+    }
+  }
+
+  #[test]
+  fn mapped_imports_need_a_lookup_table() {
+    let (data, slots) = fixture(Arch::X86, true);
+    let mapped = PeImage::parse(&data, Layout::File).unwrap().map().unwrap();
+
+    assert_eq!(
+      PeImage::parse(&mapped, Layout::Mapped)
+        .unwrap()
+        .find_import("WS2_32.dll", &ImportName::Ordinal(23))
+        .unwrap(),
+      Some(slots[1][0])
+    );
+
+    let (data, _) = fixture(Arch::X86, false);
+    let image = PeImage::parse(&data, Layout::File).unwrap();
+    let mapped = image.map().unwrap();
+    let descriptor = image.directory(DataDirectoryKind::Import).unwrap().rva;
+
+    assert_eq!(
+      PeImage::parse(&mapped, Layout::Mapped).unwrap().imports().unwrap_err(),
+      PeError::ImportLookupError(descriptor)
+    );
+  }
+}
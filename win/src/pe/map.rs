@@ -0,0 +1,31 @@
+use super::{read_slice, write_slice, Layout, PeImage, PeResult};
+
+impl<'a> PeImage<'a> {
+  // lays the image out the way the loader maps it, each section at its rva and zero filled past its raw data
+  pub fn map(&self) -> PeResult<Vec<u8>> {
+    let size = self.size_of_image as usize;
+
+    if self.layout == Layout::Mapped {
+      return read_slice(self.data, 0, size).map(<[u8]>::to_vec);
+    }
+
+    let mut mapped = vec![0; size];
+    let headers = (self.size_of_headers as usize).min(self.data.len());
+
+    write_slice(&mut mapped, 0, &self.data[..headers])?;
+
+    for section in &self.sections {
+      // the raw size is rounded up to the file alignment and can run past the section
+      let len = match section.virtual_size {
+        0 => section.raw_size,
+        virtual_size => section.raw_size.min(virtual_size),
+      };
+
+      let raw = read_slice(self.data, section.raw_offset as usize, len as usize)?;
+
+      write_slice(&mut mapped, section.virtual_address as usize, raw)?;
+    }
+
+    Ok(mapped)
+  }
+}
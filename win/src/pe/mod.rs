@@ -0,0 +1,512 @@
+use std::{error, fmt, str};
+
+use crate::arch::Arch;
+
+pub mod carrier;
+pub mod export;
+pub mod import;
+pub mod map;
+pub mod reloc;
+pub mod tls;
+
+#[cfg(test)]
+mod fixture;
+
+// values mirror winnt.h, kept here so images can be parsed off windows
+const DOS_SIGNATURE: u16 = 0x5a4d;
+const NT_SIGNATURE: u32 = 0x4550;
+
+const MACHINE_I386: u16 = 0x14c;
+const MACHINE_AMD64: u16 = 0x8664;
+
+const OPTIONAL_MAGIC_PE32: u16 = 0x10b;
+const OPTIONAL_MAGIC_PE32_PLUS: u16 = 0x20b;
+
+// IMAGE_SCN_MEM_* section characteristics
+const SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+const SCN_MEM_READ: u32 = 0x4000_0000;
+const SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+const DOS_LFANEW_OFFSET: usize = 0x3c;
+const FILE_HEADER_SIZE: usize = 20;
+const SECTION_HEADER_SIZE: usize = 40;
+const DATA_DIRECTORY_SIZE: usize = 8;
+
+// the headers of a mapped image always fit in its first page, so this much can be read before parsing
+pub const MAPPED_HEADERS_SIZE: usize = 0x1000;
+
+pub type PeResult<T> = Result<T, PeError>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeError {
+  TruncatedError { offset: usize, len: usize },
+  DosSignatureError(u16),
+  NtSignatureError(u32),
+  MachineError(u16),
+  OptionalMagicError(u16),
+  RvaError(u32),
+  StringParseError(u32),
+  RelocationTypeError(u16),
+  // a mapped image without a lookup table, see PeImage::imports
+  ImportLookupError(u32),
+}
+
+impl fmt::Display for PeError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      PeError::TruncatedError { offset, len } => {
+        write!(f, "image truncated - {} bytes at {:#x} out of bounds", len, offset)
+      }
+      PeError::DosSignatureError(signature) => write!(f, "invalid dos signature - {:#x}", signature),
+      PeError::NtSignatureError(signature) => write!(f, "invalid nt signature - {:#x}", signature),
+      PeError::MachineError(machine) => write!(f, "unsupported machine type - {:#x}", machine),
+      PeError::OptionalMagicError(magic) => write!(f, "invalid optional header magic - {:#x}", magic),
+      PeError::RvaError(rva) => write!(f, "rva {:#x} is not backed by the image", rva),
+      PeError::StringParseError(rva) => write!(f, "invalid string at rva {:#x}", rva),
+      PeError::RelocationTypeError(kind) => write!(f, "unsupported relocation type - {}", kind),
+      PeError::ImportLookupError(rva) => write!(
+        f,
+        "import descriptor at rva {:#x} has no lookup table and its address table may already be bound",
+        rva
+      ),
+    }
+  }
+}
+
+impl error::Error for PeError {}
+
+// whether the bytes are the file as it is on disk, or the image as the loader mapped it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+  File,
+  Mapped,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataDirectoryKind {
+  Export = 0,
+  Import = 1,
+  Resource = 2,
+  Exception = 3,
+  Security = 4,
+  BaseReloc = 5,
+  Debug = 6,
+  Architecture = 7,
+  GlobalPtr = 8,
+  Tls = 9,
+  LoadConfig = 10,
+  BoundImport = 11,
+  Iat = 12,
+  DelayImport = 13,
+  ComDescriptor = 14,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DataDirectory {
+  pub rva: u32,
+  pub size: u32,
+}
+
+impl DataDirectory {
+  pub fn is_empty(&self) -> bool {
+    self.rva == 0 || self.size == 0
+  }
+
+  pub fn contains(&self, rva: u32) -> bool {
+    rva >= self.rva && rva - self.rva < self.size
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+  pub name: String,
+  pub virtual_address: u32,
+  pub virtual_size: u32,
+  pub raw_offset: u32,
+  pub raw_size: u32,
+  pub characteristics: u32,
+}
+
+impl Section {
+  pub fn contains(&self, rva: u32) -> bool {
+    rva >= self.virtual_address && rva - self.virtual_address < self.virtual_size.max(self.raw_size)
+  }
+
+  // what the section takes up once mapped, before rounding up to the section alignment
+  pub fn mapped_size(&self) -> u32 {
+    match self.virtual_size {
+      0 => self.raw_size,
+      virtual_size => virtual_size,
+    }
+  }
+
+  pub fn is_executable(&self) -> bool {
+    self.characteristics & SCN_MEM_EXECUTE != 0
+  }
+
+  pub fn is_readable(&self) -> bool {
+    self.characteristics & SCN_MEM_READ != 0
+  }
+
+  pub fn is_writable(&self) -> bool {
+    self.characteristics & SCN_MEM_WRITE != 0
+  }
+}
+
+// a parsed view over a pe image, borrowing its bytes
+#[derive(Debug, Clone)]
+pub struct PeImage<'a> {
+  data: &'a [u8],
+  layout: Layout,
+  arch: Arch,
+  entry_point: u32,
+  image_base: u64,
+  size_of_image: u32,
+  size_of_headers: u32,
+  directories: Vec<DataDirectory>,
+  sections: Vec<Section>,
+}
+
+impl<'a> PeImage<'a> {
+  pub fn parse(data: &'a [u8], layout: Layout) -> PeResult<Self> {
+    let dos_signature = read_u16(data, 0)?;
+
+    if dos_signature != DOS_SIGNATURE {
+      return Err(PeError::DosSignatureError(dos_signature));
+    }
+
+    let nt_offset = read_u32(data, DOS_LFANEW_OFFSET)? as usize;
+    let nt_signature = read_u32(data, nt_offset)?;
+
+    if nt_signature != NT_SIGNATURE {
+      return Err(PeError::NtSignatureError(nt_signature));
+    }
+
+    let file_header = nt_offset + 4;
+    let machine = read_u16(data, file_header)?;
+    let section_count = read_u16(data, file_header + 2)? as usize;
+    let optional_size = read_u16(data, file_header + 16)? as usize;
+
+    let arch = match machine {
+      MACHINE_I386 => Arch::X86,
+      MACHINE_AMD64 => Arch::X64,
+      _ => return Err(PeError::MachineError(machine)),
+    };
+
+    let optional = file_header + FILE_HEADER_SIZE;
+    let magic = read_u16(data, optional)?;
+
+    // the only fields whose offsets differ between pe32 and pe32+
+    let (image_base, directory_count_offset) = match magic {
+      OPTIONAL_MAGIC_PE32 => (read_u32(data, optional + 28)? as u64, 92),
+      OPTIONAL_MAGIC_PE32_PLUS => (read_u64(data, optional + 24)?, 108),
+      _ => return Err(PeError::OptionalMagicError(magic)),
+    };
+
+    let entry_point = read_u32(data, optional + 16)?;
+    let size_of_image = read_u32(data, optional + 56)?;
+    let size_of_headers = read_u32(data, optional + 60)?;
+    let directory_count = read_u32(data, optional + directory_count_offset)? as usize;
+    let directory_table = optional + directory_count_offset + 4;
+
+    let directories = (0..directory_count.min(16))
+      .map(|index| {
+        let offset = directory_table + index * DATA_DIRECTORY_SIZE;
+
+        Ok(DataDirectory {
+          rva: read_u32(data, offset)?,
+          size: read_u32(data, offset + 4)?,
+        })
+      })
+      .collect::<PeResult<Vec<_>>>()?;
+
+    let section_table = optional + optional_size;
+
+    let sections = (0..section_count)
+      .map(|index| {
+        let offset = section_table + index * SECTION_HEADER_SIZE;
+        let name = read_slice(data, offset, 8)?;
+        let name_len = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+
+        Ok(Section {
+          name: String::from_utf8_lossy(&name[..name_len]).into_owned(),
+          virtual_size: read_u32(data, offset + 8)?,
+          virtual_address: read_u32(data, offset + 12)?,
+          raw_size: read_u32(data, offset + 16)?,
+          raw_offset: read_u32(data, offset + 20)?,
+          characteristics: read_u32(data, offset + 36)?,
+        })
+      })
+      .collect::<PeResult<Vec<_>>>()?;
+
+    Ok(Self {
+      data,
+      layout,
+      arch,
+      entry_point,
+      image_base,
+      size_of_image,
+      size_of_headers,
+      directories,
+      sections,
+    })
+  }
+
+  pub fn data(&self) -> &'a [u8] {
+    self.data
+  }
+
+  pub fn layout(&self) -> Layout {
+    self.layout
+  }
+
+  pub fn arch(&self) -> Arch {
+    self.arch
+  }
+
+  pub fn entry_point(&self) -> u32 {
+    self.entry_point
+  }
+
+  // preferred load address from the optional header
+  pub fn image_base(&self) -> u64 {
+    self.image_base
+  }
+
+  pub fn size_of_image(&self) -> u32 {
+    self.size_of_image
+  }
+
+  pub fn size_of_headers(&self) -> u32 {
+    self.size_of_headers
+  }
+
+  pub fn sections(&self) -> &[Section] {
+    &self.sections
+  }
+
+  // empty directories are returned as None
+  pub fn directory(&self, kind: DataDirectoryKind) -> Option<DataDirectory> {
+    self
+      .directories
+      .get(kind as usize)
+      .copied()
+      .filter(|directory| !directory.is_empty())
+  }
+
+  pub fn rva_to_offset(&self, rva: u32) -> PeResult<usize> {
+    match self.layout {
+      Layout::Mapped => Ok(rva as usize),
+      Layout::File if rva < self.size_of_headers => Ok(rva as usize),
+      Layout::File => {
+        let section = self
+          .sections
+          .iter()
+          .find(|section| section.contains(rva))
+          .ok_or(PeError::RvaError(rva))?;
+
+        let section_offset = rva - section.virtual_address;
+
+        // the tail of a section past its raw data is zero filled by the loader, it has no file offset
+        if section_offset >= section.raw_size {
+          return Err(PeError::RvaError(rva));
+        }
+
+        Ok((section.raw_offset + section_offset) as usize)
+      }
+    }
+  }
+
+  pub fn slice_at(&self, rva: u32, len: usize) -> PeResult<&'a [u8]> {
+    read_slice(self.data, self.rva_to_offset(rva)?, len)
+  }
+
+  pub fn u16_at(&self, rva: u32) -> PeResult<u16> {
+    read_u16(self.data, self.rva_to_offset(rva)?)
+  }
+
+  pub fn u32_at(&self, rva: u32) -> PeResult<u32> {
+    read_u32(self.data, self.rva_to_offset(rva)?)
+  }
+
+  pub fn u64_at(&self, rva: u32) -> PeResult<u64> {
+    read_u64(self.data, self.rva_to_offset(rva)?)
+  }
+
+  // pointer sized value, widened to u64 for 32 bit images
+  pub fn pointer_at(&self, rva: u32) -> PeResult<u64> {
+    match self.arch {
+      Arch::X86 => self.u32_at(rva).map(u64::from),
+      Arch::X64 => self.u64_at(rva),
+    }
+  }
+
+  // nul terminated ascii string, as used for export and import names
+  pub fn str_at(&self, rva: u32) -> PeResult<&'a str> {
+    let offset = self.rva_to_offset(rva)?;
+    let bytes = self
+      .data
+      .get(offset..)
+      .ok_or(PeError::TruncatedError { offset, len: 1 })?;
+    let len = bytes
+      .iter()
+      .position(|&byte| byte == 0)
+      .ok_or(PeError::StringParseError(rva))?;
+
+    str::from_utf8(&bytes[..len]).map_err(|_| PeError::StringParseError(rva))
+  }
+}
+
+fn read_slice(data: &[u8], offset: usize, len: usize) -> PeResult<&[u8]> {
+  offset
+    .checked_add(len)
+    .and_then(|end| data.get(offset..end))
+    .ok_or(PeError::TruncatedError { offset, len })
+}
+
+fn read_u16(data: &[u8], offset: usize) -> PeResult<u16> {
+  let bytes = read_slice(data, offset, 2)?;
+
+  Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+}
+
+fn read_u32(data: &[u8], offset: usize) -> PeResult<u32> {
+  let bytes = read_slice(data, offset, 4)?;
+
+  Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_u64(data: &[u8], offset: usize) -> PeResult<u64> {
+  let bytes = read_slice(data, offset, 8)?;
+  let mut value = [0; 8];
+
+  value.copy_from_slice(bytes);
+
+  Ok(u64::from_le_bytes(value))
+}
+
+fn write_slice(data: &mut [u8], offset: usize, bytes: &[u8]) -> PeResult<()> {
+  let len = bytes.len();
+
+  offset
+    .checked_add(len)
+    .and_then(|end| data.get_mut(offset..end))
+    .ok_or(PeError::TruncatedError { offset, len })?
+    .copy_from_slice(bytes);
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{fixture::Fixture, *};
+
+  #[test]
+  fn parses_headers() {
+    for arch in [Arch::X86, Arch::X64] {
+      let mut fixture = Fixture::new(arch);
+      let rva = fixture.add(&[0xc3]);
+      fixture.set_directory(DataDirectoryKind::Tls, rva, 4);
+
+      let data = fixture.build();
+      let image = PeImage::parse(&data, Layout::File).unwrap();
+
+      assert_eq!(image.arch(), arch);
+      assert_eq!(image.image_base(), fixture.image_base());
+      assert_eq!(image.entry_point(), 0x1000);
+      assert_eq!(image.size_of_image(), 0x2000);
+      assert_eq!(image.size_of_headers(), 0x400);
+      assert_eq!(
+        image.directory(DataDirectoryKind::Tls),
+        Some(DataDirectory { rva, size: 4 })
+      );
+      assert_eq!(image.directory(DataDirectoryKind::Export), None);
+      assert_eq!(image.sections().len(), 1);
+      assert_eq!(image.sections()[0].name, ".text");
+      assert_eq!(image.rva_to_offset(rva).unwrap(), 0x400);
+      assert_eq!(image.slice_at(rva, 1).unwrap(), [0xc3]);
+    }
+  }
+
+  #[test]
+  fn maps_sections_at_their_rva() {
+    let mut fixture = Fixture::new(Arch::X86);
+    let rva = fixture.add(&[1, 2, 3, 4]);
+
+    let data = fixture.build();
+    let mapped = PeImage::parse(&data, Layout::File).unwrap().map().unwrap();
+    let image = PeImage::parse(&mapped, Layout::Mapped).unwrap();
+
+    assert_eq!(mapped.len(), 0x2000);
+    assert_eq!(&mapped[rva as usize..rva as usize + 4], [1, 2, 3, 4]);
+    assert_eq!(image.u32_at(rva).unwrap(), 0x0403_0201);
+  }
+
+  #[test]
+  fn rejects_corrupt_headers() {
+    let data = Fixture::new(Arch::X86).build();
+
+    let mut dos = data.clone();
+    dos[0] = b'Z';
+    assert_eq!(
+      PeImage::parse(&dos, Layout::File).unwrap_err(),
+      PeError::DosSignatureError(0x5a5a)
+    );
+
+    let mut nt = data.clone();
+    nt[0x80] = 0;
+    assert_eq!(
+      PeImage::parse(&nt, Layout::File).unwrap_err(),
+      PeError::NtSignatureError(0x4500)
+    );
+
+    let mut machine = data.clone();
+    machine[0x84..0x86].copy_from_slice(&0x1c0_u16.to_le_bytes());
+    assert_eq!(
+      PeImage::parse(&machine, Layout::File).unwrap_err(),
+      PeError::MachineError(0x1c0)
+    );
+
+    let mut magic = data.clone();
+    magic[0x98..0x9a].copy_from_slice(&0x107_u16.to_le_bytes());
+    assert_eq!(
+      PeImage::parse(&magic, Layout::File).unwrap_err(),
+      PeError::OptionalMagicError(0x107)
+    );
+
+    // e_lfanew pointing past the end
+    let mut lfanew = data.clone();
+    lfanew[0x3c..0x40].copy_from_slice(&0x10000_u32.to_le_bytes());
+    assert_eq!(
+      PeImage::parse(&lfanew, Layout::File).unwrap_err(),
+      PeError::TruncatedError {
+        offset: 0x10000,
+        len: 4
+      }
+    );
+  }
+
+  #[test]
+  fn rejects_truncated_headers() {
+    let data = Fixture::new(Arch::X64).build();
+
+    assert_eq!(
+      PeImage::parse(&data[..1], Layout::File).unwrap_err(),
+      PeError::TruncatedError { offset: 0, len: 2 }
+    );
+
+    // cut inside the section table
+    assert!(matches!(
+      PeImage::parse(&data[..0x190], Layout::File).unwrap_err(),
+      PeError::TruncatedError { .. }
+    ));
+
+    // headers intact, but the section's raw data is gone
+    let image = PeImage::parse(&data[..0x400], Layout::File).unwrap();
+    assert_eq!(
+      image.slice_at(0x1000, 1).unwrap_err(),
+      PeError::TruncatedError { offset: 0x400, len: 1 }
+    );
+    assert_eq!(image.rva_to_offset(0x5000).unwrap_err(), PeError::RvaError(0x5000));
+  }
+}
@@ -0,0 +1,194 @@
+use super::{read_u32, read_u64, write_slice, DataDirectoryKind, PeError, PeImage, PeResult};
+
+// IMAGE_REL_BASED_* values we know how to apply
+const REL_BASED_ABSOLUTE: u16 = 0;
+pub(super) const REL_BASED_HIGHLOW: u16 = 3;
+pub(super) const REL_BASED_DIR64: u16 = 10;
+
+const BLOCK_HEADER_SIZE: u32 = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocationKind {
+  // a 32 bit absolute address
+  HighLow,
+  // a 64 bit absolute address
+  Dir64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Relocation {
+  pub rva: u32,
+  pub kind: RelocationKind,
+}
+
+impl<'a> PeImage<'a> {
+  // every address in the image that has to be adjusted when it is not loaded at its preferred base
+  pub fn relocations(&self) -> PeResult<Vec<Relocation>> {
+    let directory = match self.directory(DataDirectoryKind::BaseReloc) {
+      Some(directory) => directory,
+      None => return Ok(Vec::new()),
+    };
+
+    let mut relocations = Vec::new();
+    let mut block = directory.rva;
+
+    while block - directory.rva < directory.size {
+      let page_rva = self.u32_at(block)?;
+      let block_size = self.u32_at(block + 4)?;
+
+      // a block always covers at least its own header, anything less would loop forever
+      if block_size < BLOCK_HEADER_SIZE {
+        break;
+      }
+
+      for entry in (BLOCK_HEADER_SIZE..block_size).step_by(2) {
+        let entry = self.u16_at(block + entry)?;
+        let rva = page_rva + (entry & 0xfff) as u32;
+
+        let kind = match entry >> 12 {
+          // padding to keep blocks 4 byte aligned
+          REL_BASED_ABSOLUTE => continue,
+          REL_BASED_HIGHLOW => RelocationKind::HighLow,
+          REL_BASED_DIR64 => RelocationKind::Dir64,
+          kind => return Err(PeError::RelocationTypeError(kind)),
+        };
+
+        relocations.push(Relocation { rva, kind });
+      }
+
+      block += block_size;
+    }
+
+    Ok(relocations)
+  }
+}
+
+// rebases a mapped image, delta is the new base minus the preferred one (wrapping)
+pub fn apply_relocations(mapped: &mut [u8], relocations: &[Relocation], delta: u64) -> PeResult<()> {
+  for relocation in relocations {
+    let offset = relocation.rva as usize;
+
+    match relocation.kind {
+      RelocationKind::HighLow => {
+        let value = read_u32(mapped, offset)?.wrapping_add(delta as u32);
+
+        write_slice(mapped, offset, &value.to_le_bytes())?;
+      }
+      RelocationKind::Dir64 => {
+        let value = read_u64(mapped, offset)?.wrapping_add(delta);
+
+        write_slice(mapped, offset, &value.to_le_bytes())?;
+      }
+    }
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::{
+    arch::Arch,
+    pe::{fixture::Fixture, Layout},
+  };
+
+  #[test]
+  fn relocates_x86() {
+    let mut fixture = Fixture::new(Arch::X86);
+    let data_rva = fixture.add(&[0; 12]);
+    let base = fixture.image_base();
+
+    // two absolute addresses, with an unrelocated value between them
+    fixture.write_pointer(data_rva, base + 0x1100);
+    fixture.write_pointer(data_rva + 4, 0x1234_5678);
+    fixture.write_pointer(data_rva + 8, base + 0x1200);
+    fixture.relocations(&[(
+      0x1000,
+      &[0x3000 | data_rva as u16 & 0xfff, 0x3000 | (data_rva + 8) as u16 & 0xfff],
+    )]);
+
+    let data = fixture.build();
+    let image = PeImage::parse(&data, Layout::File).unwrap();
+    let relocations = image.relocations().unwrap();
+
+    assert_eq!(
+      relocations,
+      [
+        Relocation {
+          rva: data_rva,
+          kind: RelocationKind::HighLow,
+        },
+        Relocation {
+          rva: data_rva + 8,
+          kind: RelocationKind::HighLow,
+        },
+      ]
+    );
+
+    let mut mapped = image.map().unwrap();
+    let offset = data_rva as usize;
+
+    // loaded below the preferred base, so the delta wraps
+    apply_relocations(&mut mapped, &relocations, 0x0800_0000_u64.wrapping_sub(base)).unwrap();
+
+    assert_eq!(mapped[offset..offset + 4], 0x0800_1100_u32.to_le_bytes());
+    assert_eq!(mapped[offset + 4..offset + 8], 0x1234_5678_u32.to_le_bytes());
+    assert_eq!(mapped[offset + 8..offset + 12], 0x0800_1200_u32.to_le_bytes());
+  }
+
+  #[test]
+  fn relocates_x64() {
+    let mut fixture = Fixture::new(Arch::X64);
+    let data_rva = fixture.add(&[0; 8]);
+    let base = fixture.image_base();
+
+    fixture.write_pointer(data_rva, base + 0x1100);
+    // an odd number of entries is padded with an absolute one, which is skipped
+    fixture.relocations(&[(0x1000, &[0xa000 | data_rva as u16 & 0xfff])]);
+
+    let data = fixture.build();
+    let image = PeImage::parse(&data, Layout::File).unwrap();
+    let relocations = image.relocations().unwrap();
+
+    assert_eq!(
+      relocations,
+      [Relocation {
+        rva: data_rva,
+        kind: RelocationKind::Dir64,
+      }]
+    );
+
+    let mut mapped = image.map().unwrap();
+    let offset = data_rva as usize;
+
+    apply_relocations(&mut mapped, &relocations, 0x7ff0_0000_0000 - base).unwrap();
+
+    assert_eq!(mapped[offset..offset + 8], 0x7ff0_0000_1100_u64.to_le_bytes());
+  }
+
+  #[test]
+  fn rejects_unknown_relocations() {
+    let mut fixture = Fixture::new(Arch::X86);
+    // IMAGE_REL_BASED_HIGH
+    fixture.relocations(&[(0x1000, &[0x1000, 0])]);
+
+    let data = fixture.build();
+    let image = PeImage::parse(&data, Layout::File).unwrap();
+
+    assert_eq!(image.relocations().unwrap_err(), PeError::RelocationTypeError(1));
+  }
+
+  #[test]
+  fn relocations_out_of_bounds() {
+    let relocation = Relocation {
+      rva: 0xffe,
+      kind: RelocationKind::HighLow,
+    };
+
+    assert_eq!(
+      apply_relocations(&mut [0; 0x1000], &[relocation], 1).unwrap_err(),
+      PeError::TruncatedError { offset: 0xffe, len: 4 }
+    );
+  }
+}
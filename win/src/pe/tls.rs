@@ -0,0 +1,151 @@
+use super::{DataDirectoryKind, PeImage, PeResult};
+use crate::arch::Arch;
+
+// tls directory field offsets
+const TLS_RAW_DATA_END_32: u32 = 4;
+const TLS_RAW_DATA_END_64: u32 = 8;
+const TLS_INDEX_32: u32 = 8;
+const TLS_INDEX_64: u32 = 16;
+const TLS_CALLBACKS_32: u32 = 12;
+const TLS_CALLBACKS_64: u32 = 24;
+const TLS_ZERO_FILL_32: u32 = 16;
+const TLS_ZERO_FILL_64: u32 = 32;
+
+// the image's tls directory, with its absolute addresses turned into rvas
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TlsDirectory {
+  // the data every thread's copy starts as, followed by zero_fill zeroed bytes
+  pub template_rva: u32,
+  pub template_size: u32,
+  pub zero_fill: u32,
+  // where the loader writes the index of the image's slot in each thread's tls array
+  pub index_rva: u32,
+  // a null terminated array of callback addresses, 0 if there is none
+  pub callbacks_rva: u32,
+  // IMAGE_SCN_ALIGN_* bits for the template
+  pub characteristics: u32,
+}
+
+impl TlsDirectory {
+  // bytes of thread_local data every thread gets its own copy of
+  pub fn size(&self) -> u64 {
+    self.template_size as u64 + self.zero_fill as u64
+  }
+}
+
+impl<'a> PeImage<'a> {
+  pub fn tls_directory(&self) -> PeResult<Option<TlsDirectory>> {
+    let directory = match self.directory(DataDirectoryKind::Tls) {
+      Some(directory) => directory,
+      None => return Ok(None),
+    };
+
+    let (end_field, index_field, callbacks_field, zero_fill_field) = match self.arch {
+      Arch::X86 => (TLS_RAW_DATA_END_32, TLS_INDEX_32, TLS_CALLBACKS_32, TLS_ZERO_FILL_32),
+      Arch::X64 => (TLS_RAW_DATA_END_64, TLS_INDEX_64, TLS_CALLBACKS_64, TLS_ZERO_FILL_64),
+    };
+
+    // the tls directory holds absolute addresses based on the preferred image base, null stays 0
+    let to_rva = |va: u64| match va {
+      0 => 0,
+      va => va.wrapping_sub(self.image_base) as u32,
+    };
+
+    let start = self.pointer_at(directory.rva)?;
+    let end = self.pointer_at(directory.rva + end_field)?;
+
+    Ok(Some(TlsDirectory {
+      template_rva: to_rva(start),
+      template_size: end.saturating_sub(start) as u32,
+      zero_fill: self.u32_at(directory.rva + zero_fill_field)?,
+      index_rva: to_rva(self.pointer_at(directory.rva + index_field)?),
+      callbacks_rva: to_rva(self.pointer_at(directory.rva + callbacks_field)?),
+      characteristics: self.u32_at(directory.rva + zero_fill_field + 4)?,
+    }))
+  }
+
+  // bytes of thread_local data every thread gets its own copy of, 0 if the image has none
+  pub fn implicit_tls_size(&self) -> PeResult<u64> {
+    Ok(self.tls_directory()?.map_or(0, |directory| directory.size()))
+  }
+
+  // rvas of the tls callbacks, which the loader calls like DllMain before the entry point
+  pub fn tls_callbacks(&self) -> PeResult<Vec<u32>> {
+    let callbacks = match self.tls_directory()? {
+      Some(directory) if directory.callbacks_rva != 0 => directory.callbacks_rva,
+      _ => return Ok(Vec::new()),
+    };
+
+    let pointer_size = self.arch.pointer_size() as u32;
+    let mut rvas = Vec::new();
+
+    for index in 0.. {
+      let callback = self.pointer_at(callbacks + index * pointer_size)?;
+
+      if callback == 0 {
+        break;
+      }
+
+      rvas.push(callback.wrapping_sub(self.image_base) as u32);
+    }
+
+    Ok(rvas)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{
+    arch::Arch,
+    pe::{fixture::Fixture, Layout, PeImage},
+  };
+
+  #[test]
+  fn reads_tls_directory() {
+    for arch in [Arch::X86, Arch::X64] {
+      let mut fixture = Fixture::new(arch);
+      let callback = fixture.add(&[0xc3]);
+      fixture.tls(&[1, 2, 3, 4], 0x10, &[callback]);
+
+      let data = fixture.build();
+      let image = PeImage::parse(&data, Layout::File).unwrap();
+
+      assert_eq!(image.tls_callbacks().unwrap(), [callback]);
+      assert_eq!(image.implicit_tls_size().unwrap(), 0x14);
+
+      let directory = image.tls_directory().unwrap().unwrap();
+
+      assert_eq!(directory.template_size, 4);
+      assert_eq!(directory.zero_fill, 0x10);
+      assert_eq!(image.slice_at(directory.template_rva, 4).unwrap(), [1, 2, 3, 4]);
+      assert_eq!(image.u32_at(directory.index_rva).unwrap(), 0);
+      assert_eq!(
+        image.pointer_at(directory.callbacks_rva).unwrap(),
+        image.image_base() + callback as u64
+      );
+    }
+  }
+
+  #[test]
+  fn tls_callbacks_only() {
+    let mut fixture = Fixture::new(Arch::X64);
+    let callback = fixture.add(&[0xc3]);
+    fixture.tls(&[], 0, &[callback]);
+
+    let data = fixture.build();
+    let image = PeImage::parse(&data, Layout::File).unwrap();
+
+    assert_eq!(image.tls_callbacks().unwrap(), [callback]);
+    assert_eq!(image.implicit_tls_size().unwrap(), 0);
+  }
+
+  #[test]
+  fn no_tls_directory() {
+    let data = Fixture::new(Arch::X86).build();
+    let image = PeImage::parse(&data, Layout::File).unwrap();
+
+    assert_eq!(image.tls_directory().unwrap(), None);
+    assert_eq!(image.tls_callbacks().unwrap(), []);
+    assert_eq!(image.implicit_tls_size().unwrap(), 0);
+  }
+}
@@ -0,0 +1,270 @@
+use std::{ffi::OsStr, io, mem, os::windows::prelude::OsStrExt, ptr};
+
+use winapi::{
+  shared::minwindef::{BOOL, TRUE},
+  um::{
+    fileapi::{CreateFileW, ReadFile, WriteFile, OPEN_EXISTING},
+    handleapi::{DuplicateHandle, INVALID_HANDLE_VALUE},
+    ioapiset::{CancelIoEx, GetOverlappedResult},
+    minwinbase::OVERLAPPED,
+    namedpipeapi::{ConnectNamedPipe, CreateNamedPipeW},
+    processthreadsapi::GetCurrentProcess,
+    synchapi::CreateEventW,
+    winbase::{
+      FILE_FLAG_OVERLAPPED, PIPE_ACCESS_DUPLEX, PIPE_READMODE_BYTE, PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE,
+      PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+    },
+    winnt::{DUPLICATE_SAME_ACCESS, GENERIC_READ, GENERIC_WRITE, HANDLE},
+  },
+};
+
+use crate::{
+  error::{code::ErrorCode, util::get_last_error, WinApiCodeResult},
+  handle::OwnedHandle,
+};
+
+const PIPE_PREFIX: &str = r"\\.\pipe\";
+const BUFFER_SIZE: u32 = 64 * 1024;
+
+// one end of a local byte mode pipe
+// all io is overlapped, so one thread can block reading while another writes through a clone
+#[derive(Debug)]
+pub struct NamedPipe {
+  handle: OwnedHandle,
+}
+
+impl NamedPipe {
+  // a new server instance of \\.\pipe\name, waiting for a client, see connect
+  pub fn create(name: &str) -> WinApiCodeResult<Self> {
+    let path = pipe_path(name);
+
+    let handle = unsafe {
+      CreateNamedPipeW(
+        path.as_ptr(),
+        PIPE_ACCESS_DUPLEX | FILE_FLAG_OVERLAPPED,
+        PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+        PIPE_UNLIMITED_INSTANCES,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        0,
+        ptr::null_mut(),
+      )
+    };
+
+    if handle == INVALID_HANDLE_VALUE {
+      let err = get_last_error("CreateNamedPipeW");
+
+      return Err(err);
+    }
+
+    let handle = unsafe {
+      // safety
+      // - checked above, the pipe handle is ours to close
+      OwnedHandle::new(handle)
+    };
+
+    Ok(Self { handle })
+  }
+
+  // connects to an existing server instance of \\.\pipe\name
+  // fails with PipeBusy while every instance is taken, and FileNotFound if there is no server at all
+  pub fn open(name: &str) -> WinApiCodeResult<Self> {
+    let path = pipe_path(name);
+
+    let handle = unsafe {
+      CreateFileW(
+        path.as_ptr(),
+        GENERIC_READ | GENERIC_WRITE,
+        0,
+        ptr::null_mut(),
+        OPEN_EXISTING,
+        FILE_FLAG_OVERLAPPED,
+        ptr::null_mut(),
+      )
+    };
+
+    if handle == INVALID_HANDLE_VALUE {
+      let err = get_last_error("CreateFileW");
+
+      return Err(err);
+    }
+
+    let handle = unsafe {
+      // safety
+      // - checked above, the file handle is ours to close
+      OwnedHandle::new(handle)
+    };
+
+    Ok(Self { handle })
+  }
+
+  // blocks until a client opens this server instance
+  pub fn connect(&self) -> WinApiCodeResult<()> {
+    let res = self.overlapped("ConnectNamedPipe", |overlapped| unsafe {
+      ConnectNamedPipe(self.handle(), overlapped)
+    });
+
+    match res {
+      Ok(_) => Ok(()),
+      // the client got in between CreateNamedPipeW and ConnectNamedPipe
+      Err(err) if err.kind() == ErrorCode::PipeConnected => Ok(()),
+      Err(err) => Err(err),
+    }
+  }
+
+  // another handle to the same end of the pipe, eg. for a reader thread
+  pub fn try_clone(&self) -> WinApiCodeResult<Self> {
+    let mut handle = ptr::null_mut();
+
+    let res = unsafe {
+      // the pseudo handle from GetCurrentProcess does not need closing
+      let process = GetCurrentProcess();
+
+      DuplicateHandle(
+        process,
+        self.handle(),
+        process,
+        &mut handle,
+        0,
+        0,
+        DUPLICATE_SAME_ACCESS,
+      )
+    };
+
+    if res == 0 {
+      let err = get_last_error("DuplicateHandle");
+
+      return Err(err);
+    }
+
+    let handle = unsafe {
+      // safety
+      // - checked above, the duplicate is ours to close
+      OwnedHandle::new(handle)
+    };
+
+    Ok(Self { handle })
+  }
+
+  // borrowed raw handle, only valid for as long as self is alive
+  pub fn handle(&self) -> HANDLE {
+    self.handle.as_raw()
+  }
+
+  // fails whatever io is pending on this end of the pipe, through any clone of it, with OperationAborted
+  // io started after this call is not affected
+  pub fn cancel_io(&self) -> WinApiCodeResult<()> {
+    let res = unsafe { CancelIoEx(self.handle(), ptr::null_mut()) };
+
+    if res == 0 {
+      let err = get_last_error("CancelIoEx");
+
+      // nothing was pending
+      if err.kind() == ErrorCode::NotFound {
+        return Ok(());
+      }
+
+      return Err(err);
+    }
+
+    Ok(())
+  }
+
+  // returns 0 once the other end has closed the pipe
+  pub fn read_bytes(&self, buffer: &mut [u8]) -> WinApiCodeResult<usize> {
+    let len = buffer.len().min(u32::MAX as usize) as u32;
+
+    let res = self.overlapped("ReadFile", |overlapped| unsafe {
+      ReadFile(
+        self.handle(),
+        buffer.as_mut_ptr().cast(),
+        len,
+        ptr::null_mut(),
+        overlapped,
+      )
+    });
+
+    match res {
+      Ok(read) => Ok(read as usize),
+      Err(err) if err.kind() == ErrorCode::BrokenPipe => Ok(0),
+      Err(err) => Err(err),
+    }
+  }
+
+  pub fn write_bytes(&self, buffer: &[u8]) -> WinApiCodeResult<usize> {
+    let len = buffer.len().min(u32::MAX as usize) as u32;
+
+    let written = self.overlapped("WriteFile", |overlapped| unsafe {
+      WriteFile(self.handle(), buffer.as_ptr().cast(), len, ptr::null_mut(), overlapped)
+    })?;
+
+    Ok(written as usize)
+  }
+
+  // starts an overlapped operation with start and waits for it to finish, returning the bytes transferred
+  fn overlapped(&self, call: &'static str, start: impl FnOnce(*mut OVERLAPPED) -> BOOL) -> WinApiCodeResult<u32> {
+    let event = unsafe { CreateEventW(ptr::null_mut(), TRUE, 0, ptr::null()) };
+
+    if event.is_null() {
+      let err = get_last_error("CreateEventW");
+
+      return Err(err);
+    }
+
+    let event = unsafe {
+      // safety
+      // - checked above, the event handle is ours to close
+      OwnedHandle::new(event)
+    };
+
+    let mut overlapped: OVERLAPPED = unsafe { mem::zeroed() };
+    overlapped.hEvent = event.as_raw();
+
+    if start(&mut overlapped) == 0 {
+      let err = get_last_error(call);
+
+      if err.kind() != ErrorCode::IoPending {
+        return Err(err);
+      }
+    }
+
+    let mut transferred = 0;
+
+    // waits even if the operation finished straight away, overlapped and event have to outlive it
+    let res = unsafe { GetOverlappedResult(self.handle(), &mut overlapped, &mut transferred, TRUE) };
+
+    if res == 0 {
+      let err = get_last_error(call);
+
+      return Err(err);
+    }
+
+    Ok(transferred)
+  }
+}
+
+impl io::Read for NamedPipe {
+  fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+    Ok(self.read_bytes(buffer)?)
+  }
+}
+
+impl io::Write for NamedPipe {
+  fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+    Ok(self.write_bytes(buffer)?)
+  }
+
+  // nothing is buffered on our side
+  fn flush(&mut self) -> io::Result<()> {
+    Ok(())
+  }
+}
+
+// nul terminated \\.\pipe\name
+fn pipe_path(name: &str) -> Vec<u16> {
+  OsStr::new(PIPE_PREFIX)
+    .encode_wide()
+    .chain(OsStr::new(name).encode_wide())
+    .chain(Some(0))
+    .collect()
+}
@@ -0,0 +1,26 @@
+use bitflags::bitflags;
+use winapi::um::winnt::{
+  PROCESS_ALL_ACCESS, PROCESS_CREATE_PROCESS, PROCESS_CREATE_THREAD, PROCESS_DUP_HANDLE, PROCESS_QUERY_INFORMATION,
+  PROCESS_QUERY_LIMITED_INFORMATION, PROCESS_SET_INFORMATION, PROCESS_SET_QUOTA, PROCESS_SUSPEND_RESUME,
+  PROCESS_TERMINATE, PROCESS_VM_OPERATION, PROCESS_VM_READ, PROCESS_VM_WRITE, SYNCHRONIZE,
+};
+
+bitflags! {
+  // access rights requested when opening a process
+  pub struct ProcessAccess: u32 {
+    const TERMINATE = PROCESS_TERMINATE;
+    const CREATE_THREAD = PROCESS_CREATE_THREAD;
+    const VM_OPERATION = PROCESS_VM_OPERATION;
+    const VM_READ = PROCESS_VM_READ;
+    const VM_WRITE = PROCESS_VM_WRITE;
+    const DUP_HANDLE = PROCESS_DUP_HANDLE;
+    const CREATE_PROCESS = PROCESS_CREATE_PROCESS;
+    const SET_QUOTA = PROCESS_SET_QUOTA;
+    const SET_INFORMATION = PROCESS_SET_INFORMATION;
+    const QUERY_INFORMATION = PROCESS_QUERY_INFORMATION;
+    const SUSPEND_RESUME = PROCESS_SUSPEND_RESUME;
+    const QUERY_LIMITED_INFORMATION = PROCESS_QUERY_LIMITED_INFORMATION;
+    const SYNCHRONIZE = SYNCHRONIZE;
+    const ALL = PROCESS_ALL_ACCESS;
+  }
+}
@@ -0,0 +1,189 @@
+use std::{
+  env,
+  ffi::{OsStr, OsString},
+  mem,
+  os::windows::prelude::OsStrExt,
+  path::{Path, PathBuf},
+  ptr,
+};
+
+use winapi::um::{
+  processthreadsapi::{CreateProcessW, PROCESS_INFORMATION, STARTUPINFOW},
+  winbase::{CREATE_SUSPENDED, CREATE_UNICODE_ENVIRONMENT},
+};
+
+use super::WinApiProcess;
+use crate::{
+  error::{util::get_last_error, WinApiCodeResult},
+  handle::OwnedHandle,
+  thread::WinApiThread,
+  wintype::Wchar,
+};
+
+const SPACE: Wchar = b' ' as Wchar;
+const TAB: Wchar = b'\t' as Wchar;
+const QUOTE: Wchar = b'"' as Wchar;
+const BACKSLASH: Wchar = b'\\' as Wchar;
+
+#[derive(Debug, Clone, Default)]
+pub struct CreateOptions {
+  pub args: Vec<OsString>,
+  // defaults to ours
+  pub cwd: Option<PathBuf>,
+  // added to, or replacing, the variables inherited from us
+  pub env: Vec<(OsString, OsString)>,
+  // the main thread is created suspended and has to be resumed by the caller
+  pub suspended: bool,
+}
+
+// a freshly created process and its main thread, both with full access
+#[derive(Debug)]
+pub struct CreatedProcess {
+  pub process: WinApiProcess,
+  pub main_thread: WinApiThread,
+}
+
+pub fn create_process(exe: &Path, options: &CreateOptions) -> WinApiCodeResult<CreatedProcess> {
+  let exe_wide = to_wide_nul(exe.as_os_str());
+  let mut command_line = command_line(exe.as_os_str(), &options.args);
+  let cwd_wide = options.cwd.as_ref().map(|cwd| to_wide_nul(cwd.as_os_str()));
+  let env_block = (!options.env.is_empty()).then(|| env_block(&options.env));
+
+  let mut flags = CREATE_UNICODE_ENVIRONMENT;
+
+  if options.suspended {
+    flags |= CREATE_SUSPENDED;
+  }
+
+  let mut startup_info: STARTUPINFOW = unsafe { mem::zeroed() };
+  startup_info.cb = mem::size_of::<STARTUPINFOW>() as u32;
+
+  let mut process_info: PROCESS_INFORMATION = unsafe { mem::zeroed() };
+
+  let res = unsafe {
+    // safety
+    // - every buffer is nul terminated and outlives the call
+    // - CreateProcessW may write to the command line, so it gets its own mutable copy
+    CreateProcessW(
+      exe_wide.as_ptr(),
+      command_line.as_mut_ptr(),
+      ptr::null_mut(),
+      ptr::null_mut(),
+      0,
+      flags,
+      env_block
+        .as_ref()
+        .map_or(ptr::null_mut(), |block| block.as_ptr() as *mut _),
+      cwd_wide.as_ref().map_or(ptr::null(), |cwd| cwd.as_ptr()),
+      &mut startup_info,
+      &mut process_info,
+    )
+  };
+
+  if res == 0 {
+    let err = get_last_error("CreateProcessW");
+
+    return Err(err);
+  }
+
+  let (process_handle, thread) = unsafe {
+    // safety
+    // - both handles are new and ours to close
+    (
+      OwnedHandle::new(process_info.hProcess),
+      WinApiThread::from_raw(process_info.hThread, process_info.dwThreadId),
+    )
+  };
+
+  Ok(CreatedProcess {
+    process: WinApiProcess::from_handle(process_handle, process_info.dwProcessId),
+    main_thread: thread,
+  })
+}
+
+fn to_wide_nul(string: &OsStr) -> Vec<Wchar> {
+  string.encode_wide().chain(Some(0)).collect()
+}
+
+// the exe followed by each arg, quoted the way CommandLineToArgvW and the msvc crt split them again
+fn command_line(exe: &OsStr, args: &[OsString]) -> Vec<Wchar> {
+  let mut command_line = Vec::new();
+
+  quote_arg(exe, &mut command_line);
+
+  for arg in args {
+    command_line.push(SPACE);
+    quote_arg(arg, &mut command_line);
+  }
+
+  command_line.push(0);
+
+  command_line
+}
+
+fn quote_arg(arg: &OsStr, command_line: &mut Vec<Wchar>) {
+  let wide = arg.encode_wide().collect::<Vec<_>>();
+  let needs_quotes = wide.is_empty() || wide.iter().any(|&c| c == SPACE || c == TAB || c == QUOTE);
+
+  if !needs_quotes {
+    command_line.extend(wide);
+
+    return;
+  }
+
+  command_line.push(QUOTE);
+
+  // backslashes are only special right before a quote, where each one has to be escaped
+  let mut backslashes = 0;
+
+  for c in wide {
+    if c == BACKSLASH {
+      backslashes += 1;
+    } else {
+      if c == QUOTE {
+        command_line.resize(command_line.len() + backslashes + 1, BACKSLASH);
+      }
+
+      backslashes = 0;
+    }
+
+    command_line.push(c);
+  }
+
+  // the closing quote counts as well
+  command_line.resize(command_line.len() + backslashes, BACKSLASH);
+  command_line.push(QUOTE);
+}
+
+// our own environment with the overrides applied, as a sorted block of nul terminated "key=value" strings
+fn env_block(overrides: &[(OsString, OsString)]) -> Vec<Wchar> {
+  let mut vars = env::vars_os().collect::<Vec<_>>();
+
+  for (key, value) in overrides {
+    // variable names are case insensitive
+    let key_upper = key.to_string_lossy().to_uppercase();
+
+    vars.retain(|(existing, _)| existing.to_string_lossy().to_uppercase() != key_upper);
+    vars.push((key.clone(), value.clone()));
+  }
+
+  vars.sort_by_cached_key(|(key, _)| key.to_string_lossy().to_uppercase());
+
+  let mut block = Vec::new();
+
+  for (key, value) in vars {
+    block.extend(key.encode_wide());
+    block.push(b'=' as Wchar);
+    block.extend(value.encode_wide());
+    block.push(0);
+  }
+
+  // an empty block still needs both terminators
+  if block.is_empty() {
+    block.push(0);
+  }
+
+  block.push(0);
+
+  block
+}
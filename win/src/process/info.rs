@@ -0,0 +1,109 @@
+use std::mem;
+
+use winapi::um::tlhelp32::{Process32FirstW, Process32NextW, PROCESSENTRY32W, TH32CS_SNAPPROCESS};
+
+use super::{access::ProcessAccess, WinApiProcess};
+use crate::{
+  error::{code::ErrorCode, util::get_last_error, WinApiError, WinApiResult},
+  snapshot::{from_wide_nul, Snapshot},
+  wintype::Pid,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessInfo {
+  pub pid: Pid,
+  pub parent_pid: Pid,
+  pub exe_name: String,
+  pub thread_count: u32,
+}
+
+impl ProcessInfo {
+  // exe names are case insensitive on windows
+  pub fn is_named(&self, name: &str) -> bool {
+    self.exe_name.eq_ignore_ascii_case(name)
+  }
+
+  // opens the process to check, so it is only done when asked for rather than for every process in the snapshot
+  // fails for processes we cannot open, eg. protected or system processes
+  pub fn is_wow64(&self) -> WinApiResult<bool> {
+    let process = WinApiProcess::new(self.pid, ProcessAccess::QUERY_LIMITED_INFORMATION)?;
+
+    process.is_wow64()
+  }
+}
+
+// every process running at the time of the snapshot
+pub struct Processes {
+  snapshot: Snapshot,
+  entry: PROCESSENTRY32W,
+  started: bool,
+  done: bool,
+}
+
+impl Processes {
+  pub fn new() -> WinApiResult<Self> {
+    let snapshot = Snapshot::new(TH32CS_SNAPPROCESS, 0)?;
+
+    let mut entry: PROCESSENTRY32W = unsafe { mem::zeroed() };
+    entry.dwSize = mem::size_of::<PROCESSENTRY32W>() as u32;
+
+    Ok(Self {
+      snapshot,
+      entry,
+      started: false,
+      done: false,
+    })
+  }
+
+  fn process_info(&self) -> ProcessInfo {
+    // a name that is not valid unicode can never match one we look for, but the process is still listed
+    let exe_name = from_wide_nul(&self.entry.szExeFile).to_string_lossy().into_owned();
+
+    ProcessInfo {
+      pid: self.entry.th32ProcessID,
+      parent_pid: self.entry.th32ParentProcessID,
+      exe_name,
+      thread_count: self.entry.cntThreads,
+    }
+  }
+}
+
+impl Iterator for Processes {
+  type Item = WinApiResult<ProcessInfo>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if self.done {
+      return None;
+    }
+
+    let (res, call) = unsafe {
+      if self.started {
+        (
+          Process32NextW(self.snapshot.handle(), &mut self.entry),
+          "Process32NextW",
+        )
+      } else {
+        (
+          Process32FirstW(self.snapshot.handle(), &mut self.entry),
+          "Process32FirstW",
+        )
+      }
+    };
+
+    self.started = true;
+
+    if res == 0 {
+      self.done = true;
+
+      let err = get_last_error(call);
+
+      if err.kind() == ErrorCode::NoMoreFiles {
+        return None;
+      }
+
+      return Some(Err(WinApiError::WinApiErrorCode(err)));
+    }
+
+    Some(Ok(self.process_info()))
+  }
+}
@@ -2,32 +2,69 @@ use std::{ffi, mem, os::windows::prelude::OsStringExt, ptr};
 
 use winapi::{
   shared::minwindef::MAX_PATH,
-  um::{handleapi::CloseHandle, processthreadsapi::OpenProcess, psapi::GetModuleBaseNameW},
+  um::{
+    memoryapi::{ReadProcessMemory, VirtualAllocEx, VirtualProtectEx, WriteProcessMemory},
+    processthreadsapi::{CreateRemoteThread, FlushInstructionCache, OpenProcess, TerminateProcess},
+    psapi::GetModuleBaseNameW,
+    winnt::{MEM_COMMIT, MEM_RESERVE},
+    wow64apiset::IsWow64Process,
+  },
 };
 
+use self::{access::ProcessAccess, remote::RemoteAllocation, util::current_is_wow64};
 use crate::{
-  error::{util::get_last_error, WinApiCodeResult, WinApiError, WinApiResult},
+  arch::Arch,
+  error::{code::ErrorCode, util::get_last_error, WinApiCodeResult, WinApiError, WinApiResult},
+  handle::OwnedHandle,
+  memory::{
+    region::{MemoryRegion, Protection, Regions},
+    ProcessMemory,
+  },
+  module::{find_module, Module},
+  snapshot::Snapshot,
+  thread::WinApiThread,
   wintype::{Pid, ProcessHandle, Wchar},
 };
 
+pub mod access;
+pub mod create;
+pub mod info;
+pub mod remote;
 pub mod util;
 
 #[derive(Debug)]
 pub struct WinApiProcess {
-  handle: ProcessHandle,
+  handle: OwnedHandle,
+  pid: Pid,
 }
 
 impl WinApiProcess {
-  pub fn new(pid: Pid, access: u32) -> WinApiCodeResult<Self> {
-    let handle = unsafe { OpenProcess(access, 0, pid) };
+  pub fn new(pid: Pid, access: ProcessAccess) -> WinApiCodeResult<Self> {
+    let handle = unsafe { OpenProcess(access.bits(), 0, pid) };
 
     if handle.is_null() {
-      let err = get_last_error();
+      let err = get_last_error("OpenProcess");
 
       return Err(err);
     }
 
-    Ok(Self { handle })
+    let handle = unsafe {
+      // safety
+      // - checked above, OpenProcess gives us a new handle to close
+      OwnedHandle::new(handle)
+    };
+
+    Ok(Self { handle, pid })
+  }
+
+  // takes ownership of a process handle we got elsewhere, eg. from CreateProcessW
+  pub fn from_handle(handle: OwnedHandle, pid: Pid) -> Self {
+    Self { handle, pid }
+  }
+
+  // borrowed raw handle, only valid for as long as self is alive
+  pub fn handle(&self) -> ProcessHandle {
+    self.handle.as_raw()
   }
 
   pub fn get_name(&self) -> WinApiResult<String> {
@@ -37,11 +74,11 @@ impl WinApiProcess {
     let ret_chars = unsafe {
       // safety
       // - cannot overflow u32, buffer capped at MAX_PATH
-      GetModuleBaseNameW(self.handle, ptr::null_mut(), buffer.as_mut_ptr(), buffer_chars as u32)
+      GetModuleBaseNameW(self.handle(), ptr::null_mut(), buffer.as_mut_ptr(), buffer_chars as u32)
     };
 
     if ret_chars == 0 {
-      let err = get_last_error();
+      let err = get_last_error("GetModuleBaseNameW");
 
       return Err(WinApiError::WinApiErrorCode(err));
     }
@@ -59,17 +96,234 @@ impl WinApiProcess {
       Err(os_string) => Err(WinApiError::StringParseError(os_string)),
     }
   }
+
+  pub fn pid(&self) -> Pid {
+    self.pid
+  }
+
+  // true for 32 bit processes running on 64 bit windows
+  // requires PROCESS_QUERY_LIMITED_INFORMATION
+  pub fn is_wow64(&self) -> WinApiResult<bool> {
+    let mut is_wow64 = 0;
+
+    let res = unsafe { IsWow64Process(self.handle(), &mut is_wow64) };
+
+    if res == 0 {
+      let err = get_last_error("IsWow64Process");
+
+      return Err(WinApiError::WinApiErrorCode(err));
+    }
+
+    Ok(is_wow64 != 0)
+  }
+
+  // requires PROCESS_QUERY_LIMITED_INFORMATION
+  pub fn arch(&self) -> WinApiResult<Arch> {
+    if self.is_wow64()? {
+      return Ok(Arch::X86);
+    }
+
+    // not wow64, so the target matches the os, which is 64 bit if we are either 64 bit or wow64 ourselves
+    if Arch::current() == Arch::X64 || current_is_wow64()? {
+      Ok(Arch::X64)
+    } else {
+      Ok(Arch::X86)
+    }
+  }
+
+  // requires PROCESS_VM_OPERATION
+  pub fn alloc(&self, size: usize, protection: Protection) -> WinApiCodeResult<RemoteAllocation<'_>> {
+    let addr = unsafe {
+      VirtualAllocEx(
+        self.handle(),
+        ptr::null_mut(),
+        size,
+        MEM_COMMIT | MEM_RESERVE,
+        protection.bits(),
+      )
+    };
+
+    if addr.is_null() {
+      let err = get_last_error("VirtualAllocEx");
+
+      return Err(err);
+    }
+
+    unsafe {
+      // safety
+      // - checked above, the allocation is ours to free
+      Ok(RemoteAllocation::from_raw(self, addr as usize, size))
+    }
+  }
+
+  // returns the previous protection of the first page
+  // requires PROCESS_VM_OPERATION
+  pub fn protect(&self, addr: usize, size: usize, protection: Protection) -> WinApiCodeResult<Protection> {
+    let mut old_protection = 0;
+
+    let res = unsafe {
+      VirtualProtectEx(
+        self.handle(),
+        addr as *mut _,
+        size,
+        protection.bits(),
+        &mut old_protection,
+      )
+    };
+
+    if res == 0 {
+      let err = get_last_error("VirtualProtectEx");
+
+      return Err(err);
+    }
+
+    Ok(Protection::new(old_protection))
+  }
+
+  // call after writing code the target may already have executed
+  pub fn flush_instruction_cache(&self, addr: usize, size: usize) -> WinApiCodeResult<()> {
+    let res = unsafe { FlushInstructionCache(self.handle(), addr as *const _, size) };
+
+    if res == 0 {
+      let err = get_last_error("FlushInstructionCache");
+
+      return Err(err);
+    }
+
+    Ok(())
+  }
+
+  // requires PROCESS_TERMINATE
+  pub fn terminate(&self, exit_code: u32) -> WinApiCodeResult<()> {
+    let res = unsafe { TerminateProcess(self.handle(), exit_code) };
+
+    if res == 0 {
+      let err = get_last_error("TerminateProcess");
+
+      return Err(err);
+    }
+
+    Ok(())
+  }
+
+  // safety
+  // - start must be a function in the target taking one pointer sized parameter, with the system calling convention
+  // - param must be valid for whatever that function does with it
+  pub unsafe fn create_remote_thread(&self, start: usize, param: usize) -> WinApiCodeResult<WinApiThread> {
+    let mut id = 0;
+
+    let thread = CreateRemoteThread(
+      self.handle(),
+      ptr::null_mut(),
+      0,
+      Some(mem::transmute::<
+        usize,
+        unsafe extern "system" fn(*mut ffi::c_void) -> u32,
+      >(start)),
+      param as *mut _,
+      0,
+      &mut id,
+    );
+
+    if thread.is_null() {
+      let err = get_last_error("CreateRemoteThread");
+
+      return Err(err);
+    }
+
+    Ok(WinApiThread::from_raw(thread, id))
+  }
+
+  // every module loaded in the process, including 32 bit modules of a wow64 process
+  pub fn modules(&self) -> WinApiResult<Vec<Module>> {
+    Snapshot::modules(self.pid)
+  }
+
+  pub fn thread_ids(&self) -> WinApiResult<Vec<u32>> {
+    Snapshot::threads(self.pid)
+  }
+
+  pub fn find_module(&self, name: &str) -> WinApiResult<Option<Module>> {
+    let modules = self.modules()?;
+
+    Ok(find_module(&modules, name).cloned())
+  }
+
+  // requires PROCESS_QUERY_INFORMATION
+  pub fn regions(&self) -> Regions<'_> {
+    unsafe {
+      // safety
+      // - handle lives as long as self
+      Regions::remote(self.handle())
+    }
+  }
+
+  pub fn region_at(&self, addr: usize) -> WinApiResult<Option<MemoryRegion>> {
+    let mut regions = unsafe { Regions::remote(self.handle()) }.starting_at(addr);
+
+    regions.next().transpose()
+  }
 }
 
-impl Drop for WinApiProcess {
-  // if bad stuff happens, print error and (probably) leak memory
-  fn drop(&mut self) {
-    let res = unsafe { CloseHandle(self.handle) };
+impl ProcessMemory for WinApiProcess {
+  fn read_partial(&self, addr: usize, buffer: &mut [u8]) -> WinApiResult<usize> {
+    if buffer.is_empty() {
+      return Ok(0);
+    }
+
+    let mut read = 0;
+
+    let res = unsafe {
+      ReadProcessMemory(
+        self.handle(),
+        addr as *const _,
+        buffer.as_mut_ptr() as *mut _,
+        buffer.len(),
+        &mut read,
+      )
+    };
 
     if res == 0 {
-      let err = get_last_error();
+      let err = get_last_error("ReadProcessMemory");
+
+      // the read ran into an unmapped or protected page, read holds how far we got
+      if matches!(err.kind(), ErrorCode::PartialCopy | ErrorCode::NoAccess) {
+        return Ok(read);
+      }
 
-      eprintln!("{:?}", err);
+      return Err(WinApiError::WinApiErrorCode(err));
     }
+
+    Ok(read)
+  }
+
+  fn write_partial(&self, addr: usize, buffer: &[u8]) -> WinApiResult<usize> {
+    if buffer.is_empty() {
+      return Ok(0);
+    }
+
+    let mut written = 0;
+
+    let res = unsafe {
+      WriteProcessMemory(
+        self.handle(),
+        addr as *mut _,
+        buffer.as_ptr() as *const _,
+        buffer.len(),
+        &mut written,
+      )
+    };
+
+    if res == 0 {
+      let err = get_last_error("WriteProcessMemory");
+
+      if matches!(err.kind(), ErrorCode::PartialCopy | ErrorCode::NoAccess) {
+        return Ok(written);
+      }
+
+      return Err(WinApiError::WinApiErrorCode(err));
+    }
+
+    Ok(written)
   }
 }
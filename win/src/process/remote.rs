@@ -0,0 +1,52 @@
+use std::mem;
+
+use winapi::um::{memoryapi::VirtualFreeEx, winnt::MEM_RELEASE};
+
+use super::WinApiProcess;
+use crate::error::util::get_last_error;
+
+// memory allocated in another process, released again on drop
+#[derive(Debug)]
+pub struct RemoteAllocation<'a> {
+  process: &'a WinApiProcess,
+  addr: usize,
+  size: usize,
+}
+
+impl<'a> RemoteAllocation<'a> {
+  // safety
+  // - addr must be the base of a VirtualAllocEx allocation in process that nothing else frees
+  pub unsafe fn from_raw(process: &'a WinApiProcess, addr: usize, size: usize) -> Self {
+    Self { process, addr, size }
+  }
+
+  pub fn addr(&self) -> usize {
+    self.addr
+  }
+
+  pub fn size(&self) -> usize {
+    self.size
+  }
+
+  // keeps the memory allocated in the target, eg. because a thread we could not stop may still use it
+  pub fn leak(self) -> usize {
+    let addr = self.addr;
+
+    mem::forget(self);
+
+    addr
+  }
+}
+
+impl<'a> Drop for RemoteAllocation<'a> {
+  // if bad stuff happens, print error and leak the memory in the target
+  fn drop(&mut self) {
+    let res = unsafe { VirtualFreeEx(self.process.handle(), self.addr as *mut _, 0, MEM_RELEASE) };
+
+    if res == 0 {
+      let err = get_last_error("VirtualFreeEx");
+
+      eprintln!("{:?}", err);
+    }
+  }
+}
@@ -1,7 +1,8 @@
 use std::mem;
 
-use winapi::um::psapi::EnumProcesses;
+use winapi::um::{processthreadsapi::GetCurrentProcess, psapi::EnumProcesses, wow64apiset::IsWow64Process};
 
+use super::info::{ProcessInfo, Processes};
 use crate::{
   error::{util::get_last_error, WinApiError, WinApiResult},
   wintype::Pid,
@@ -27,7 +28,7 @@ pub fn enum_processes() -> WinApiResult<Vec<Pid>> {
     let res = unsafe { EnumProcesses(buffer.as_mut_ptr(), buffer_bytes as u32, &mut ret_bytes) };
 
     if res == 0 {
-      let err = get_last_error();
+      let err = get_last_error("EnumProcesses");
 
       return Err(WinApiError::WinApiErrorCode(err));
     }
@@ -47,3 +48,38 @@ pub fn enum_processes() -> WinApiResult<Vec<Pid>> {
     buffer_size *= 2;
   }
 }
+
+// true if we are a 32 bit process running on 64 bit windows
+pub fn current_is_wow64() -> WinApiResult<bool> {
+  let mut is_wow64 = 0;
+
+  // the pseudo handle from GetCurrentProcess does not need closing
+  let res = unsafe { IsWow64Process(GetCurrentProcess(), &mut is_wow64) };
+
+  if res == 0 {
+    let err = get_last_error("IsWow64Process");
+
+    return Err(WinApiError::WinApiErrorCode(err));
+  }
+
+  Ok(is_wow64 != 0)
+}
+
+pub fn processes() -> WinApiResult<Processes> {
+  Processes::new()
+}
+
+// every running process with a matching exe name, there can be more than one instance of a game open
+pub fn find_by_name(exe_name: &str) -> WinApiResult<Vec<ProcessInfo>> {
+  let mut found = Vec::new();
+
+  for info in processes()? {
+    let info = info?;
+
+    if info.is_named(exe_name) {
+      found.push(info);
+    }
+  }
+
+  Ok(found)
+}
@@ -0,0 +1,130 @@
+use std::{ffi, mem, os::windows::prelude::OsStringExt, path::PathBuf};
+
+use winapi::um::{
+  handleapi::INVALID_HANDLE_VALUE,
+  tlhelp32::{
+    CreateToolhelp32Snapshot, Module32FirstW, Module32NextW, Thread32First, Thread32Next, MODULEENTRY32W,
+    TH32CS_SNAPMODULE, TH32CS_SNAPMODULE32, TH32CS_SNAPTHREAD, THREADENTRY32,
+  },
+  winnt::HANDLE,
+};
+
+use crate::{
+  error::{code::ErrorCode, util::get_last_error, WinApiError, WinApiResult},
+  handle::OwnedHandle,
+  module::Module,
+  wintype::{Pid, Wchar},
+};
+
+// toolhelp fails with ERROR_BAD_LENGTH while the target is loading or unloading modules, so we retry a few times
+const SNAPSHOT_RETRIES: usize = 8;
+
+#[derive(Debug)]
+pub(crate) struct Snapshot {
+  handle: OwnedHandle,
+}
+
+impl Snapshot {
+  pub(crate) fn new(flags: u32, pid: Pid) -> WinApiResult<Self> {
+    let mut retries = 0;
+
+    loop {
+      let handle = unsafe { CreateToolhelp32Snapshot(flags, pid) };
+
+      if handle != INVALID_HANDLE_VALUE {
+        let handle = unsafe {
+          // safety
+          // - checked above, the snapshot handle is ours to close
+          OwnedHandle::new(handle)
+        };
+
+        return Ok(Self { handle });
+      }
+
+      let err = get_last_error("CreateToolhelp32Snapshot");
+
+      if err.kind() != ErrorCode::BadLength || retries == SNAPSHOT_RETRIES {
+        return Err(WinApiError::WinApiErrorCode(err));
+      }
+
+      retries += 1;
+    }
+  }
+
+  pub(crate) fn handle(&self) -> HANDLE {
+    self.handle.as_raw()
+  }
+
+  // includes 32 bit modules of wow64 processes when called from a 64 bit process
+  pub(crate) fn modules(pid: Pid) -> WinApiResult<Vec<Module>> {
+    let snapshot = Self::new(TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32, pid)?;
+
+    let mut entry: MODULEENTRY32W = unsafe { mem::zeroed() };
+    entry.dwSize = mem::size_of::<MODULEENTRY32W>() as u32;
+
+    let mut res = unsafe { Module32FirstW(snapshot.handle(), &mut entry) };
+    let mut call = "Module32FirstW";
+    let mut modules = Vec::new();
+
+    while res != 0 {
+      // a name that is not valid unicode can never match one we look for, but the module is still listed
+      let name = from_wide_nul(&entry.szModule).to_string_lossy().into_owned();
+      let path = PathBuf::from(from_wide_nul(&entry.szExePath));
+
+      modules.push(Module {
+        name,
+        path,
+        base: entry.modBaseAddr as usize,
+        size: entry.modBaseSize as usize,
+      });
+
+      res = unsafe { Module32NextW(snapshot.handle(), &mut entry) };
+      call = "Module32NextW";
+    }
+
+    let err = get_last_error(call);
+
+    if err.kind() != ErrorCode::NoMoreFiles {
+      return Err(WinApiError::WinApiErrorCode(err));
+    }
+
+    Ok(modules)
+  }
+
+  // thread ids owned by pid, in the order toolhelp lists them which in practice starts with the main thread
+  pub(crate) fn threads(pid: Pid) -> WinApiResult<Vec<u32>> {
+    // thread snapshots always cover every process, the pid argument is ignored
+    let snapshot = Self::new(TH32CS_SNAPTHREAD, 0)?;
+
+    let mut entry: THREADENTRY32 = unsafe { mem::zeroed() };
+    entry.dwSize = mem::size_of::<THREADENTRY32>() as u32;
+
+    let mut res = unsafe { Thread32First(snapshot.handle(), &mut entry) };
+    let mut call = "Thread32First";
+    let mut threads = Vec::new();
+
+    while res != 0 {
+      if entry.th32OwnerProcessID == pid {
+        threads.push(entry.th32ThreadID);
+      }
+
+      res = unsafe { Thread32Next(snapshot.handle(), &mut entry) };
+      call = "Thread32Next";
+    }
+
+    let err = get_last_error(call);
+
+    if err.kind() != ErrorCode::NoMoreFiles {
+      return Err(WinApiError::WinApiErrorCode(err));
+    }
+
+    Ok(threads)
+  }
+}
+
+// fixed size winapi buffers are nul terminated somewhere inside
+pub(crate) fn from_wide_nul(buffer: &[Wchar]) -> ffi::OsString {
+  let len = buffer.iter().position(|&char| char == 0).unwrap_or(buffer.len());
+
+  ffi::OsString::from_wide(&buffer[..len])
+}
@@ -0,0 +1,25 @@
+use bitflags::bitflags;
+use winapi::um::winnt::{
+  SYNCHRONIZE, THREAD_ALL_ACCESS, THREAD_DIRECT_IMPERSONATION, THREAD_GET_CONTEXT, THREAD_IMPERSONATE,
+  THREAD_QUERY_INFORMATION, THREAD_QUERY_LIMITED_INFORMATION, THREAD_SET_CONTEXT, THREAD_SET_INFORMATION,
+  THREAD_SET_LIMITED_INFORMATION, THREAD_SET_THREAD_TOKEN, THREAD_SUSPEND_RESUME, THREAD_TERMINATE,
+};
+
+bitflags! {
+  // access rights requested when opening a thread
+  pub struct ThreadAccess: u32 {
+    const TERMINATE = THREAD_TERMINATE;
+    const SUSPEND_RESUME = THREAD_SUSPEND_RESUME;
+    const GET_CONTEXT = THREAD_GET_CONTEXT;
+    const SET_CONTEXT = THREAD_SET_CONTEXT;
+    const SET_INFORMATION = THREAD_SET_INFORMATION;
+    const QUERY_INFORMATION = THREAD_QUERY_INFORMATION;
+    const SET_THREAD_TOKEN = THREAD_SET_THREAD_TOKEN;
+    const IMPERSONATE = THREAD_IMPERSONATE;
+    const DIRECT_IMPERSONATION = THREAD_DIRECT_IMPERSONATION;
+    const SET_LIMITED_INFORMATION = THREAD_SET_LIMITED_INFORMATION;
+    const QUERY_LIMITED_INFORMATION = THREAD_QUERY_LIMITED_INFORMATION;
+    const SYNCHRONIZE = SYNCHRONIZE;
+    const ALL = THREAD_ALL_ACCESS;
+  }
+}
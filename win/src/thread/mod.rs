@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+use winapi::{
+  shared::winerror::WAIT_TIMEOUT,
+  um::{
+    processthreadsapi::{GetExitCodeThread, OpenThread, ResumeThread, SuspendThread},
+    synchapi::WaitForSingleObject,
+    winbase::{INFINITE, WAIT_FAILED},
+    winnt::HANDLE,
+  },
+};
+
+use self::access::ThreadAccess;
+use crate::{
+  arch::Arch,
+  context::ThreadContext,
+  error::{util::get_last_error, WinApiCodeResult},
+  handle::OwnedHandle,
+};
+
+pub mod access;
+
+// SuspendThread and ResumeThread return (DWORD)-1 on failure
+const SUSPEND_FAILED: u32 = u32::MAX;
+
+#[derive(Debug)]
+pub struct WinApiThread {
+  handle: OwnedHandle,
+  id: u32,
+}
+
+impl WinApiThread {
+  pub fn open(id: u32, access: ThreadAccess) -> WinApiCodeResult<Self> {
+    let handle = unsafe { OpenThread(access.bits(), 0, id) };
+
+    if handle.is_null() {
+      let err = get_last_error("OpenThread");
+
+      return Err(err);
+    }
+
+    let handle = unsafe {
+      // safety
+      // - checked above, OpenThread gives us a new handle to close
+      OwnedHandle::new(handle)
+    };
+
+    Ok(Self { handle, id })
+  }
+
+  // safety
+  // - handle must be a valid thread handle that nothing else closes
+  pub unsafe fn from_raw(handle: HANDLE, id: u32) -> Self {
+    Self {
+      handle: OwnedHandle::new(handle),
+      id,
+    }
+  }
+
+  pub fn id(&self) -> u32 {
+    self.id
+  }
+
+  // borrowed raw handle, only valid for as long as self is alive
+  pub fn handle(&self) -> HANDLE {
+    self.handle.as_raw()
+  }
+
+  // waits for the thread to exit and returns its exit code, or none if the timeout passed first
+  // no timeout waits forever
+  pub fn wait(&self, timeout: Option<Duration>) -> WinApiCodeResult<Option<u32>> {
+    let timeout_ms = match timeout {
+      // INFINITE is u32::MAX so anything longer is clamped just below it
+      Some(timeout) => timeout.as_millis().min((INFINITE - 1) as u128) as u32,
+      None => INFINITE,
+    };
+
+    let res = unsafe { WaitForSingleObject(self.handle(), timeout_ms) };
+
+    if res == WAIT_FAILED {
+      let err = get_last_error("WaitForSingleObject");
+
+      return Err(err);
+    }
+
+    if res == WAIT_TIMEOUT {
+      return Ok(None);
+    }
+
+    self.exit_code().map(Some)
+  }
+
+  pub fn exit_code(&self) -> WinApiCodeResult<u32> {
+    let mut exit_code = 0;
+
+    let res = unsafe { GetExitCodeThread(self.handle(), &mut exit_code) };
+
+    if res == 0 {
+      let err = get_last_error("GetExitCodeThread");
+
+      return Err(err);
+    }
+
+    Ok(exit_code)
+  }
+
+  // returns the previous suspend count
+  pub fn suspend(&self) -> WinApiCodeResult<u32> {
+    let res = unsafe { SuspendThread(self.handle()) };
+
+    if res == SUSPEND_FAILED {
+      let err = get_last_error("SuspendThread");
+
+      return Err(err);
+    }
+
+    Ok(res)
+  }
+
+  // returns the previous suspend count, the thread only runs again once it reaches 0
+  pub fn resume(&self) -> WinApiCodeResult<u32> {
+    let res = unsafe { ResumeThread(self.handle()) };
+
+    if res == SUSPEND_FAILED {
+      let err = get_last_error("ResumeThread");
+
+      return Err(err);
+    }
+
+    Ok(res)
+  }
+
+  // arch is that of the owning process, see WinApiProcess::arch
+  // the thread should be suspended, the registers of a running thread are stale as soon as they are read
+  pub fn context(&self, arch: Arch) -> WinApiCodeResult<ThreadContext> {
+    unsafe {
+      // safety
+      // - our handle is valid for as long as self is alive
+      ThreadContext::get(self.handle(), arch)
+    }
+  }
+
+  // safety
+  // - the thread must be suspended
+  // - context must have been read from this thread, and the registers changed so it can keep running
+  pub unsafe fn set_context(&self, context: &ThreadContext) -> WinApiCodeResult<()> {
+    context.set(self.handle())
+  }
+}